@@ -0,0 +1,77 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use engine3d::collision::*;
+use engine3d::geom::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Debris-like scenes: rotated boxes scattered over a region that grows with
+/// their number, so each box has a handful of neighbours.
+fn scene(n: usize) -> Vec<Box> {
+    let mut rng = ChaCha8Rng::seed_from_u64(n as u64);
+    let spread = (n as f32).cbrt() * 2.0;
+    (0..n)
+        .map(|_| Box {
+            c: Pos3::new(
+                rng.gen_range(-spread..spread),
+                rng.gen_range(-spread..spread),
+                rng.gen_range(-spread..spread),
+            ),
+            axes: Mat3::from(
+                Quat::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalize(),
+            ),
+            half_sizes: Vec3::new(0.5, 0.5, 0.5),
+        })
+        .collect()
+}
+
+fn gather_aa(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gather_contacts_aa");
+    for &n in &[50, 200, 1000] {
+        let boxes = scene(n);
+        let mut contacts = Vec::with_capacity(n * 8);
+        group.bench_with_input(BenchmarkId::new("brute", n), &boxes, |bench, boxes| {
+            bench.iter(|| {
+                contacts.clear();
+                gather_contacts_aa_brute(black_box(boxes), &mut contacts);
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("sweep", n), &boxes, |bench, boxes| {
+            bench.iter(|| {
+                contacts.clear();
+                gather_contacts_aa(black_box(boxes), &mut contacts);
+            })
+        });
+    }
+    group.finish();
+}
+
+fn gather_ab(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gather_contacts_ab");
+    for &n in &[50, 200, 1000] {
+        let boxes = scene(n);
+        let others = scene(n + 1);
+        let mut contacts = Vec::with_capacity(n * 8);
+        group.bench_function(BenchmarkId::new("brute", n), |bench| {
+            bench.iter(|| {
+                contacts.clear();
+                gather_contacts_ab_brute(black_box(&boxes), black_box(&others), &mut contacts);
+            })
+        });
+        group.bench_function(BenchmarkId::new("sweep", n), |bench| {
+            bench.iter(|| {
+                contacts.clear();
+                gather_contacts_ab(black_box(&boxes), black_box(&others), &mut contacts);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, gather_aa, gather_ab);
+criterion_main!(benches);
@@ -1,22 +1,435 @@
+use crate::geom::*;
 use crate::model::{DrawModel, Model};
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
 
+/// How many bones can pull on one vertex.
+pub const BONES_PER_VERTEX: usize = 4;
+/// The most bones a skeleton can have: as many as there's room for in
+/// `shader_bones.vert`'s `Bones` uniform.
+pub const BONE_MAX: usize = 128;
+
+/// One bone's skinning transform as the GPU sees it: a vertex in the bind
+/// pose is rotated by `rotation` and then moved by `position`.
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Default)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Bone {
     position: [f32; 4],
-    rotation: [f32; 4], // a quaternion
+    rotation: [f32; 4], // a quaternion, xyzw
+}
+
+/// A bone that leaves its vertices where they are.
+impl Default for Bone {
+    fn default() -> Self {
+        Pose::default().into()
+    }
+}
+
+impl From<Pose> for Bone {
+    fn from(p: Pose) -> Self {
+        Self {
+            position: [p.translation.x, p.translation.y, p.translation.z, 0.0],
+            rotation: [p.rotation.v.x, p.rotation.v.y, p.rotation.v.z, p.rotation.s],
+        }
+    }
+}
+
+/// A rotation followed by a translation: where a bone is relative to its
+/// parent, or to the model.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Pose {
+    #[serde(with = "Vec3Def", default = "Vec3::zero")]
+    pub translation: Vec3,
+    #[serde(with = "QuatDef", default = "Quat::one")]
+    pub rotation: Quat,
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::zero(),
+            rotation: Quat::one(),
+        }
+    }
+}
+
+impl Pose {
+    pub fn new(translation: Vec3, rotation: Quat) -> Self {
+        Self {
+            translation,
+            rotation,
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        let rotation = self.rotation.conjugate();
+        Self {
+            translation: -(rotation * self.translation),
+            rotation,
+        }
+    }
+
+    pub fn transform_point(&self, p: Pos3) -> Pos3 {
+        Pos3::from_vec(self.rotation * p.to_vec() + self.translation)
+    }
+
+    /// `t` of the way from `self` to `other`, slerping the rotation.
+    pub fn lerp(&self, other: &Pose, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+        }
+    }
+}
+
+/// `parent * child` places `child`, given relative to `parent`, wherever
+/// `parent` is placed.
+impl std::ops::Mul for Pose {
+    type Output = Pose;
+    fn mul(self, child: Pose) -> Pose {
+        Pose {
+            translation: self.translation + self.rotation * child.translation,
+            rotation: self.rotation * child.rotation,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SkeletonBone {
+    pub name: String,
+    /// Parents always come before their children.
+    #[serde(default)]
+    pub parent: Option<usize>,
+    /// Where the bone is relative to its parent in the bind pose, the pose
+    /// the model was made in.
+    #[serde(default)]
+    pub rest: Pose,
+}
+
+/// A hierarchy of bones, each placed relative to its parent.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct Skeleton {
+    pub bones: Vec<SkeletonBone>,
+}
+
+impl Skeleton {
+    pub fn len(&self) -> usize {
+        self.bones.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bones.is_empty()
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.bones.iter().position(|b| b.name == name)
+    }
+
+    /// Every bone at rest, relative to its parent.
+    pub fn rest(&self) -> Vec<Pose> {
+        self.bones.iter().map(|b| b.rest).collect()
+    }
+
+    /// Where each bone ends up in the model, given where each is relative
+    /// to its parent.
+    pub fn model_poses(&self, local: &[Pose]) -> Vec<Pose> {
+        let mut model: Vec<Pose> = Vec::with_capacity(local.len());
+        for (b, &l) in self.bones.iter().zip(local) {
+            let p = match b.parent {
+                Some(parent) => model[parent] * l,
+                None => l,
+            };
+            model.push(p);
+        }
+        model
+    }
+
+    /// The transform that carries each bone, and the vertices it pulls on,
+    /// from the bind pose to the pose `local`.
+    pub fn skin(&self, local: &[Pose]) -> Vec<Pose> {
+        let bind = self.model_poses(&self.rest());
+        self.model_poses(local)
+            .into_iter()
+            .zip(bind)
+            .map(|(posed, bind)| posed * bind.inverse())
+            .collect()
+    }
+
+    /// `skin`, ready for `InstanceGroups::render_anim`.
+    pub fn bones(&self, local: &[Pose]) -> Vec<Bone> {
+        self.skin(local).into_iter().map(Bone::from).collect()
+    }
+
+    /// Each bone in the bind pose as the segment from its head to the middle
+    /// of its children's heads (or just its head, without children), for
+    /// weighting vertices to the bones they're nearest to.
+    pub fn segments(&self) -> BoneSegments {
+        let bind = self.model_poses(&self.rest());
+        let mut children = vec![(Vec3::zero(), 0); self.bones.len()];
+        for (b, pose) in self.bones.iter().zip(bind.iter()) {
+            if let Some(parent) = b.parent {
+                children[parent].0 += pose.translation;
+                children[parent].1 += 1;
+            }
+        }
+        let segments = bind
+            .iter()
+            .zip(children)
+            .map(|(pose, (sum, n))| {
+                let head = Pos3::from_vec(pose.translation);
+                let tail = if n == 0 {
+                    head
+                } else {
+                    Pos3::from_vec(sum / n as f32)
+                };
+                (head, tail)
+            })
+            .collect();
+        BoneSegments(segments)
+    }
+}
+
+/// A skeleton's bones as segments in its bind pose, from
+/// `Skeleton::segments`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BoneSegments(Vec<(Pos3, Pos3)>);
+
+impl BoneSegments {
+    /// Which bones pull on a vertex at `p` in the bind pose, and how hard:
+    /// the (at most) `BONES_PER_VERTEX` bones it's nearest to, weighted by
+    /// inverse square distance.  Weights add up to one.
+    pub fn weights(&self, p: Pos3) -> ([u8; BONES_PER_VERTEX], [f32; BONES_PER_VERTEX]) {
+        let mut near: Vec<(usize, f32)> = self
+            .0
+            .iter()
+            .map(|&(head, tail)| distance_to_segment(p, head, tail))
+            .enumerate()
+            .collect();
+        near.sort_by(|a, b| a.1.total_cmp(&b.1));
+        near.truncate(BONES_PER_VERTEX);
+        let mut ids = [0; BONES_PER_VERTEX];
+        let mut weights = [0.0; BONES_PER_VERTEX];
+        if near.is_empty() {
+            weights[0] = 1.0;
+            return (ids, weights);
+        }
+        for (k, &(i, d)) in near.iter().enumerate() {
+            ids[k] = i as u8;
+            weights[k] = 1.0 / (d * d + 1e-4);
+        }
+        let total: f32 = weights.iter().sum();
+        for w in weights.iter_mut() {
+            *w /= total;
+        }
+        (ids, weights)
+    }
+}
+
+fn distance_to_segment(p: Pos3, a: Pos3, b: Pos3) -> f32 {
+    let ab = b - a;
+    let len2 = ab.magnitude2();
+    let t = if len2 > 0.0 {
+        ((p - a).dot(ab) / len2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (p - (a + ab * t)).magnitude()
+}
+
+/// A bone's pose at some time into a clip.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Key {
+    pub time: f32,
+    #[serde(flatten)]
+    pub pose: Pose,
+}
+
+/// How one bone moves over a clip, relative to its parent.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Track {
+    pub bone: String,
+    /// In order of time.
+    pub keys: Vec<Key>,
+}
+
+impl Track {
+    /// The pose at `time`, between the keys either side of it, or held at
+    /// the first or last key outside them.
+    pub fn sample(&self, time: f32) -> Option<Pose> {
+        let first = self.keys.first()?;
+        let after = self.keys.iter().position(|k| k.time > time);
+        Some(match after {
+            Some(0) => first.pose,
+            Some(i) => {
+                let (a, b) = (&self.keys[i - 1], &self.keys[i]);
+                a.pose.lerp(&b.pose, (time - a.time) / (b.time - a.time))
+            }
+            None => self.keys.last().unwrap().pose,
+        })
+    }
+}
+
+/// A named animation, such as a walk cycle.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Clip {
+    pub name: String,
+    pub duration: f32,
+    /// Looping clips start over once they reach the end; others stop on
+    /// their last frame.
+    #[serde(default)]
+    pub looping: bool,
+    pub tracks: Vec<Track>,
+}
+
+impl Clip {
+    /// Every bone of `skeleton` relative to its parent, `time` into the
+    /// clip.  Bones the clip doesn't move stay at rest.
+    pub fn sample(&self, skeleton: &Skeleton, time: f32) -> Vec<Pose> {
+        let time = if self.looping && self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            time.min(self.duration)
+        };
+        let mut pose = skeleton.rest();
+        for t in self.tracks.iter() {
+            if let (Some(i), Some(p)) = (skeleton.find(&t.bone), t.sample(time)) {
+                pose[i] = p;
+            }
+        }
+        pose
+    }
+}
+
+/// `t` of the way from pose `a` to pose `b`, bone by bone.
+pub fn blend(a: &[Pose], b: &[Pose], t: f32) -> Vec<Pose> {
+    a.iter().zip(b).map(|(a, b)| a.lerp(b, t)).collect()
+}
+
+/// A skeleton and the clips made for it, read from a `.rig.json` file next
+/// to the model.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct Rig {
+    pub skeleton: Skeleton,
+    #[serde(default)]
+    pub clips: Vec<Clip>,
+}
+
+impl Rig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path.as_ref())
+            .with_context(|| format!("Couldn't open rig {}", path.as_ref().display()))?;
+        let rig: Rig = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Bad rig {}", path.as_ref().display()))?;
+        for (i, b) in rig.skeleton.bones.iter().enumerate() {
+            if b.parent.is_some_and(|p| p >= i) {
+                bail!("Bone {} comes before its parent", b.name);
+            }
+        }
+        if rig.skeleton.len() > BONE_MAX {
+            bail!(
+                "Too many bones: {} (at most {})",
+                rig.skeleton.len(),
+                BONE_MAX
+            );
+        }
+        Ok(rig)
+    }
+
+    /// Where a rig for the model at `model` would be.
+    pub fn path_for(model: impl AsRef<Path>) -> std::path::PathBuf {
+        model.as_ref().with_extension("rig.json")
+    }
+
+    pub fn clip(&self, name: &str) -> Option<usize> {
+        self.clips.iter().position(|c| c.name == name)
+    }
+}
+
+/// Which clip a rigged model is playing and how far into it, fading from
+/// the clip before over a short while when it changes.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct State {
+    pub clip: usize,
+    pub time: f32,
+    // the clip faded out of, how far into it, and how far through the fade
+    // (out of `fade`)
+    previous: Option<(usize, f32, f32)>,
+    fade: f32,
+}
+
+impl State {
+    pub fn new(clip: usize) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            previous: None,
+            fade: 0.0,
+        }
+    }
+
+    /// Start `clip` from the beginning, fading over to it from whatever's
+    /// playing for `fade` seconds.  Asking for the clip that's already
+    /// playing carries on with it.
+    pub fn play(&mut self, clip: usize, fade: f32) {
+        if clip == self.clip {
+            return;
+        }
+        self.previous = if fade > 0.0 {
+            Some((self.clip, self.time, 0.0))
+        } else {
+            None
+        };
+        self.clip = clip;
+        self.time = 0.0;
+        self.fade = fade;
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+        if let Some((_, time, faded)) = &mut self.previous {
+            *time += dt;
+            *faded += dt;
+            if *faded >= self.fade {
+                self.previous = None;
+            }
+        }
+    }
+
+    /// Every bone of `rig` relative to its parent, right now.
+    pub fn pose(&self, rig: &Rig) -> Vec<Pose> {
+        let skeleton = &rig.skeleton;
+        let current = rig.clips[self.clip].sample(skeleton, self.time);
+        match self.previous {
+            Some((clip, time, faded)) => {
+                let previous = rig.clips[clip].sample(skeleton, time);
+                blend(&previous, &current, faded / self.fade)
+            }
+            None => current,
+        }
+    }
+
+    /// `pose`, ready for `InstanceGroups::render_anim`.
+    pub fn bones(&self, rig: &Rig) -> Vec<Bone> {
+        rig.skeleton.bones(&self.pose(rig))
+    }
 }
 
 pub trait DrawAnimated<'a, 'b>
 where
     'b: 'a,
 {
+    /// Draw `model` skinned by the bones at `offset` (in bytes) into the
+    /// bone buffer behind `bones`.
     fn draw_model_skinned(
         &mut self,
         model: &'b Model,
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
         bones: &'b wgpu::BindGroup,
+        offset: u32,
     );
 }
 
@@ -30,10 +443,9 @@ where
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
         bones: &'b wgpu::BindGroup,
+        offset: u32,
     ) {
-        self.set_bind_group(3, &bones, &[]);
+        self.set_bind_group(3, bones, &[offset]);
         self.draw_model_instanced(model, 0..1, uniforms, light);
     }
 }
-
-pub struct State {}
@@ -61,7 +61,9 @@ impl Assets {
                     | DebouncedEvent::Write(path)
                     | DebouncedEvent::Create(path) => {
                         match path.extension().map(|s| s.to_str().unwrap()) {
-                            Some("obj") => self.update_model(device, queue, layout, path),
+                            Some("obj") | Some("gltf") | Some("glb") => {
+                                self.update_model(device, queue, layout, path)
+                            }
                             Some("png") | Some("jpg") | Some("mtl") => self.update_model(
                                 device,
                                 queue,
@@ -93,6 +95,11 @@ impl Assets {
             .or_insert_with(|| Model::load(device, queue, layout, ar.join(&model)).unwrap());
         mref
     }
+    /// Where `p`, relative to the asset root, is on disk, for assets the
+    /// engine doesn't load itself.
+    pub fn asset_path(&self, p: impl AsRef<Path>) -> PathBuf {
+        self.asset_root.join(p)
+    }
     pub fn model_ref_for(&mut self, p: impl AsRef<Path>) -> ModelRef {
         let new_ref = ModelRef(self.model_refs.len());
         *self.model_refs.entry(p.as_ref().into()).or_insert(new_ref)
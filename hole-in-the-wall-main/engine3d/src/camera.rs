@@ -14,11 +14,35 @@ pub struct GameCamera {
 }
 
 impl GameCamera {
+    pub fn new(aspect: f32) -> Self {
+        Self {
+            eye: (0.0, 5.0, -10.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 200.0,
+        }
+    }
     pub fn build_view_projection_matrix(&self) -> (cgmath::Matrix4<f32>, cgmath::Matrix4<f32>) {
         let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
         (view, proj)
     }
+    /// The ray from the eye through a point on screen, given in normalized
+    /// device coordinates: -1 to 1 from left to right and bottom to top.
+    pub fn ray_through(&self, x: f32, y: f32) -> Ray {
+        let forward = (self.target - self.eye).normalize();
+        let right = forward.cross(self.up).normalize();
+        let up = right.cross(forward);
+        let half_h = (cgmath::Deg(self.fovy) / 2.0).tan();
+        let dir = forward + right * (x * half_h * self.aspect) + up * (y * half_h);
+        Ray {
+            p: self.eye,
+            dir: dir.normalize(),
+        }
+    }
 }
 
 pub trait Camera {
@@ -0,0 +1,210 @@
+//! Clustered light culling.  The view frustum is cut into a grid of
+//! clusters, tiles across the screen and slices in depth, and each cluster
+//! gets the list of lights that can reach it, so the fragment shader only
+//! has to light each fragment with the lights of its own cluster.
+
+use crate::camera::GameCamera;
+use crate::geom::*;
+use crate::lights::{self, Light, LightKind};
+
+/// How many clusters the frustum is cut into across, down and in depth.
+pub const CLUSTER_DIMS: [u32; 3] = [16, 9, 24];
+
+/// What the fragment shader needs to work out which cluster it's in.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ClusterUniform {
+    // w is unused
+    pub dims: [u32; 4],
+    pub screen: [f32; 2],
+    pub near: f32,
+    pub far: f32,
+}
+
+impl ClusterUniform {
+    pub fn new(camera: &GameCamera, dims: [u32; 3], width: u32, height: u32) -> Self {
+        Self {
+            dims: [dims[0], dims[1], dims[2], 0],
+            screen: [width as f32, height as f32],
+            near: camera.znear,
+            far: camera.zfar,
+        }
+    }
+}
+
+/// The box around each cluster of a camera's view, in view space.  These
+/// only change with the camera's projection, so they're worked out once and
+/// kept until it changes, rather than every time the lights are binned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterGrid {
+    pub dims: [u32; 3],
+    /// The camera's `fovy`, `aspect`, `znear` and `zfar` the boxes are for
+    projection: [f32; 4],
+    /// The corners of each cluster's box, numbered as in `Clusters`
+    bounds: Vec<(Pos3, Pos3)>,
+}
+
+impl ClusterGrid {
+    pub fn new(camera: &GameCamera, dims: [u32; 3]) -> Self {
+        let [nx, ny, nz] = dims;
+        let mut bounds = Vec::with_capacity((nx * ny * nz) as usize);
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    bounds.push(cluster_bounds(camera, dims, [x, y, z]));
+                }
+            }
+        }
+        Self {
+            dims,
+            projection: projection(camera),
+            bounds,
+        }
+    }
+
+    /// Whether these are still the clusters of `camera`'s view: false once
+    /// it's been resized or its projection has changed.
+    pub fn fits(&self, camera: &GameCamera) -> bool {
+        self.projection == projection(camera)
+    }
+}
+
+fn projection(camera: &GameCamera) -> [f32; 4] {
+    [camera.fovy, camera.aspect, camera.znear, camera.zfar]
+}
+
+/// The lights that reach each cluster.  Clusters are numbered from the top
+/// left of the screen, across, then down, then away from the camera.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clusters {
+    pub dims: [u32; 3],
+    /// Where each cluster's lights start in `indices`, and how many it has
+    pub ranges: Vec<[u32; 2]>,
+    /// Indices into the list of lights, grouped by cluster
+    pub indices: Vec<u32>,
+}
+
+impl Clusters {
+    /// Bin `lights` into the clusters of `grid`, which must fit `camera`.
+    /// Directional lights, and point lights and spotlights without a range,
+    /// reach every cluster.
+    pub fn build(grid: &ClusterGrid, camera: &GameCamera, lights: &[Light]) -> Self {
+        let (view, _) = camera.build_view_projection_matrix();
+        let bounds: Vec<Option<(Pos3, f32)>> = lights
+            .iter()
+            .map(|l| bounding_sphere(l).map(|(c, r)| (view.transform_point(c), r)))
+            .collect();
+        let mut ranges = Vec::with_capacity(grid.bounds.len());
+        let mut indices = vec![];
+        for &(min, max) in &grid.bounds {
+            let start = indices.len() as u32;
+            for (i, b) in bounds.iter().enumerate() {
+                let reaches = match b {
+                    None => true,
+                    Some((c, r)) => {
+                        let nearest = Pos3::new(
+                            c.x.clamp(min.x, max.x),
+                            c.y.clamp(min.y, max.y),
+                            c.z.clamp(min.z, max.z),
+                        );
+                        (nearest - c).magnitude2() <= r * r
+                    }
+                };
+                if reaches {
+                    indices.push(i as u32);
+                }
+            }
+            ranges.push([start, indices.len() as u32 - start]);
+        }
+        Self {
+            dims: grid.dims,
+            ranges,
+            indices,
+        }
+    }
+
+    /// The lights of cluster `index`.
+    pub fn lights(&self, index: usize) -> &[u32] {
+        let [start, count] = self.ranges[index];
+        &self.indices[start as usize..(start + count) as usize]
+    }
+
+    /// Which cluster a fragment at pixel `(x, y)` of the screen `uniform`
+    /// describes, `depth` in front of the camera, is in.  Mirrors
+    /// `cluster_index` in `shader.frag`.
+    pub fn cluster_at(&self, uniform: &ClusterUniform, x: f32, y: f32, depth: f32) -> usize {
+        let [nx, ny, nz] = self.dims;
+        let tile = |p: f32, size: f32, n: u32| (p / size * n as f32).clamp(0.0, n as f32 - 1.0);
+        let tx = tile(x, uniform.screen[0], nx) as u32;
+        let ty = tile(y, uniform.screen[1], ny) as u32;
+        let slice = (depth.max(uniform.near) / uniform.near).ln()
+            / (uniform.far / uniform.near).ln()
+            * nz as f32;
+        let tz = slice.clamp(0.0, nz as f32 - 1.0) as u32;
+        (tx + nx * (ty + ny * tz)) as usize
+    }
+}
+
+/// How far from the camera slice `k` of `n` starts.  Slices get deeper
+/// further away, so that each is roughly as deep as it is wide.
+fn slice_depth(camera: &GameCamera, k: u32, n: u32) -> f32 {
+    camera.znear * (camera.zfar / camera.znear).powf(k as f32 / n as f32)
+}
+
+/// The corners of the box around a cluster, in view space.
+fn cluster_bounds(camera: &GameCamera, dims: [u32; 3], [x, y, z]: [u32; 3]) -> (Pos3, Pos3) {
+    let half_h = (cgmath::Deg(camera.fovy) / 2.0).tan();
+    let half_w = half_h * camera.aspect;
+    // Tiles go down the screen from the top
+    let ndc_x = |i: u32| -1.0 + 2.0 * i as f32 / dims[0] as f32;
+    let ndc_y = |j: u32| 1.0 - 2.0 * j as f32 / dims[1] as f32;
+    let mut min = Pos3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut max = Pos3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+    for &d in &[
+        slice_depth(camera, z, dims[2]),
+        slice_depth(camera, z + 1, dims[2]),
+    ] {
+        for &sx in &[ndc_x(x), ndc_x(x + 1)] {
+            for &sy in &[ndc_y(y), ndc_y(y + 1)] {
+                // The camera looks down -z
+                let p = Pos3::new(sx * d * half_w, sy * d * half_h, -d);
+                min = Pos3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+                max = Pos3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+            }
+        }
+    }
+    (min, max)
+}
+
+/// A sphere around everywhere `light` reaches, in world space, or `None`
+/// if it reaches everywhere.
+pub fn bounding_sphere(light: &Light) -> Option<(Pos3, f32)> {
+    if light.range <= 0.0 {
+        return None;
+    }
+    let range = light.range;
+    match light.kind {
+        LightKind::Directional { .. } => None,
+        LightKind::Point { pos } => Some((pos, range)),
+        LightKind::Spot {
+            pos,
+            dir,
+            inner,
+            outer,
+        } => {
+            let dir = dir.normalize();
+            // As wide as the shader draws it, which may be wider than `outer`
+            let [_, cos_outer] = lights::cone(inner, outer);
+            let cos = cos_outer.max(0.0);
+            let sin = (1.0 - cos * cos).sqrt();
+            // Wide cones are bounded by the disc at their end, narrow ones
+            // by the sphere through the disc's rim and the tip
+            if cos < std::f32::consts::FRAC_1_SQRT_2 {
+                Some((pos + dir * range * cos, range * sin))
+            } else {
+                let r = range / (2.0 * cos);
+                Some((pos + dir * r, r))
+            }
+        }
+    }
+}
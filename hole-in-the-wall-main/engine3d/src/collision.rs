@@ -1,5 +1,7 @@
 use serde::{Serialize, Deserialize};
 use crate::geom::*;
+use crate::physics::Material;
+use std::collections::BTreeSet;
 
 #[derive(Clone, Copy, Debug)]
 #[derive(Serialize, Deserialize)]
@@ -8,124 +10,556 @@ pub struct Contact<T: Copy> {
     pub b: T,
     #[serde(with = "Vec3Def")]
     pub mtv: Vec3,
+    pub manifold: Manifold,
 }
 
-pub fn restitute_dyn_stat<S1: Shape, S2: Shape>(
-    ashapes: &mut [S1],
-    avel: &mut [Vec3],
-    bshapes: &[S2],
+impl<T: Copy> Contact<T> {
+    fn new(a: T, b: T, manifold: Manifold) -> Self {
+        Self {
+            a,
+            b,
+            mtv: manifold.normal * manifold.depth,
+            manifold,
+        }
+    }
+}
+
+/// The first moment during a step at which two moving shapes touch: `toi`
+/// is the time of impact as a fraction of the step, and `hit` is where `a`
+/// meets `b` (its normal pushes `a` out of `b`).
+#[derive(Clone, Copy, Debug)]
+pub struct Impact<T: Copy> {
+    pub a: T,
+    pub b: T,
+    pub toi: f32,
+    pub hit: CastHit,
+}
+
+/// The change in `vrel`, the velocity of one side of a contact relative to
+/// the other, that bounces and slides it as `material` says, where `n`
+/// pushes that side out of the other.  `None` if the two are already
+/// separating there.
+fn restitute(vrel: Vec3, n: Vec3, material: Material) -> Option<Vec3> {
+    let vn = n.dot(vrel);
+    if vn >= 0.0 {
+        return None;
+    }
+    let vt = vrel - vn * n;
+    // friction gets as much to work with as the bounce does
+    let grip = (1.0 + material.restitution) * -vn;
+    let slide = vt.magnitude();
+    let vt = if slide <= material.static_friction * grip {
+        Vec3::zero()
+    } else {
+        vt * ((slide - material.dynamic_friction * grip).max(0.0) / slide)
+    };
+    Some(vt - material.restitution * vn * n - vrel)
+}
+
+/// Take the velocity (in `avels`) of each moving shape into each static
+/// shape it touches away, bouncing and sliding as the two shapes'
+/// materials, combined, say.  Contacts are from `gather_contacts_ab`.
+pub fn restitute_dyn_stat(
+    avels: &mut [Vec3],
+    amaterials: &[Material],
+    bmaterials: &[Material],
     contacts: &mut [Contact<usize>],
-    bounce: bool,
-) where
-    S1: Collide<S2>,
-{
-    contacts.sort_unstable_by(|a, b| b.mtv.magnitude2().partial_cmp(&a.mtv.magnitude2()).unwrap());
+) {
+    contacts.sort_unstable_by(|a, b| b.mtv.magnitude2().total_cmp(&a.mtv.magnitude2()));
     for c in contacts.iter() {
-        let a = c.a;
-        let b = c.b;
-
-        if let Some(disp) = ashapes[a].disp(&bshapes[b]) {
-            // ashapes[a].translate(disp);
-            // avels[a] += disp;
-            // println!("prev {:?}", avel[a]);
-            if bounce {
-                avel[a] -= 2.0 * disp.dot(avel[a]) * disp;
-            } else {
-                avel[a] -= disp.dot(avel[a]) * disp;
-            }
-            // println!("afte {:?}", avel[a]);
+        let material = amaterials[c.a].combine(bmaterials[c.b]);
+        if let Some(dv) = restitute(avels[c.a], c.manifold.normal, material) {
+            avels[c.a] += dv;
         }
     }
 }
 
-pub fn restitute_dyn_dyn<S1: Shape, S2: Shape>(
-    ashapes: &mut [S1],
+/// Bounce and slide pairs of moving shapes in contacts from
+/// `gather_contacts_ab` off each other as their materials, combined, say.
+/// Every shape counts as weighing the same, so each side takes half of the
+/// change in their relative velocity.
+pub fn restitute_dyn_dyn(
     avels: &mut [Vec3],
-    bshapes: &mut [S2],
+    amaterials: &[Material],
     bvels: &mut [Vec3],
+    bmaterials: &[Material],
     contacts: &mut [Contact<usize>],
-) where
-    S1: Collide<S2>,
-{
-    contacts.sort_unstable_by(|a, b| b.mtv.magnitude2().partial_cmp(&a.mtv.magnitude2()).unwrap());
-    // That can bump into each other in perfectly elastic collisions!
+) {
+    contacts.sort_unstable_by(|a, b| b.mtv.magnitude2().total_cmp(&a.mtv.magnitude2()));
     for c in contacts.iter() {
-        let a = c.a;
-        let b = c.b;
-        // Just split the difference.  In crowded situations this will
-        // cause issues, but those will always be hard to solve with
-        // this kind of technique.
-        if let Some(disp) = ashapes[a].disp(&bshapes[b]) {
-            // ashapes[a].translate(-disp / 2.0);
-
-            let vel_diff = disp.dot(avels[a]).abs() * disp + disp.dot(bvels[b]).abs() * disp;
-            avels[a] += vel_diff;
-            bvels[b] -= vel_diff;
-            // avels[a] += disp.dot(avels[a]).abs() * disp;
-            // bshapes[b].translate(disp / 2.0);
-            // bvels[b] -= disp.dot(bvels[b]).abs() * disp;
+        let material = amaterials[c.a].combine(bmaterials[c.b]);
+        let vrel = avels[c.a] - bvels[c.b];
+        if let Some(dv) = restitute(vrel, c.manifold.normal, material) {
+            avels[c.a] += dv / 2.0;
+            bvels[c.b] -= dv / 2.0;
         }
     }
 }
 
-pub fn restitute_dyns<S1: Shape>(
-    ashapes: &mut [S1],
+/// `restitute_dyn_dyn` for contacts from `gather_contacts_aa` within one
+/// group of shapes.
+pub fn restitute_dyns(
     avels: &mut [Vec3],
+    amaterials: &[Material],
     contacts: &mut [Contact<usize>],
+) {
+    contacts.sort_unstable_by(|a, b| b.mtv.magnitude2().total_cmp(&a.mtv.magnitude2()));
+    for c in contacts.iter() {
+        let material = amaterials[c.a].combine(amaterials[c.b]);
+        let vrel = avels[c.a] - avels[c.b];
+        if let Some(dv) = restitute(vrel, c.manifold.normal, material) {
+            avels[c.a] += dv / 2.0;
+            avels[c.b] -= dv / 2.0;
+        }
+    }
+}
+
+/// Gather every contact between `a` and `b`, in the same order as
+/// `gather_contacts_ab_brute`.  Only pairs whose bounds overlap along all
+/// three axes are handed to the narrow phase.
+pub fn gather_contacts_ab<S1, S2>(a: &[S1], b: &[S2], into: &mut Vec<Contact<usize>>)
+where
+    S1: Collide<S2> + Bounded,
+    S2: Shape + Bounded,
+{
+    gather_contacts_ab_filtered(a, b, |_, _| true, into);
+}
+
+/// `gather_contacts_ab`, but only handing pairs `(ai, bi)` for which `keep`
+/// is true to the narrow phase, e.g. those whose `Layers` interact.
+pub fn gather_contacts_ab_filtered<S1, S2>(
+    a: &[S1],
+    b: &[S2],
+    keep: impl Fn(usize, usize) -> bool,
+    into: &mut Vec<Contact<usize>>,
 ) where
-    S1: Collide<S1>,
+    S1: Collide<S2> + Bounded,
+    S2: Shape + Bounded,
 {
-    contacts.sort_unstable_by(|a, b| b.mtv.magnitude2().partial_cmp(&a.mtv.magnitude2()).unwrap());
-    // That can bump into each other in perfectly elastic collisions!
-    for c in contacts.iter() {
-        let a = c.a;
-        let b = c.b;
-        // Just split the difference.  In crowded situations this will
-        // cause issues, but those will always be hard to solve with
-        // this kind of technique.
-        if let Some(disp) = ashapes[a].disp(&ashapes[b]) {
-            let vel_diff = disp.dot(avels[a]).abs() * disp + disp.dot(avels[b]).abs() * disp;
-            avels[a] += vel_diff;
-            avels[b] -= vel_diff;
-            // ashapes[a].translate(-disp / 2.0);
-            // avels[a] -= disp / 2.0;
-            // ashapes[b].translate(disp / 2.0);
-            // avels[b] += disp / 2.0;
+    let abounds: Vec<AABB> = a.iter().map(|s| s.bounds()).collect();
+    let bbounds: Vec<AABB> = b.iter().map(|s| s.bounds()).collect();
+    for (ai, bi) in overlapping_pairs_ab(&abounds, &bbounds) {
+        if !keep(ai, bi) {
+            continue;
+        }
+        if let Some(m) = a[ai].manifold(&b[bi]) {
+            into.push(Contact::new(ai, bi, m));
+        }
+    }
+}
+
+/// Gather every contact between distinct members of `ss`, in the same order
+/// as `gather_contacts_aa_brute`.
+pub fn gather_contacts_aa<S1>(ss: &[S1], into: &mut Vec<Contact<usize>>)
+where
+    S1: Collide<S1> + Bounded,
+{
+    gather_contacts_aa_filtered(ss, |_, _| true, into);
+}
+
+/// `gather_contacts_aa`, but only handing pairs `(ai, bi)` for which `keep`
+/// is true to the narrow phase.
+pub fn gather_contacts_aa_filtered<S1>(
+    ss: &[S1],
+    keep: impl Fn(usize, usize) -> bool,
+    into: &mut Vec<Contact<usize>>,
+) where
+    S1: Collide<S1> + Bounded,
+{
+    let bounds: Vec<AABB> = ss.iter().map(|s| s.bounds()).collect();
+    for (ai, bi) in overlapping_pairs_aa(&bounds) {
+        if !keep(ai, bi) {
+            continue;
+        }
+        if let Some(m) = ss[ai].manifold(&ss[bi]) {
+            into.push(Contact::new(ai, bi, m));
+        }
+    }
+}
+
+/// Which layers a shape is on, and which layers it collides with.  Two
+/// shapes interact only if each one's mask takes in a layer of the other.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Layers {
+    pub layer: u32,
+    pub mask: u32,
+}
+
+impl Layers {
+    /// On every layer and colliding with everything, like unlayered shapes.
+    pub const ALL: Layers = Layers {
+        layer: !0,
+        mask: !0,
+    };
+    /// Colliding with nothing at all.
+    pub const NONE: Layers = Layers { layer: 0, mask: 0 };
+
+    pub fn new(layer: u32, mask: u32) -> Self {
+        Self { layer, mask }
+    }
+    pub fn interacts(self, other: Layers) -> bool {
+        self.mask & other.layer != 0 && other.mask & self.layer != 0
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum TriggerPhase {
+    /// The body started touching the trigger this step.
+    Enter,
+    /// The body was already touching the trigger and still is.
+    Stay,
+    /// The body stopped touching the trigger (or stopped interacting with
+    /// it) this step.
+    Exit,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct TriggerEvent<T: Copy> {
+    pub body: T,
+    pub trigger: T,
+    pub phase: TriggerPhase,
+}
+
+/// Trigger volumes: shapes that report what touches them but never push
+/// anything back.  `Triggers` remembers which bodies were touching which
+/// triggers from one update to the next, so bodies and triggers should keep
+/// their keys (indices, by default) between steps.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Triggers<T: Copy + Ord = usize> {
+    touching: BTreeSet<(T, T)>,
+    events: Vec<TriggerEvent<T>>,
+}
+
+impl<T: Copy + Ord> Default for Triggers<T> {
+    fn default() -> Self {
+        Self {
+            touching: BTreeSet::new(),
+            events: vec![],
+        }
+    }
+}
+
+impl Triggers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check `bodies` against `triggers` and work out this step's events.
+    pub fn update<S1, S2>(
+        &mut self,
+        bodies: &[S1],
+        blayers: &[Layers],
+        triggers: &[S2],
+        tlayers: &[Layers],
+    ) where
+        S1: Collide<S2> + Bounded,
+        S2: Shape + Bounded,
+    {
+        self.update_touching(touching_triggers(bodies, blayers, triggers, tlayers));
+    }
+}
+
+/// The (body, trigger) index pairs of `bodies` touching `triggers`, among
+/// those whose layers interact.
+pub fn touching_triggers<S1, S2>(
+    bodies: &[S1],
+    blayers: &[Layers],
+    triggers: &[S2],
+    tlayers: &[Layers],
+) -> BTreeSet<(usize, usize)>
+where
+    S1: Collide<S2> + Bounded,
+    S2: Shape + Bounded,
+{
+    let bbounds: Vec<AABB> = bodies.iter().map(|s| s.bounds()).collect();
+    let tbounds: Vec<AABB> = triggers.iter().map(|s| s.bounds()).collect();
+    overlapping_pairs_ab(&bbounds, &tbounds)
+        .into_iter()
+        .filter(|&(b, t)| blayers[b].interacts(tlayers[t]))
+        .filter(|&(b, t)| bodies[b].touching(&triggers[t]))
+        .collect()
+}
+
+impl<T: Copy + Ord> Triggers<T> {
+    /// Work out this step's events from the (body, trigger) pairs touching
+    /// now.
+    pub fn update_touching(&mut self, touching: BTreeSet<(T, T)>) {
+        self.events.clear();
+        for &(body, trigger) in touching.union(&self.touching) {
+            let phase = match (
+                self.touching.contains(&(body, trigger)),
+                touching.contains(&(body, trigger)),
+            ) {
+                (false, _) => TriggerPhase::Enter,
+                (true, true) => TriggerPhase::Stay,
+                (true, false) => TriggerPhase::Exit,
+            };
+            self.events.push(TriggerEvent {
+                body,
+                trigger,
+                phase,
+            });
         }
+        self.touching = touching;
+    }
+
+    /// This step's events, ordered by body and then trigger.
+    pub fn events(&self) -> &[TriggerEvent<T>] {
+        &self.events
     }
+
+    /// Did anything start touching `trigger` this step?
+    pub fn entered(&self, trigger: T) -> bool {
+        self.events
+            .iter()
+            .any(|e| e.trigger == trigger && e.phase == TriggerPhase::Enter)
+    }
+
+    /// Is anything touching `trigger`?
+    pub fn occupied(&self, trigger: T) -> bool {
+        self.touching.iter().any(|&(_, t)| t == trigger)
+    }
+
+    /// Forget everything, so whatever is touching a trigger on the next
+    /// update enters it afresh.
+    pub fn clear(&mut self) {
+        self.touching.clear();
+        self.events.clear();
+    }
+}
+
+/// Continuous collision detection: gather every pair of `a` and `b` that
+/// touch at some point while moving at `avels` and `bvels` for `dt`, even if
+/// they pass right through each other within the step.  Shapes are swept
+/// along straight lines and don't turn.
+pub fn gather_impacts_dyn_dyn<S1, S2>(
+    a: &[S1],
+    avels: &[Vec3],
+    b: &[S2],
+    bvels: &[Vec3],
+    dt: f32,
+    into: &mut Vec<Impact<usize>>,
+) where
+    S1: Collide<S2> + Bounded + Copy,
+    S2: Shape + Bounded,
+    Sweep<S1>: Cast<S2>,
+{
+    let abounds: Vec<AABB> = a
+        .iter()
+        .zip(avels)
+        .map(|(s, v)| s.bounds().swept(v * dt))
+        .collect();
+    let bbounds: Vec<AABB> = b
+        .iter()
+        .zip(bvels)
+        .map(|(s, v)| s.bounds().swept(v * dt))
+        .collect();
+    for (ai, bi) in overlapping_pairs_ab(&abounds, &bbounds) {
+        // Sweep a through b's frame of reference
+        let d = (avels[ai] - bvels[bi]) * dt;
+        let dist = d.magnitude();
+        let hit = if dist < f32::EPSILON {
+            // Not moving relative to each other, so only already touching
+            // counts; a manifold without points has nowhere to say it hit
+            a[ai].manifold(&b[bi]).and_then(|m| {
+                Some(CastHit {
+                    point: *m.points().first()?,
+                    t: 0.0,
+                    normal: m.normal,
+                })
+            })
+        } else {
+            let sweep = Sweep {
+                shape: a[ai],
+                dir: d / dist,
+            };
+            sweep.cast(&b[bi]).filter(|hit| hit.t <= dist)
+        };
+        if let Some(hit) = hit {
+            into.push(Impact {
+                a: ai,
+                b: bi,
+                toi: hit.t / dist.max(f32::EPSILON),
+                hit,
+            });
+        }
+    }
+}
+
+/// `gather_impacts_dyn_dyn` against shapes that stay put, such as planes.
+pub fn gather_impacts_dyn_stat<S1, S2>(
+    a: &[S1],
+    avels: &[Vec3],
+    b: &[S2],
+    dt: f32,
+    into: &mut Vec<Impact<usize>>,
+) where
+    S1: Collide<S2> + Bounded + Copy,
+    S2: Shape + Bounded,
+    Sweep<S1>: Cast<S2>,
+{
+    let bvels = vec![Vec3::zero(); b.len()];
+    gather_impacts_dyn_dyn(a, avels, b, &bvels, dt, into);
 }
 
-pub fn gather_contacts_ab<S1: Shape, S2: Shape>(a: &[S1], b: &[S2], into: &mut Vec<Contact<usize>>)
+pub fn gather_contacts_ab_brute<S1, S2>(a: &[S1], b: &[S2], into: &mut Vec<Contact<usize>>)
 where
     S1: Collide<S2>,
+    S2: Shape,
 {
     for (ai, a) in a.iter().enumerate() {
         for (bi, b) in b.iter().enumerate() {
-            if let Some(disp) = a.disp(b) {
-                into.push(Contact {
-                    a: ai,
-                    b: bi,
-                    mtv: disp,
-                });
+            if let Some(m) = a.manifold(b) {
+                into.push(Contact::new(ai, bi, m));
             }
         }
     }
 }
 
-pub fn gather_contacts_aa<S1: Shape>(ss: &[S1], into: &mut Vec<Contact<usize>>)
+pub fn gather_contacts_aa_brute<S1>(ss: &[S1], into: &mut Vec<Contact<usize>>)
 where
     S1: Collide<S1>,
 {
     for (ai, a) in ss.iter().enumerate() {
         for (bi, b) in ss[(ai + 1)..].iter().enumerate() {
             let bi = ai + 1 + bi;
-            if let Some(disp) = a.disp(b) {
-                into.push(Contact {
-                    a: ai,
-                    b: bi,
-                    mtv: disp,
-                });
+            if let Some(m) = a.manifold(b) {
+                into.push(Contact::new(ai, bi, m));
             }
         }
     }
 }
+
+/// The first of `targets` that `caster` hits, and its index.  Targets can be
+/// any mix of shapes, e.g. `&[&floor, &player.body]`.
+pub fn cast_nearest<C>(caster: &C, targets: &[&dyn Target<C>]) -> Option<(usize, CastHit)> {
+    nearest_hit(targets.iter().map(|t| t.hit_by(caster)))
+}
+
+/// The first of a group of shapes of one kind that `caster` hits, and its
+/// index.
+pub fn cast_nearest_in<C, S>(caster: &C, targets: &[S]) -> Option<(usize, CastHit)>
+where
+    C: Cast<S>,
+    S: Shape,
+{
+    nearest_hit(targets.iter().map(|t| caster.cast(t)))
+}
+
+/// Can `from` see `to`, or do any of `blockers` get in the way?
+pub fn line_of_sight(from: Pos3, to: Pos3, blockers: &[&dyn Target<Ray>]) -> bool {
+    let offset = to - from;
+    let dist = offset.magnitude();
+    if dist == 0.0 {
+        return true;
+    }
+    let ray = Ray {
+        p: from,
+        dir: offset / dist,
+    };
+    blockers
+        .iter()
+        .filter_map(|b| b.hit_by(&ray))
+        .all(|hit| hit.t >= dist)
+}
+
+fn nearest_hit(hits: impl Iterator<Item = Option<CastHit>>) -> Option<(usize, CastHit)> {
+    hits.enumerate()
+        .filter_map(|(i, hit)| hit.map(|hit| (i, hit)))
+        .min_by(|(_, a), (_, b)| a.t.total_cmp(&b.t))
+}
+
+/// Sort-and-sweep broad phase (Ericson pp.329-38): sort the bounds by their
+/// minimum along one axis, then each box only needs checking against the ones
+/// that start before it ends.  Pairs come back sorted, so the narrow phase
+/// visits them in the same order as the brute-force loops.
+fn overlapping_pairs_aa(bounds: &[AABB]) -> Vec<(usize, usize)> {
+    let axis = sweep_axis(bounds.iter());
+    let order = sorted_by_min(bounds, axis);
+    let mut pairs = vec![];
+    for (k, &i) in order.iter().enumerate() {
+        for &j in order[(k + 1)..].iter() {
+            if bounds[j].min()[axis] > bounds[i].max()[axis] {
+                break;
+            }
+            if bounds[i].overlaps(&bounds[j]) {
+                pairs.push((i.min(j), i.max(j)));
+            }
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+/// The two-list version of `overlapping_pairs_aa`: walk both sorted lists
+/// together, sweeping whichever box starts first against the other list.
+fn overlapping_pairs_ab(abounds: &[AABB], bbounds: &[AABB]) -> Vec<(usize, usize)> {
+    let axis = sweep_axis(abounds.iter().chain(bbounds.iter()));
+    let aorder = sorted_by_min(abounds, axis);
+    let border = sorted_by_min(bbounds, axis);
+    let mut pairs = vec![];
+    let (mut ak, mut bk) = (0, 0);
+    while ak < aorder.len() && bk < border.len() {
+        let (ai, bi) = (aorder[ak], border[bk]);
+        if abounds[ai].min()[axis] <= bbounds[bi].min()[axis] {
+            for &bj in border[bk..].iter() {
+                if bbounds[bj].min()[axis] > abounds[ai].max()[axis] {
+                    break;
+                }
+                if abounds[ai].overlaps(&bbounds[bj]) {
+                    pairs.push((ai, bj));
+                }
+            }
+            ak += 1;
+        } else {
+            for &aj in aorder[ak..].iter() {
+                if abounds[aj].min()[axis] > bbounds[bi].max()[axis] {
+                    break;
+                }
+                if abounds[aj].overlaps(&bbounds[bi]) {
+                    pairs.push((aj, bi));
+                }
+            }
+            bk += 1;
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Sweep along whichever axis the centers are most spread out on, so the
+/// fewest boxes overlap in the sorted order.
+fn sweep_axis<'a>(bounds: impl Iterator<Item = &'a AABB>) -> usize {
+    let mut sum = Vec3::zero();
+    let mut sum2 = Vec3::zero();
+    let mut n = 0.0;
+    for b in bounds {
+        let c = b.c.to_vec();
+        sum += c;
+        sum2 += c.mul_element_wise(c);
+        n += 1.0;
+    }
+    if n == 0.0 {
+        return 0;
+    }
+    let variance = sum2 / n - (sum / n).mul_element_wise(sum / n);
+    if variance.x >= variance.y && variance.x >= variance.z {
+        0
+    } else if variance.y >= variance.z {
+        1
+    } else {
+        2
+    }
+}
+
+fn sorted_by_min(bounds: &[AABB], axis: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..bounds.len()).collect();
+    order.sort_unstable_by(|&i, &j| bounds[i].min()[axis].total_cmp(&bounds[j].min()[axis]));
+    order
+}
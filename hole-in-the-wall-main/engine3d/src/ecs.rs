@@ -0,0 +1,827 @@
+use crate::assets::ModelRef;
+use crate::collision::{self, Contact, Impact, Layers, Triggers};
+use crate::geom::*;
+use crate::joints::{Joint, JointKind};
+use crate::physics::{self, Material, RigidBody, Solid};
+use crate::render::{InstanceGroups, InstanceRaw};
+use crate::scene::{NodeId, Scene};
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::{Index, IndexMut};
+
+/// A handle to something in a `World`.  Handles to despawned entities go
+/// stale rather than pointing at whatever reuses their slot.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+/// One kind of component, for any entities that have it.  Games can keep
+/// their own `Components` alongside the `World`'s for data of their own.
+#[derive(Clone, Debug)]
+pub struct Components<T> {
+    slots: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Default for Components<T> {
+    fn default() -> Self {
+        Self { slots: vec![] }
+    }
+}
+
+impl<T> Components<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Give `e` this component, handing back the one it replaces.
+    pub fn insert(&mut self, e: Entity, value: T) -> Option<T> {
+        let i = e.index as usize;
+        if self.slots.len() <= i {
+            self.slots.resize_with(i + 1, || None);
+        }
+        self.slots[i]
+            .replace((e.generation, value))
+            .and_then(|(g, old)| if g == e.generation { Some(old) } else { None })
+    }
+    pub fn remove(&mut self, e: Entity) -> Option<T> {
+        if !self.contains(e) {
+            return None;
+        }
+        self.slots[e.index as usize].take().map(|(_, v)| v)
+    }
+    pub fn get(&self, e: Entity) -> Option<&T> {
+        match self.slots.get(e.index as usize) {
+            Some(Some((g, v))) if *g == e.generation => Some(v),
+            _ => None,
+        }
+    }
+    pub fn get_mut(&mut self, e: Entity) -> Option<&mut T> {
+        match self.slots.get_mut(e.index as usize) {
+            Some(Some((g, v))) if *g == e.generation => Some(v),
+            _ => None,
+        }
+    }
+    pub fn contains(&self, e: Entity) -> bool {
+        self.get(e).is_some()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.slots.iter().enumerate().filter_map(|(i, slot)| {
+            slot.as_ref().map(|(generation, v)| {
+                let e = Entity {
+                    index: i as u32,
+                    generation: *generation,
+                };
+                (e, v)
+            })
+        })
+    }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(i, slot)| {
+            slot.as_mut().map(|(generation, v)| {
+                let e = Entity {
+                    index: i as u32,
+                    generation: *generation,
+                };
+                (e, v)
+            })
+        })
+    }
+    fn clear_slot(&mut self, index: u32) {
+        if let Some(slot) = self.slots.get_mut(index as usize) {
+            *slot = None;
+        }
+    }
+}
+
+impl<T> Index<Entity> for Components<T> {
+    type Output = T;
+    fn index(&self, e: Entity) -> &T {
+        self.get(e).expect("entity has no such component")
+    }
+}
+
+impl<T> IndexMut<Entity> for Components<T> {
+    fn index_mut(&mut self, e: Entity) -> &mut T {
+        self.get_mut(e).expect("entity has no such component")
+    }
+}
+
+/// Where an entity is and which way it faces.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Transform {
+    #[serde(with = "Pos3Def")]
+    pub pos: Pos3,
+    #[serde(with = "QuatDef")]
+    pub rot: Quat,
+}
+
+impl Transform {
+    pub fn at(pos: Pos3) -> Self {
+        Self {
+            pos,
+            rot: Quat::one(),
+        }
+    }
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_translation(self.pos.to_vec()) * Mat4::from(self.rot)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Velocity {
+    #[serde(with = "Vec3Def")]
+    pub linear: Vec3,
+    #[serde(with = "Vec3Def")]
+    pub angular: Vec3, // in world space
+}
+
+impl Velocity {
+    pub fn linear(linear: Vec3) -> Self {
+        Self {
+            linear,
+            angular: Vec3::zero(),
+        }
+    }
+}
+
+impl Default for Velocity {
+    fn default() -> Self {
+        Self::linear(Vec3::zero())
+    }
+}
+
+/// Lets contacts push an entity around.  Entities with colliders but no
+/// mass are immovable.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Mass {
+    pub inv_mass: f32,
+    #[serde(with = "Vec3Def")]
+    pub inv_inertia: Vec3, // inverse principal moments, in the entity's frame
+}
+
+impl Mass {
+    pub fn new<S: Solid>(shape: &S, mass: f32) -> Self {
+        let body = RigidBody::new(shape, mass);
+        Self {
+            inv_mass: body.inv_mass,
+            inv_inertia: body.inv_inertia,
+        }
+    }
+}
+
+/// A shape, in the entity's own frame, for collisions and triggers.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Collider {
+    pub shape: AnyShape,
+    pub layers: Layers,
+    /// Triggers report what touches them (see `World::update_triggers`) but
+    /// never push back.
+    pub trigger: bool,
+    pub material: Material,
+}
+
+impl Collider {
+    pub fn new(shape: AnyShape) -> Self {
+        Self {
+            shape,
+            layers: Layers::ALL,
+            trigger: false,
+            material: Material::default(),
+        }
+    }
+    pub fn cuboid(half_sizes: Vec3) -> Self {
+        Self::new(AnyShape::Box(Box {
+            c: Pos3::origin(),
+            axes: Mat3::one(),
+            half_sizes,
+        }))
+    }
+    pub fn with_layers(self, layers: Layers) -> Self {
+        Self { layers, ..self }
+    }
+    pub fn with_material(self, material: Material) -> Self {
+        Self { material, ..self }
+    }
+    pub fn as_trigger(self) -> Self {
+        Self {
+            trigger: true,
+            ..self
+        }
+    }
+    /// The shape in world space, for an entity at `t`.
+    pub fn posed(&self, t: &Transform) -> AnyShape {
+        let place = |p: Pos3| t.pos + t.rot * p.to_vec();
+        match self.shape {
+            AnyShape::Sphere(s) => AnyShape::Sphere(Sphere {
+                c: place(s.c),
+                r: s.r,
+            }),
+            AnyShape::Box(b) => AnyShape::Box(Box {
+                c: place(b.c),
+                axes: Mat3::from(t.rot) * b.axes,
+                half_sizes: b.half_sizes,
+            }),
+            AnyShape::Plane(p) => {
+                let n = t.rot * p.n;
+                AnyShape::Plane(Plane {
+                    n,
+                    d: p.d + n.dot(t.pos.to_vec()),
+                })
+            }
+            AnyShape::Capsule(c) => AnyShape::Capsule(Capsule {
+                a: place(c.a),
+                b: place(c.b),
+                r: c.r,
+            }),
+        }
+    }
+}
+
+/// What to draw for an entity: `local` places the model in the entity's
+/// frame, e.g. to scale a unit cube up to the size of its collider.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Model {
+    pub model: ModelRef,
+    pub local: Mat4,
+    pub visible: bool,
+    /// Whether it casts shadows from lights that cast them.
+    pub casts_shadow: bool,
+}
+
+impl Model {
+    pub fn new(model: ModelRef) -> Self {
+        Self {
+            model,
+            local: Mat4::one(),
+            visible: true,
+            casts_shadow: false,
+        }
+    }
+    pub fn scaled(model: ModelRef, scale: Vec3) -> Self {
+        Self {
+            local: Mat4::from_nonuniform_scale(scale.x, scale.y, scale.z),
+            ..Self::new(model)
+        }
+    }
+    pub fn casting_shadow(self) -> Self {
+        Self {
+            casts_shadow: true,
+            ..self
+        }
+    }
+}
+
+/// Entities moving slower than this, turning included, count as still.
+pub const SLEEP_SPEED: f32 = 0.1;
+/// How many steps an entity has to stay still before it can fall asleep.
+pub const SLEEP_STEPS: u32 = 30;
+
+/// Lets an entity fall asleep once it and everything it's resting on or
+/// under have been still for `SLEEP_STEPS`.  Asleep, it isn't moved or
+/// collided with anything else that's asleep or can't move, until something
+/// moving bumps into it or it's woken with `World::wake`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Sleep {
+    still: u32,
+    // entities that fell asleep together wake up together
+    island: Option<u32>,
+}
+
+impl Sleep {
+    pub fn is_asleep(&self) -> bool {
+        self.island.is_some()
+    }
+}
+
+/// A sound that follows an entity around.  The engine doesn't play sound
+/// itself; games read these through `World::emitters`.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct AudioEmitter {
+    pub sound: String,
+    pub volume: f32,
+    pub looping: bool,
+    pub playing: bool,
+}
+
+impl AudioEmitter {
+    pub fn new(sound: impl Into<String>, volume: f32, looping: bool) -> Self {
+        Self {
+            sound: sound.into(),
+            volume,
+            looping,
+            playing: false,
+        }
+    }
+}
+
+/// Every entity and the engine's components for them, with systems to
+/// move, collide and draw them.
+#[derive(Default)]
+pub struct World {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    pub transforms: Components<Transform>,
+    pub velocities: Components<Velocity>,
+    pub masses: Components<Mass>,
+    pub colliders: Components<Collider>,
+    pub models: Components<Model>,
+    pub emitters: Components<AudioEmitter>,
+    pub sleep: Components<Sleep>,
+    /// Joints between entities, solved along with their contacts.  Joints
+    /// go away with either of their entities.
+    pub joints: Vec<Joint<Entity>>,
+    /// Things that ride along with entities, such as labels and lights,
+    /// hang off the entities' nodes (see `World::node`).
+    pub scene: Scene,
+    nodes: Components<NodeId>,
+    triggers: Triggers<Entity>,
+    next_island: u32,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.generations.push(0);
+                self.alive.push(false);
+                (self.generations.len() - 1) as u32
+            }
+        };
+        self.alive[index as usize] = true;
+        Entity {
+            index,
+            generation: self.generations[index as usize],
+        }
+    }
+
+    /// Remove `e` and all its components.
+    pub fn despawn(&mut self, e: Entity) {
+        if !self.is_alive(e) {
+            return;
+        }
+        let i = e.index as usize;
+        self.alive[i] = false;
+        self.generations[i] += 1;
+        self.free.push(e.index);
+        self.transforms.clear_slot(e.index);
+        self.velocities.clear_slot(e.index);
+        self.masses.clear_slot(e.index);
+        self.colliders.clear_slot(e.index);
+        self.models.clear_slot(e.index);
+        self.emitters.clear_slot(e.index);
+        self.sleep.clear_slot(e.index);
+        self.joints.retain(|j| j.a != e && j.b != Some(e));
+        if let Some(node) = self.nodes.remove(e) {
+            self.scene.remove(node);
+        }
+    }
+
+    pub fn is_alive(&self, e: Entity) -> bool {
+        let i = e.index as usize;
+        i < self.alive.len() && self.alive[i] && self.generations[i] == e.generation
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive
+            .iter()
+            .enumerate()
+            .filter(|(_, alive)| **alive)
+            .map(move |(i, _)| Entity {
+                index: i as u32,
+                generation: self.generations[i],
+            })
+    }
+
+    /// Move everything with a velocity along for `dt`, apart from whatever's
+    /// asleep.
+    pub fn integrate(&mut self, dt: f32) {
+        for (e, v) in self.velocities.iter() {
+            if self.is_asleep(e) {
+                continue;
+            }
+            if let Some(t) = self.transforms.get_mut(e) {
+                let drot = 0.5 * dt * Quat::new(0.0, v.angular.x, v.angular.y, v.angular.z) * t.rot;
+                t.rot = (t.rot + drot).normalize();
+                t.pos += v.linear * dt;
+            }
+        }
+    }
+
+    pub fn is_asleep(&self, e: Entity) -> bool {
+        self.sleep.get(e).is_some_and(Sleep::is_asleep)
+    }
+
+    /// Wake `e`, and everything that fell asleep along with it.
+    pub fn wake(&mut self, e: Entity) {
+        let island = match self.sleep.get(e).and_then(|s| s.island) {
+            Some(island) => island,
+            None => return,
+        };
+        for (_, s) in self.sleep.iter_mut() {
+            if s.island == Some(island) {
+                *s = Sleep::default();
+            }
+        }
+    }
+
+    // how fast `e` is moving or turning, whichever's faster
+    fn speed(&self, e: Entity) -> f32 {
+        self.velocities
+            .get(e)
+            .map_or(0.0, |v| v.linear.magnitude().max(v.angular.magnitude()))
+    }
+
+    /// Count how long every entity that can sleep has been still, and put to
+    /// sleep each group of them that's been touching and still for long
+    /// enough, counting jointed entities as touching.  Anything awake
+    /// touching a group that can't fall asleep itself, such as the player
+    /// standing on a pile, keeps it awake.  Call once per step, after
+    /// `solve`.
+    pub fn update_sleep(&mut self, contacts: &[Contact<Entity>]) {
+        let awake: Vec<Entity> = self
+            .sleep
+            .iter()
+            .filter(|(_, s)| !s.is_asleep())
+            .map(|(e, _)| e)
+            .collect();
+        for &e in awake.iter() {
+            let still = self.speed(e) < SLEEP_SPEED;
+            let s = &mut self.sleep[e];
+            s.still = if still { s.still + 1 } else { 0 };
+        }
+        let index: BTreeMap<Entity, usize> =
+            awake.iter().enumerate().map(|(i, &e)| (e, i)).collect();
+
+        // Union-find over awake sleepers that touch
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        let mut parent: Vec<usize> = (0..awake.len()).collect();
+        let mut restless = vec![false; awake.len()];
+        let joined = self.joints.iter().filter_map(|j| Some((j.a, j.b?)));
+        for (a, b) in contacts.iter().map(|c| (c.a, c.b)).chain(joined) {
+            match (index.get(&a), index.get(&b)) {
+                (Some(&i), Some(&j)) => {
+                    let (ri, rj) = (root(&mut parent, i), root(&mut parent, j));
+                    parent[ri] = rj;
+                }
+                (Some(&i), None) => restless[i] |= self.keeps_awake(b),
+                (None, Some(&j)) => restless[j] |= self.keeps_awake(a),
+                (None, None) => {}
+            }
+        }
+        let mut settled = vec![true; awake.len()];
+        for (i, &e) in awake.iter().enumerate() {
+            let r = root(&mut parent, i);
+            settled[r] &= !restless[i] && self.sleep[e].still >= SLEEP_STEPS;
+        }
+        let mut islands = BTreeMap::new();
+        for (i, &e) in awake.iter().enumerate() {
+            let r = root(&mut parent, i);
+            if !settled[r] {
+                continue;
+            }
+            let next = &mut self.next_island;
+            let island = *islands.entry(r).or_insert_with(|| {
+                *next += 1;
+                *next
+            });
+            self.sleep[e].island = Some(island);
+            if let Some(v) = self.velocities.get_mut(e) {
+                *v = Velocity::default();
+            }
+        }
+    }
+
+    // something awake that can move but can't fall asleep
+    fn keeps_awake(&self, e: Entity) -> bool {
+        self.velocities.contains(e) && !self.sleep.contains(e)
+    }
+
+    /// `e`'s collider in world space.
+    pub fn shape(&self, e: Entity) -> Option<AnyShape> {
+        Some(self.colliders.get(e)?.posed(self.transforms.get(e)?))
+    }
+
+    fn posed_colliders(&self, triggers: bool) -> (Vec<Entity>, Vec<AnyShape>, Vec<Layers>) {
+        let mut entities = vec![];
+        let mut shapes = vec![];
+        let mut layers = vec![];
+        for (e, c) in self.colliders.iter() {
+            if c.trigger != triggers {
+                continue;
+            }
+            if let Some(t) = self.transforms.get(e) {
+                entities.push(e);
+                shapes.push(c.posed(t));
+                layers.push(c.layers);
+            }
+        }
+        (entities, shapes, layers)
+    }
+
+    /// Gather contacts between every pair of colliders whose layers
+    /// interact, leaving out triggers, and pairs where neither side can move
+    /// or is awake.
+    pub fn gather_contacts(&self, into: &mut Vec<Contact<Entity>>) {
+        let (entities, shapes, layers) = self.posed_colliders(false);
+        let awake: Vec<bool> = entities
+            .iter()
+            .map(|&e| self.velocities.contains(e) && !self.is_asleep(e))
+            .collect();
+        let mut contacts = vec![];
+        // Nothing can happen between two shapes both asleep or fixed in place
+        crate::collision::gather_contacts_aa_filtered(
+            &shapes,
+            |i, j| (awake[i] || awake[j]) && layers[i].interacts(layers[j]),
+            &mut contacts,
+        );
+        into.extend(contacts.into_iter().map(|c| Contact {
+            a: entities[c.a],
+            b: entities[c.b],
+            mtv: c.mtv,
+            manifold: c.manifold,
+        }));
+    }
+
+    /// Sweep `e` and `others` along their velocities for `dt`, finding which
+    /// of `others` it would hit during the step (see
+    /// `collision::gather_impacts_dyn_dyn`).  Only boxes are swept.
+    pub fn gather_impacts(&self, e: Entity, others: &[Entity], dt: f32) -> Vec<Impact<Entity>> {
+        let boxed = |e: Entity| match self.shape(e) {
+            Some(AnyShape::Box(b)) => Some(b),
+            _ => None,
+        };
+        let vel = |e: Entity| self.velocities.get(e).map_or(Vec3::zero(), |v| v.linear);
+        let a = match boxed(e) {
+            Some(a) => a,
+            None => return vec![],
+        };
+        let (bs, targets): (Vec<Entity>, Vec<Box>) = others
+            .iter()
+            .filter_map(|&o| boxed(o).map(|b| (o, b)))
+            .unzip();
+        let bvels: Vec<Vec3> = bs.iter().map(|&o| vel(o)).collect();
+        let mut impacts = vec![];
+        crate::collision::gather_impacts_dyn_dyn(
+            &[a],
+            &[vel(e)],
+            &targets,
+            &bvels,
+            dt,
+            &mut impacts,
+        );
+        impacts
+            .into_iter()
+            .map(|i| Impact {
+                a: e,
+                b: bs[i.b],
+                toi: i.toi,
+                hit: i.hit,
+            })
+            .collect()
+    }
+
+    /// Where the world point `p` is in `e`'s own frame, measured from its
+    /// center, or just `p` for the world.
+    fn anchor(&self, e: Option<Entity>, p: Pos3) -> Vec3 {
+        match e {
+            Some(e) => {
+                let t = self.transforms[e];
+                t.rot.conjugate() * (p - t.pos)
+            }
+            None => p.to_vec(),
+        }
+    }
+
+    // how `e` is turned, or not at all for the world
+    fn rotation(&self, e: Option<Entity>) -> Quat {
+        e.map_or(Quat::one(), |e| self.transforms[e].rot)
+    }
+
+    /// Pin `a` to `b`, or to the world with no `b`, at the world point `at`,
+    /// leaving them free to turn any way about it.  Fails if `b` is `a`, as
+    /// do the other joints.
+    pub fn ball_socket(&mut self, a: Entity, b: Option<Entity>, at: Pos3) -> Result<()> {
+        let (anchor_a, anchor_b) = (self.anchor(Some(a), at), self.anchor(b, at));
+        let joint = Joint::new(a, anchor_a, b, anchor_b, JointKind::BallSocket)?;
+        self.joints.push(joint);
+        Ok(())
+    }
+
+    /// Pin `a` to `b`, or to the world, at `at`, so they can only turn
+    /// about the line through `at` along `axis`, like a door on its hinges.
+    pub fn hinge(&mut self, a: Entity, b: Option<Entity>, at: Pos3, axis: Vec3) -> Result<()> {
+        let axis = axis.normalize();
+        let kind = JointKind::Hinge {
+            axis_a: self.rotation(Some(a)).conjugate() * axis,
+            axis_b: self.rotation(b).conjugate() * axis,
+        };
+        let (anchor_a, anchor_b) = (self.anchor(Some(a), at), self.anchor(b, at));
+        let joint = Joint::new(a, anchor_a, b, anchor_b, kind)?;
+        self.joints.push(joint);
+        Ok(())
+    }
+
+    /// Keep the point `at_a` on `a` and `at_b` on `b` (or in the world) as
+    /// far apart as they are now.
+    pub fn distance(&mut self, a: Entity, at_a: Pos3, b: Option<Entity>, at_b: Pos3) -> Result<()> {
+        let kind = JointKind::Distance {
+            length: (at_a - at_b).magnitude(),
+        };
+        let (anchor_a, anchor_b) = (self.anchor(Some(a), at_a), self.anchor(b, at_b));
+        let joint = Joint::new(a, anchor_a, b, anchor_b, kind)?;
+        self.joints.push(joint);
+        Ok(())
+    }
+
+    /// Weld `a` to `b`, or to the world, just as they are now.
+    pub fn fix(&mut self, a: Entity, b: Option<Entity>) -> Result<()> {
+        let at = self.transforms[a].pos;
+        let kind = JointKind::Fixed {
+            rot: self.rotation(b).conjugate() * self.rotation(Some(a)),
+        };
+        let (anchor_a, anchor_b) = (self.anchor(Some(a), at), self.anchor(b, at));
+        let joint = Joint::new(a, anchor_a, b, anchor_b, kind)?;
+        self.joints.push(joint);
+        Ok(())
+    }
+
+    /// Push apart the entities in `contacts` by changing their velocities,
+    /// bouncing and sliding as their colliders' materials say, and hold
+    /// jointed entities to their `joints`.  Anything asleep wakes up if
+    /// something moving touches it or is joined to it, and otherwise stays
+    /// put.  `dt` is the step the solved velocities will be integrated over.
+    pub fn solve(&mut self, contacts: &[Contact<Entity>], dt: f32) {
+        let joined: Vec<(Entity, Entity)> = self
+            .joints
+            .iter()
+            .filter_map(|j| Some((j.a, j.b?)))
+            .collect();
+        let pairs: Vec<(Entity, Entity)> =
+            contacts.iter().map(|c| (c.a, c.b)).chain(joined).collect();
+        for &(a, b) in &pairs {
+            for &(sleeper, other) in &[(a, b), (b, a)] {
+                if self.is_asleep(sleeper)
+                    && !self.is_asleep(other)
+                    && self.speed(other) > SLEEP_SPEED
+                {
+                    self.wake(sleeper);
+                }
+            }
+        }
+
+        let mut index = BTreeMap::new();
+        let mut centers = vec![];
+        let mut bodies = vec![];
+        let mut entities = vec![];
+        let jointed = self.joints.iter().map(|j| j.a);
+        for e in pairs.iter().flat_map(|&(a, b)| [a, b]).chain(jointed) {
+            if index.contains_key(&e) {
+                continue;
+            }
+            let t = self.transforms[e];
+            let v = self.velocities.get(e).copied().unwrap_or_default();
+            let fixed = Mass {
+                inv_mass: 0.0,
+                inv_inertia: Vec3::zero(),
+            };
+            let mass = match self.masses.get(e) {
+                Some(&m) if !self.is_asleep(e) => m,
+                _ => fixed,
+            };
+            index.insert(e, bodies.len());
+            centers.push(t.pos);
+            entities.push(e);
+            bodies.push(RigidBody {
+                inv_mass: mass.inv_mass,
+                inv_inertia: mass.inv_inertia,
+                vel: v.linear,
+                omega: v.angular,
+                rot: t.rot,
+            });
+        }
+        let contacts: Vec<Contact<usize>> = contacts
+            .iter()
+            .map(|c| Contact {
+                a: index[&c.a],
+                b: index[&c.b],
+                mtv: c.mtv,
+                manifold: c.manifold,
+            })
+            .collect();
+        let joints: Vec<Joint<usize>> = self
+            .joints
+            .iter()
+            .map(|j| Joint {
+                a: index[&j.a],
+                b: j.b.map(|b| index[&b]),
+                anchor_a: j.anchor_a,
+                anchor_b: j.anchor_b,
+                kind: j.kind,
+            })
+            .collect();
+        let material = |e: Entity| {
+            self.colliders
+                .get(e)
+                .map_or_else(Material::default, |c| c.material)
+        };
+        physics::solve_contacts(&centers, &mut bodies, &contacts, &joints, dt, |c| {
+            material(entities[c.a]).combine(material(entities[c.b]))
+        });
+        for (e, b) in entities.into_iter().zip(bodies) {
+            if !self.masses.contains(e) || self.is_asleep(e) {
+                continue;
+            }
+            let v = Velocity {
+                linear: b.vel,
+                angular: b.omega,
+            };
+            self.velocities.insert(e, v);
+        }
+    }
+
+    /// Check every trigger collider against every other collider and work
+    /// out this step's enter, stay and exit events.
+    pub fn update_triggers(&mut self) {
+        let (bodies, bshapes, blayers) = self.posed_colliders(false);
+        let (triggers, tshapes, tlayers) = self.posed_colliders(true);
+        let touching = collision::touching_triggers(&bshapes, &blayers, &tshapes, &tlayers)
+            .into_iter()
+            .map(|(b, t)| (bodies[b], triggers[t]))
+            .collect();
+        self.triggers.update_touching(touching);
+    }
+
+    /// What touched which trigger as of the last `update_triggers`.
+    pub fn triggers(&self) -> &Triggers<Entity> {
+        &self.triggers
+    }
+
+    /// The scene node that follows `e` around, made the first time it's
+    /// asked for.  Anything parented to it moves with `e`, and it goes
+    /// when `e` is despawned.
+    pub fn node(&mut self, e: Entity) -> NodeId {
+        if let Some(&node) = self.nodes.get(e) {
+            return node;
+        }
+        let node = self.scene.add(None);
+        self.nodes.insert(e, node);
+        node
+    }
+
+    /// Move entities' nodes to where the entities are now.
+    pub fn sync_scene(&mut self) {
+        for (e, &node) in self.nodes.iter() {
+            if let Some(t) = self.transforms.get(e) {
+                if self.scene.translation(node) != t.pos.to_vec() {
+                    self.scene.set_translation(node, t.pos.to_vec());
+                }
+                if self.scene.rotation(node) != t.rot {
+                    self.scene.set_rotation(node, t.rot);
+                }
+            }
+        }
+    }
+
+    /// Draw every visible entity with a model, and every visible scene node
+    /// with a model.
+    pub fn render(&self, igs: &mut InstanceGroups) {
+        for (e, m) in self.models.iter() {
+            if !m.visible {
+                continue;
+            }
+            if let Some(t) = self.transforms.get(e) {
+                let ir = InstanceRaw {
+                    model: (t.matrix() * m.local).into(),
+                };
+                if m.casts_shadow {
+                    igs.render_casting_shadow(m.model, ir);
+                } else {
+                    igs.render(m.model, ir);
+                }
+            }
+        }
+        self.scene.render(igs);
+    }
+
+    /// Every sound emitter, with where its entity is.
+    pub fn emitters(&self) -> impl Iterator<Item = (Entity, &AudioEmitter, Pos3)> + '_ {
+        self.emitters.iter().filter_map(move |(e, a)| {
+            let t = self.transforms.get(e)?;
+            Some((e, a, t.pos))
+        })
+    }
+}
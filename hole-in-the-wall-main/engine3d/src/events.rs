@@ -1,6 +1,89 @@
+use anyhow::*;
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
 pub use winit::event::VirtualKeyCode as KeyCode;
 
+/// A single change to the input state.  Live winit events are translated
+/// into these, and scripted or recorded input is fed through the same path.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Input {
+    KeyPressed(KeyCode),
+    KeyReleased(KeyCode),
+    MousePressed(usize),
+    MouseReleased(usize),
+    CursorMoved(f32, f32),
+    MouseMotion(f32, f32),
+}
+
+/// Inputs to apply at the start of given simulation frames.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Script {
+    inputs: BTreeMap<usize, Vec<Input>>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn push(&mut self, frame: usize, input: Input) {
+        self.inputs.entry(frame).or_default().push(input);
+    }
+    pub fn at(mut self, frame: usize, input: Input) -> Self {
+        self.push(frame, input);
+        self
+    }
+    pub fn press(self, frame: usize, k: KeyCode) -> Self {
+        self.at(frame, Input::KeyPressed(k))
+    }
+    pub fn release(self, frame: usize, k: KeyCode) -> Self {
+        self.at(frame, Input::KeyReleased(k))
+    }
+    /// Hold `k` down from frame `from` until it is released on frame `to`.
+    pub fn hold(self, from: usize, to: usize, k: KeyCode) -> Self {
+        self.press(from, k).release(to, k)
+    }
+    pub fn inputs_at(&self, frame: usize) -> &[Input] {
+        self.inputs
+            .get(&frame)
+            .map(|is| is.as_slice())
+            .unwrap_or(&[])
+    }
+    /// One past the last frame with any input on it.
+    pub fn len(&self) -> usize {
+        self.inputs.keys().next_back().map(|f| f + 1).unwrap_or(0)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+/// Everything needed to reproduce a run: the seed the engine's RNG started
+/// from and every input, keyed by the frame it was applied on.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Recording {
+    pub seed: u64,
+    pub script: Script,
+}
+
+impl Recording {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path.as_ref())
+            .with_context(|| format!("Couldn't open recording {}", path.as_ref().display()))?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Couldn't read recording {}", path.as_ref().display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = File::create(path.as_ref())
+            .with_context(|| format!("Couldn't create recording {}", path.as_ref().display()))?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        Ok(())
+    }
+}
+
 #[derive(Default)]
 pub struct Events {
     // how long has each been held?
@@ -11,18 +94,28 @@ pub struct Events {
     mouse_delta: (f32, f32),
     mouse_buttons: Vec<Option<usize>>,
     mouse_buttons_released: Vec<bool>,
+    // frames since recording or replay started
+    frame: usize,
+    recording: Option<Script>,
+    replaying: Option<Script>,
 }
 
 impl Events {
     pub(crate) fn device_event(&mut self, ev: &winit::event::DeviceEvent) {
+        if self.is_replaying() {
+            return;
+        }
         match ev {
             winit::event::DeviceEvent::MouseMotion { delta: (x, y) } => {
-                self.mouse_delta = (*x as f32, *y as f32)
+                self.input(Input::MouseMotion(*x as f32, *y as f32))
             }
             _ => {}
         }
     }
     pub(crate) fn window_event(&mut self, event: &winit::event::WindowEvent) {
+        if self.is_replaying() {
+            return;
+        }
         match event {
             winit::event::WindowEvent::KeyboardInput {
                 input:
@@ -35,13 +128,13 @@ impl Events {
             } => {
                 let pressed = *state == winit::event::ElementState::Pressed;
                 if pressed {
-                    self.held.entry(*keycode).or_insert(0);
+                    self.input(Input::KeyPressed(*keycode));
                 } else {
-                    self.released.insert(*keycode);
+                    self.input(Input::KeyReleased(*keycode));
                 }
             }
             winit::event::WindowEvent::CursorMoved { position, .. } => {
-                self.mouse_pos = (position.x as f32, position.y as f32)
+                self.input(Input::CursorMoved(position.x as f32, position.y as f32))
             }
             winit::event::WindowEvent::MouseInput { state, button, .. } => {
                 let pressed = *state == winit::event::ElementState::Pressed;
@@ -51,19 +144,42 @@ impl Events {
                     winit::event::MouseButton::Middle => 2,
                     winit::event::MouseButton::Other(num) => *num,
                 } as usize;
+                if pressed {
+                    self.input(Input::MousePressed(button));
+                } else {
+                    self.input(Input::MouseReleased(button));
+                }
+            }
+            _ => {} // mouse, etc
+        }
+    }
+    /// Apply one input change, exactly as if it had arrived from the window.
+    pub fn input(&mut self, input: Input) {
+        if let Some(script) = &mut self.recording {
+            script.push(self.frame, input);
+        }
+        match input {
+            Input::KeyPressed(keycode) => {
+                self.held.entry(keycode).or_insert(0);
+            }
+            Input::KeyReleased(keycode) => {
+                self.released.insert(keycode);
+            }
+            Input::CursorMoved(x, y) => self.mouse_pos = (x, y),
+            Input::MouseMotion(x, y) => self.mouse_delta = (x, y),
+            Input::MousePressed(button) | Input::MouseReleased(button) => {
                 self.mouse_buttons.reserve(button);
                 self.mouse_buttons_released.reserve(button);
                 while self.mouse_buttons.len() <= button {
                     self.mouse_buttons.push(None);
                     self.mouse_buttons_released.push(false);
                 }
-                if pressed {
+                if let Input::MousePressed(_) = input {
                     self.mouse_buttons[button] = Some(0);
                 } else {
                     self.mouse_buttons_released[button] = true;
                 }
             }
-            _ => {} // mouse, etc
         }
     }
     pub(crate) fn next_frame(&mut self) {
@@ -98,6 +214,41 @@ impl Events {
             }
         }
         self.mouse_delta = (0.0, 0.0);
+        self.frame += 1;
+        self.replay_frame();
+    }
+
+    /// Start recording every input from the next simulation frame on.
+    pub fn record(&mut self) {
+        self.frame = 0;
+        self.recording = Some(Script::new());
+    }
+
+    /// Stop recording and hand back what was recorded.
+    pub fn take_recording(&mut self) -> Option<Script> {
+        self.recording.take()
+    }
+
+    /// Take input from `script` instead of the window, starting with the
+    /// next simulation frame.
+    pub fn replay(&mut self, script: Script) {
+        self.frame = 0;
+        self.replaying = Some(script);
+        self.replay_frame();
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replaying.is_some()
+    }
+
+    fn replay_frame(&mut self) {
+        let inputs = match &self.replaying {
+            Some(script) => script.inputs_at(self.frame).to_vec(),
+            None => return,
+        };
+        for input in inputs {
+            self.input(input);
+        }
     }
 
     // Why does held need to ensure !released, and released need to check !pressed?
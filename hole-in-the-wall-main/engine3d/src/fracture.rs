@@ -0,0 +1,195 @@
+use crate::ecs::{Collider, Entity, Mass, Model, Transform, Velocity, World};
+use crate::geom::*;
+use rand::Rng;
+
+/// Debris moving slower than this, turning included, counts as still.
+const REST_SPEED: f32 = 0.2;
+/// How many steps debris has to stay still before it's cleared away.
+const REST_STEPS: u32 = 60;
+
+/// Split `b` into `pieces` boxes that fill it exactly, cut smaller around
+/// `impact`.  Each cut halves a piece somewhere near its middle across its
+/// longest side, and the piece cut next is the biggest one, counting pieces
+/// near the impact as bigger than they are.
+pub fn fracture(b: &Box, impact: Pos3, pieces: usize, rng: &mut impl Rng) -> Vec<Box> {
+    // (center, half sizes) in the box's own frame
+    let mut parts = vec![(Vec3::zero(), b.half_sizes)];
+    let hit = b.axes.transpose() * (impact - b.c);
+    let priority = |&(c, h): &(Vec3, Vec3)| {
+        let volume = h.x * h.y * h.z;
+        volume / (1.0 + (c - hit).magnitude2())
+    };
+    while parts.len() < pieces.max(1) {
+        let i = (0..parts.len())
+            .max_by(|&i, &j| {
+                priority(&parts[i])
+                    .partial_cmp(&priority(&parts[j]))
+                    .unwrap()
+            })
+            .unwrap();
+        let (c, h) = parts.swap_remove(i);
+        let k = if h.x >= h.y && h.x >= h.z {
+            0
+        } else if h.y >= h.z {
+            1
+        } else {
+            2
+        };
+        let t: f32 = rng.gen_range(0.3..0.7);
+        let (mut lo, mut hi) = ((c, h), (c, h));
+        lo.1[k] = h[k] * t;
+        hi.1[k] = h[k] * (1.0 - t);
+        lo.0[k] = c[k] - h[k] + lo.1[k];
+        hi.0[k] = c[k] + h[k] - hi.1[k];
+        parts.push(lo);
+        parts.push(hi);
+    }
+    parts
+        .into_iter()
+        .map(|(c, half_sizes)| Box {
+            c: b.c + b.axes * c,
+            axes: b.axes,
+            half_sizes,
+        })
+        .collect()
+}
+
+/// Break the box entity `e` into `pieces` fragments around `impact` (see
+/// `fracture`) and despawn it.  The fragments move as the part of `e` they
+/// came from was moving, split `e`'s mass by volume, and keep its layers
+/// and model, shrunk to fit.  Entities without a box collider are left
+/// alone.
+///
+/// The fragments are ordinary entities with rigid bodies, so `World::solve`
+/// handles their contacts with each other and with everything else, and
+/// sets them spinning as they knock together.
+pub fn shatter(
+    world: &mut World,
+    e: Entity,
+    impact: Pos3,
+    pieces: usize,
+    rng: &mut impl Rng,
+) -> Vec<Entity> {
+    let b = match world.shape(e) {
+        Some(AnyShape::Box(b)) => b,
+        _ => return vec![],
+    };
+    let t = world.transforms[e];
+    let vel = world.velocities.get(e).copied();
+    let mass = world
+        .masses
+        .get(e)
+        .filter(|m| m.inv_mass > 0.0)
+        .map(|m| 1.0 / m.inv_mass);
+    let collider = world.colliders[e];
+    let model = world.models.get(e).copied();
+    let volume = b.half_sizes.x * b.half_sizes.y * b.half_sizes.z;
+
+    let mut fragments = vec![];
+    for f in fracture(&b, impact, pieces, rng) {
+        let p = world.spawn();
+        world.transforms.insert(
+            p,
+            Transform {
+                pos: f.c,
+                rot: t.rot,
+            },
+        );
+        if let Some(v) = vel {
+            let linear = v.linear + v.angular.cross(f.c - b.c);
+            world.velocities.insert(
+                p,
+                Velocity {
+                    linear,
+                    angular: v.angular,
+                },
+            );
+        }
+        if let Some(mass) = mass {
+            let share = f.half_sizes.x * f.half_sizes.y * f.half_sizes.z / volume;
+            world.masses.insert(p, Mass::new(&f, mass * share));
+        }
+        world.colliders.insert(
+            p,
+            Collider {
+                shape: AnyShape::Box(Box {
+                    c: Pos3::origin(),
+                    axes: Mat3::one(),
+                    half_sizes: f.half_sizes,
+                }),
+                ..collider
+            },
+        );
+        if let Some(model) = model {
+            // `local` fits the mesh to the whole box in the entity's frame,
+            // so shrink the fitted mesh there, along the box's own axes,
+            // rather than the mesh before it's fitted
+            let s = f.half_sizes.div_element_wise(b.half_sizes);
+            world.models.insert(
+                p,
+                Model {
+                    local: Mat4::from_nonuniform_scale(s.x, s.y, s.z) * model.local,
+                    ..model
+                },
+            );
+        }
+        fragments.push(p);
+    }
+    world.despawn(e);
+    fragments
+}
+
+/// Fragments left over from `shatter`, cleared away once they settle down
+/// or wander off.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Debris {
+    // each piece and how many steps it's been still for
+    pieces: Vec<(Entity, u32)>,
+}
+
+impl Debris {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, pieces: impl IntoIterator<Item = Entity>) {
+        self.pieces.extend(pieces.into_iter().map(|e| (e, 0)));
+    }
+
+    pub fn pieces(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.pieces.iter().map(|&(e, _)| e)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    /// Despawn the pieces that have been still for a while, or are more than
+    /// `radius` from `center`.  Call once per step.
+    pub fn cull(&mut self, world: &mut World, center: Pos3, radius: f32) {
+        self.pieces.retain_mut(|(e, still)| {
+            let (pos, speed) = match (world.transforms.get(*e), world.velocities.get(*e)) {
+                (Some(t), Some(v)) => (t.pos, v.linear.magnitude().max(v.angular.magnitude())),
+                (Some(t), None) => (t.pos, 0.0),
+                _ => return false,
+            };
+            *still = if speed < REST_SPEED { *still + 1 } else { 0 };
+            let keep = *still < REST_STEPS && (pos - center).magnitude() <= radius;
+            if !keep {
+                world.despawn(*e);
+            }
+            keep
+        });
+    }
+
+    /// Despawn every piece.
+    pub fn clear(&mut self, world: &mut World) {
+        for (e, _) in self.pieces.drain(..) {
+            world.despawn(e);
+        }
+    }
+}
@@ -90,6 +90,25 @@ impl Shape for Box {
     }
 }
 
+impl Box {
+    pub fn vertices(&self) -> [Pos3; 8] {
+        let mut vs = [self.c; 8];
+        for (i, v) in vs.iter_mut().enumerate() {
+            for k in 0..3 {
+                let sign = if i & (1 << k) == 0 { -1.0 } else { 1.0 };
+                *v += self.axes[k] * self.half_sizes[k] * sign;
+            }
+        }
+        vs
+    }
+    /// Half the length of the box's shadow on `l`.
+    pub fn radius_along(&self, l: Vec3) -> f32 {
+        (0..3)
+            .map(|k| self.half_sizes[k] * self.axes[k].dot(l).abs())
+            .sum()
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct AABB {
     pub c: Pos3,
@@ -102,6 +121,145 @@ impl Shape for AABB {
     }
 }
 
+impl AABB {
+    pub fn to_box(&self) -> Box {
+        Box {
+            c: self.c,
+            axes: Mat3::one(),
+            half_sizes: self.half_sizes,
+        }
+    }
+    pub fn min(&self) -> Pos3 {
+        self.c - self.half_sizes
+    }
+    pub fn max(&self) -> Pos3 {
+        self.c + self.half_sizes
+    }
+    pub fn overlaps(&self, b: &AABB) -> bool {
+        let (amin, amax) = (self.min(), self.max());
+        let (bmin, bmax) = (b.min(), b.max());
+        (0..3).all(|i| amin[i] <= bmax[i] && bmin[i] <= amax[i])
+    }
+    /// Bounds of everywhere this box goes while moving by `d`.
+    pub fn swept(&self, d: Vec3) -> AABB {
+        AABB {
+            c: self.c + d / 2.0,
+            half_sizes: self.half_sizes + Vec3::new(d.x.abs(), d.y.abs(), d.z.abs()) / 2.0,
+        }
+    }
+}
+
+/// Shapes with an axis-aligned bounding box, used by the broad phase to skip
+/// pairs that can't collide.  Bounds must be conservative with respect to
+/// `Collide`: whenever two shapes are touching, their bounds overlap.
+pub trait Bounded {
+    fn bounds(&self) -> AABB;
+}
+
+impl Bounded for Sphere {
+    fn bounds(&self) -> AABB {
+        AABB {
+            c: self.c,
+            half_sizes: Vec3::new(self.r, self.r, self.r),
+        }
+    }
+}
+
+impl Bounded for Plane {
+    fn bounds(&self) -> AABB {
+        // Planes are unbounded, so they're candidates against everything
+        AABB {
+            c: Pos3::origin(),
+            half_sizes: Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        }
+    }
+}
+
+impl Bounded for Box {
+    fn bounds(&self) -> AABB {
+        // The OBB test below pads every projected radius by EPS, so boxes
+        // a little way apart still count as touching and the tight bounds of
+        // the box aren't enough.  Projecting the offset between two touching
+        // boxes a and b through a's face axes only (assuming orthonormal
+        // axes) gives, along each world axis,
+        //   |offset| <= tight_a + (1 + sqrt(3) EPS) * sum(b.half_sizes)
+        // and likewise with a and b swapped.  Each box takes half of the sum
+        // of its tight extent and its padded sum of half sizes, so the two
+        // boxes' extents add up to the average of those two limits.
+        let sum = (self.half_sizes.x + self.half_sizes.y + self.half_sizes.z)
+            * (1.0 + 3.0_f32.sqrt() * EPS);
+        let mut half_sizes = Vec3::zero();
+        for i in 0..3 {
+            let tight = self.axes[0][i].abs() * self.half_sizes[0]
+                + self.axes[1][i].abs() * self.half_sizes[1]
+                + self.axes[2][i].abs() * self.half_sizes[2];
+            // A little extra room for rounding in the narrow phase
+            half_sizes[i] = (tight + sum) / 2.0 * (1.0 + EPS);
+        }
+        AABB {
+            c: self.c,
+            half_sizes,
+        }
+    }
+}
+
+impl Bounded for AABB {
+    fn bounds(&self) -> AABB {
+        // AABBs collide as boxes, padding and all
+        self.to_box().bounds()
+    }
+}
+
+impl Bounded for Capsule {
+    fn bounds(&self) -> AABB {
+        let r = Vec3::new(self.r, self.r, self.r);
+        let min = Pos3::new(
+            self.a.x.min(self.b.x),
+            self.a.y.min(self.b.y),
+            self.a.z.min(self.b.z),
+        ) - r;
+        let max = Pos3::new(
+            self.a.x.max(self.b.x),
+            self.a.y.max(self.b.y),
+            self.a.z.max(self.b.z),
+        ) + r;
+        AABB {
+            c: min.midpoint(max),
+            half_sizes: (max - min) / 2.0,
+        }
+    }
+}
+
+/// A line segment with a radius: a cylinder with hemispherical caps.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Capsule {
+    pub a: Pos3,
+    pub b: Pos3,
+    pub r: f32,
+}
+
+impl Shape for Capsule {
+    fn translate(&mut self, v: Vec3) {
+        self.a += v;
+        self.b += v;
+    }
+}
+
+impl Capsule {
+    /// Center, unit direction and half length of the capsule's spine.
+    fn segment(&self) -> (Pos3, Vec3, f32) {
+        let ab = self.b - self.a;
+        let len = ab.magnitude();
+        let dir = if len > 0.0 { ab / len } else { Vec3::unit_y() };
+        (self.a.midpoint(self.b), dir, len / 2.0)
+    }
+    /// The point of the spine closest to `p`.
+    pub fn closest_point(&self, p: Pos3) -> Pos3 {
+        let (c, dir, h) = self.segment();
+        c + dir * (p - c).dot(dir).max(-h).min(h)
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Ray {
     pub p: Pos3,
@@ -114,11 +272,81 @@ impl Shape for Ray {
     }
 }
 
+/// Where two shapes touch.  `normal` is the unit direction that pushes the
+/// first shape out of the second, `depth` how far it has to go (zero for
+/// shapes that are only just touching), and there are up to four points
+/// spanning the touching area.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(from = "ManifoldDef", into = "ManifoldDef")]
+pub struct Manifold {
+    pub normal: Vec3,
+    pub depth: f32,
+    points: [Pos3; 4],
+    len: usize,
+}
+
+impl Manifold {
+    /// Panics if given more than four points.
+    pub fn new(normal: Vec3, depth: f32, points: &[Pos3]) -> Self {
+        assert!(points.len() <= 4);
+        let mut m = Self {
+            normal,
+            depth,
+            points: [Pos3::origin(); 4],
+            len: points.len(),
+        };
+        m.points[..points.len()].copy_from_slice(points);
+        m
+    }
+    pub fn points(&self) -> &[Pos3] {
+        &self.points[..self.len]
+    }
+    /// The same contact seen from the other shape.
+    pub fn flipped(self) -> Self {
+        Self {
+            normal: -self.normal,
+            ..self
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PointDef(#[serde(with = "Pos3Def")] Pos3);
+
+#[derive(Serialize, Deserialize)]
+struct ManifoldDef {
+    #[serde(with = "Vec3Def")]
+    normal: Vec3,
+    depth: f32,
+    points: Vec<PointDef>,
+}
+
+impl From<Manifold> for ManifoldDef {
+    fn from(m: Manifold) -> Self {
+        Self {
+            normal: m.normal,
+            depth: m.depth,
+            points: m.points().iter().map(|&p| PointDef(p)).collect(),
+        }
+    }
+}
+
+impl From<ManifoldDef> for Manifold {
+    fn from(m: ManifoldDef) -> Self {
+        let points: Vec<Pos3> = m.points.iter().take(4).map(|p| p.0).collect();
+        Manifold::new(m.normal, m.depth, &points)
+    }
+}
+
 pub trait Collide<S: Shape>: Shape {
     fn touching(&self, s2: &S) -> bool {
-        self.disp(s2).is_some()
+        self.manifold(s2).is_some()
+    }
+    /// What's the offset I'd need to push self out of s2?
+    fn disp(&self, s2: &S) -> Option<Vec3> {
+        self.manifold(s2).map(|m| m.normal * m.depth)
     }
-    fn disp(&self, s2: &S) -> Option<Vec3>;
+    fn manifold(&self, s2: &S) -> Option<Manifold>;
 }
 
 impl Collide<Sphere> for Sphere {
@@ -127,17 +355,23 @@ impl Collide<Sphere> for Sphere {
         // (squared) sum of the radii?
         s2.c.distance2(self.c) <= (self.r + s2.r).powi(2)
     }
-    /// What's the offset I'd need to push s1 and s2 out of each other?
-    fn disp(&self, s2: &Sphere) -> Option<Vec3> {
-        let offset = s2.c - self.c;
-        let distance = offset.magnitude();
-        if distance < self.r + s2.r {
+    fn manifold(&self, s2: &Sphere) -> Option<Manifold> {
+        // The same test as `touching`, so that spheres only just touching
+        // still get a (zero-depth) manifold
+        if self.touching(s2) {
+            let offset = self.c - s2.c;
+            let distance = offset.magnitude();
             // Make sure we don't divide by 0
-            let distance = if distance == 0.0 { 1.0 } else { distance };
+            let normal = if distance == 0.0 {
+                Vec3::unit_y()
+            } else {
+                offset / distance
+            };
             // How much combined radius is "left over"?
-            let disp_mag = (self.r + s2.r) - distance;
-            // Normalize offset and multiply by the amount to push
-            Some(offset * (disp_mag / distance))
+            let depth = ((self.r + s2.r) - distance).max(0.0);
+            // Halfway between the two surfaces
+            let point = s2.c + normal * (s2.r - depth / 2.0);
+            Some(Manifold::new(normal, depth, &[point]))
         } else {
             None
         }
@@ -149,14 +383,15 @@ impl Collide<Plane> for Sphere {
         // Find the distance of the sphere's center to the plane
         (self.c.dot(p.n) - p.d).abs() <= self.r
     }
-    fn disp(&self, p: &Plane) -> Option<Vec3> {
+    fn manifold(&self, p: &Plane) -> Option<Manifold> {
         // Find the distance of the sphere's center to the plane
         let dist = self.c.dot(p.n) - p.d;
         if dist.abs() <= self.r {
             // If we offset from the sphere position opposite the normal,
             // we'll end up hitting the plane at `dist` units away.  So
             // the displacement is just the plane's normal * dist.
-            Some(p.n * (self.r - dist))
+            let point = self.c - p.n * dist;
+            Some(Manifold::new(p.n, self.r - dist, &[point]))
         } else {
             None
         }
@@ -164,96 +399,29 @@ impl Collide<Plane> for Sphere {
 }
 
 impl Collide<Plane> for Box {
-    fn touching(&self, p: &Plane) -> bool {
-        // Treat plane as a huge box
-        let plane_box = Box {
-            c: Pos3::new(0.0, p.d - 50.0, 0.0),
-            axes: Mat3::one(),
-            half_sizes: Vec3::new(100.0, 50.0, 100.0),
-        };
-
-        self.touching(&plane_box)
-
-        /*
-        // Find the distance of the box's center to the plane
-        let dist = self.c.dot(p.n) - p.d;
-
-        // Check if any vertex is strictly below the plane (not including on the plane)
-        let v = self.c
-            + self.axes.x * self.half_sizes.x
-            + self.axes.y * self.half_sizes.y
-            + self.axes.z * self.half_sizes.z;
-        if ((v.dot(p.n) - p.d).signum() - dist.signum()).abs() > 1.5 {
-            return true;
-        }
-        let v = self.c + self.axes.x * self.half_sizes.x + self.axes.y * self.half_sizes.y
-            - self.axes.z * self.half_sizes.z;
-        if ((v.dot(p.n) - p.d).signum() - dist.signum()).abs() > 1.5 {
-            return true;
-        }
-        let v = self.c + self.axes.x * self.half_sizes.x - self.axes.y * self.half_sizes.y
-            + self.axes.z * self.half_sizes.z;
-        if ((v.dot(p.n) - p.d).signum() - dist.signum()).abs() > 1.5 {
-            return true;
-        }
-        let v = self.c + self.axes.x * self.half_sizes.x
-            - self.axes.y * self.half_sizes.y
-            - self.axes.z * self.half_sizes.z;
-        if ((v.dot(p.n) - p.d).signum() - dist.signum()).abs() > 1.5 {
-            return true;
-        }
-        let v = self.c - self.axes.x * self.half_sizes.x
-            + self.axes.y * self.half_sizes.y
-            + self.axes.z * self.half_sizes.z;
-        if ((v.dot(p.n) - p.d).signum() - dist.signum()).abs() > 1.5 {
-            return true;
-        }
-        let v = self.c - self.axes.x * self.half_sizes.x + self.axes.y * self.half_sizes.y
-            - self.axes.z * self.half_sizes.z;
-        if ((v.dot(p.n) - p.d).signum() - dist.signum()).abs() > 1.5 {
-            return true;
-        }
-        let v = self.c - self.axes.x * self.half_sizes.x - self.axes.y * self.half_sizes.y
-            + self.axes.z * self.half_sizes.z;
-        if ((v.dot(p.n) - p.d).signum() - dist.signum()).abs() > 1.5 {
-            return true;
-        }
-        let v = self.c
-            - self.axes.x * self.half_sizes.x
-            - self.axes.y * self.half_sizes.y
-            - self.axes.z * self.half_sizes.z;
-        if ((v.dot(p.n) - p.d).signum() - dist.signum()).abs() > 1.5 {
-            return true;
-        }
-
-        false
-
-        // assumes box is not rotated
-        // dist.abs() <= self.half_sizes.x
-        // || dist.abs() <= self.half_sizes.y
-        // || dist.abs() <= self.half_sizes.z
-        */
-    }
-
-    fn disp(&self, p: &Plane) -> Option<Vec3> {
-        // Find the distance of the box's center to the plane
-        // let dist = self.c.dot(p.n) - p.d;
-
-        // if dist.abs() <= self.half_sizes.x {
-        // Some(p.n * (self.half_sizes.x - dist))
-        // } else if dist.abs() <= self.half_sizes.y {
-        // Some(p.n * (self.half_sizes.y - dist))
-        // } else if dist.abs() <= self.half_sizes.z {
-        // Some(p.n * (self.half_sizes.z - dist))
-        // } else {
-        // None
-        // }
-
-        if self.touching(p) {
-            Some(p.n)
-        } else {
-            None
+    fn manifold(&self, p: &Plane) -> Option<Manifold> {
+        // Signed distance of each corner to the plane
+        let vs = self.vertices();
+        let dists: Vec<f32> = vs.iter().map(|v| v.dot(p.n) - p.d).collect();
+        let min = dists.iter().cloned().fold(f32::MAX, f32::min);
+        let max = dists.iter().cloned().fold(f32::MIN, f32::max);
+        // Like spheres, boxes touch a plane when they cross it, give or take
+        // EPS so that a box resting on it keeps in contact
+        if min > EPS || max < 0.0 {
+            return None;
         }
+        // The deepest corner, edge or face, halfway between box and plane
+        let points: Vec<Pos3> = vs
+            .iter()
+            .zip(dists.iter())
+            .filter(|(_, &d)| d <= min + EPS)
+            .map(|(&v, &d)| v - p.n * (d / 2.0))
+            .collect();
+        Some(Manifold::new(
+            p.n,
+            (-min).max(0.0),
+            &reduce_points(&points, p.n),
+        ))
     }
 }
 
@@ -359,45 +527,569 @@ impl Collide<Box> for Box {
         true
     }
 
-    fn disp(&self, b: &Box) -> Option<Vec3> {
-        // Ensure self and b are not touching, regardless of orientation
-        // Will overcorrect most of the time
+    fn manifold(&self, b: &Box) -> Option<Manifold> {
         if self.touching(b) {
-            let disp = self.c - b.c;
-            let dispabs = Vec3::new(disp.x.abs(), disp.y.abs(), disp.z.abs());
-
-            // Get axis of b to consider as normal vector by finding biggest overlap with dispabs
-            let overlap_x = disp.dot(b.axes.x).abs();
-            let overlap_y = disp.dot(b.axes.y).abs();
-            let overlap_z = disp.dot(b.axes.z).abs();
-
-            let mut normal = Vec3::zero();
-            if overlap_x > overlap_y && overlap_x > overlap_z {
-                normal = b.axes.x * disp.dot(b.axes.x).signum();
-            } else if overlap_y > overlap_x && overlap_y > overlap_z {
-                normal = b.axes.y * disp.dot(b.axes.y).signum();
+            Some(box_box_manifold(self, b))
+        } else {
+            None
+        }
+    }
+}
+
+impl Collide<Box> for Sphere {
+    fn manifold(&self, b: &Box) -> Option<Manifold> {
+        // Closest point on the box to the sphere's center (Ericson p.133)
+        let d = self.c - b.c;
+        let mut q = b.c;
+        for k in 0..3 {
+            let dist = d.dot(b.axes[k]).max(-b.half_sizes[k]).min(b.half_sizes[k]);
+            q += b.axes[k] * dist;
+        }
+        let offset = self.c - q;
+        let distance = offset.magnitude();
+        if distance > self.r {
+            return None;
+        }
+        if distance > 0.0 {
+            let normal = offset / distance;
+            let depth = self.r - distance;
+            return Some(Manifold::new(normal, depth, &[q]));
+        }
+        // The center is inside the box, so leave through the nearest face
+        let (mut best, mut normal) = (f32::MAX, Vec3::zero());
+        for k in 0..3 {
+            let along = d.dot(b.axes[k]);
+            let to_face = b.half_sizes[k] - along.abs();
+            if to_face < best {
+                best = to_face;
+                normal = b.axes[k] * along.signum();
+            }
+        }
+        let point = self.c + normal * best;
+        Some(Manifold::new(normal, self.r + best, &[point]))
+    }
+}
+
+impl Collide<AABB> for Sphere {
+    fn manifold(&self, b: &AABB) -> Option<Manifold> {
+        self.manifold(&b.to_box())
+    }
+}
+
+impl Collide<Capsule> for Sphere {
+    fn manifold(&self, c: &Capsule) -> Option<Manifold> {
+        c.manifold(self).map(Manifold::flipped)
+    }
+}
+
+impl Collide<Sphere> for Box {
+    fn manifold(&self, s: &Sphere) -> Option<Manifold> {
+        s.manifold(self).map(Manifold::flipped)
+    }
+}
+
+impl Collide<AABB> for Box {
+    fn manifold(&self, b: &AABB) -> Option<Manifold> {
+        self.manifold(&b.to_box())
+    }
+}
+
+impl Collide<Capsule> for Box {
+    fn manifold(&self, c: &Capsule) -> Option<Manifold> {
+        c.manifold(self).map(Manifold::flipped)
+    }
+}
+
+// AABBs are boxes that happen not to be rotated
+impl Collide<Sphere> for AABB {
+    fn manifold(&self, s: &Sphere) -> Option<Manifold> {
+        self.to_box().manifold(s)
+    }
+}
+
+impl Collide<Box> for AABB {
+    fn manifold(&self, b: &Box) -> Option<Manifold> {
+        self.to_box().manifold(b)
+    }
+}
+
+impl Collide<AABB> for AABB {
+    fn manifold(&self, b: &AABB) -> Option<Manifold> {
+        self.to_box().manifold(&b.to_box())
+    }
+}
+
+impl Collide<Plane> for AABB {
+    fn manifold(&self, p: &Plane) -> Option<Manifold> {
+        self.to_box().manifold(p)
+    }
+}
+
+impl Collide<Capsule> for AABB {
+    fn manifold(&self, c: &Capsule) -> Option<Manifold> {
+        self.to_box().manifold(c)
+    }
+}
+
+// Planes are pushed the opposite way to whatever they touch
+impl Collide<Sphere> for Plane {
+    fn manifold(&self, s: &Sphere) -> Option<Manifold> {
+        s.manifold(self).map(Manifold::flipped)
+    }
+}
+
+impl Collide<Box> for Plane {
+    fn manifold(&self, b: &Box) -> Option<Manifold> {
+        b.manifold(self).map(Manifold::flipped)
+    }
+}
+
+impl Collide<AABB> for Plane {
+    fn manifold(&self, b: &AABB) -> Option<Manifold> {
+        b.manifold(self).map(Manifold::flipped)
+    }
+}
+
+impl Collide<Capsule> for Plane {
+    fn manifold(&self, c: &Capsule) -> Option<Manifold> {
+        c.manifold(self).map(Manifold::flipped)
+    }
+}
+
+impl Collide<Plane> for Plane {
+    fn manifold(&self, _p: &Plane) -> Option<Manifold> {
+        // Planes are scenery, and there's no sensible way to push one
+        // infinite plane out of another
+        None
+    }
+}
+
+impl Collide<Sphere> for Capsule {
+    fn manifold(&self, s: &Sphere) -> Option<Manifold> {
+        let end = Sphere {
+            c: self.closest_point(s.c),
+            r: self.r,
+        };
+        end.manifold(s)
+    }
+}
+
+impl Collide<Box> for Capsule {
+    fn manifold(&self, b: &Box) -> Option<Manifold> {
+        // Signed distance to a convex shape is convex along the spine, so a
+        // ternary search finds its deepest (or closest) point
+        let (c, dir, h) = self.segment();
+        let (mut lo, mut hi) = (-h, h);
+        for _ in 0..50 {
+            let m1 = lo + (hi - lo) / 3.0;
+            let m2 = hi - (hi - lo) / 3.0;
+            if signed_distance(b, c + dir * m1) <= signed_distance(b, c + dir * m2) {
+                hi = m2;
             } else {
-                normal = b.axes.z * disp.dot(b.axes.z).signum();
+                lo = m1;
             }
+        }
+        let end = Sphere {
+            c: c + dir * ((lo + hi) / 2.0),
+            r: self.r,
+        };
+        end.manifold(b)
+    }
+}
 
-            Some(normal.normalize())
+impl Collide<AABB> for Capsule {
+    fn manifold(&self, b: &AABB) -> Option<Manifold> {
+        self.manifold(&b.to_box())
+    }
+}
 
-            // let final_dist = self.half_sizes.magnitude() + b.half_sizes.magnitude();
-            // Some(disp.normalize_to(final_dist) - disp)
-        } else {
-            None
+impl Collide<Plane> for Capsule {
+    fn manifold(&self, p: &Plane) -> Option<Manifold> {
+        // Like a sphere at each end: touching while the spine comes within
+        // r of the plane on either side
+        let da = self.a.dot(p.n) - p.d;
+        let db = self.b.dot(p.n) - p.d;
+        let (min, max) = (da.min(db), da.max(db));
+        if min > self.r || max < -self.r {
+            return None;
+        }
+        let points: Vec<Pos3> = [(self.a, da), (self.b, db)]
+            .iter()
+            .filter(|&&(_, d)| d <= min + EPS)
+            .map(|&(e, d)| e - p.n * d)
+            .collect();
+        Some(Manifold::new(p.n, self.r - min, &points))
+    }
+}
+
+impl Collide<Capsule> for Capsule {
+    fn manifold(&self, c: &Capsule) -> Option<Manifold> {
+        let (pa, pb) = closest_points_on_segments(self.segment(), c.segment());
+        let ends = (Sphere { c: pa, r: self.r }, Sphere { c: pb, r: c.r });
+        ends.0.manifold(&ends.1)
+    }
+}
+
+/// A shape of any kind, for collections that mix them.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AnyShape {
+    Sphere(Sphere),
+    Box(Box),
+    Plane(Plane),
+    Capsule(Capsule),
+}
+
+impl Shape for AnyShape {
+    fn translate(&mut self, v: Vec3) {
+        match self {
+            AnyShape::Sphere(s) => s.translate(v),
+            AnyShape::Box(b) => b.translate(v),
+            AnyShape::Plane(p) => p.translate(v),
+            AnyShape::Capsule(c) => c.translate(v),
+        }
+    }
+}
+
+impl Bounded for AnyShape {
+    fn bounds(&self) -> AABB {
+        match self {
+            AnyShape::Sphere(s) => s.bounds(),
+            AnyShape::Box(b) => b.bounds(),
+            AnyShape::Plane(p) => p.bounds(),
+            AnyShape::Capsule(c) => c.bounds(),
+        }
+    }
+}
+
+impl<S: Shape> Collide<S> for AnyShape
+where
+    Sphere: Collide<S>,
+    Box: Collide<S>,
+    Plane: Collide<S>,
+    Capsule: Collide<S>,
+{
+    fn touching(&self, s: &S) -> bool {
+        match self {
+            AnyShape::Sphere(a) => a.touching(s),
+            AnyShape::Box(a) => a.touching(s),
+            AnyShape::Plane(a) => a.touching(s),
+            AnyShape::Capsule(a) => a.touching(s),
+        }
+    }
+    fn manifold(&self, s: &S) -> Option<Manifold> {
+        match self {
+            AnyShape::Sphere(a) => a.manifold(s),
+            AnyShape::Box(a) => a.manifold(s),
+            AnyShape::Plane(a) => a.manifold(s),
+            AnyShape::Capsule(a) => a.manifold(s),
+        }
+    }
+}
+
+impl Collide<AnyShape> for Sphere {
+    fn touching(&self, s: &AnyShape) -> bool {
+        s.touching(self)
+    }
+    fn manifold(&self, s: &AnyShape) -> Option<Manifold> {
+        s.manifold(self).map(Manifold::flipped)
+    }
+}
+
+impl Collide<AnyShape> for Box {
+    fn touching(&self, s: &AnyShape) -> bool {
+        s.touching(self)
+    }
+    fn manifold(&self, s: &AnyShape) -> Option<Manifold> {
+        s.manifold(self).map(Manifold::flipped)
+    }
+}
+
+impl Collide<AnyShape> for Plane {
+    fn touching(&self, s: &AnyShape) -> bool {
+        s.touching(self)
+    }
+    fn manifold(&self, s: &AnyShape) -> Option<Manifold> {
+        s.manifold(self).map(Manifold::flipped)
+    }
+}
+
+impl Collide<AnyShape> for Capsule {
+    fn touching(&self, s: &AnyShape) -> bool {
+        s.touching(self)
+    }
+    fn manifold(&self, s: &AnyShape) -> Option<Manifold> {
+        s.manifold(self).map(Manifold::flipped)
+    }
+}
+
+/// Distance from `p` to the surface of `b`, negative inside it.
+fn signed_distance(b: &Box, p: Pos3) -> f32 {
+    let d = p - b.c;
+    let q = Vec3::new(
+        d.dot(b.axes[0]).abs() - b.half_sizes[0],
+        d.dot(b.axes[1]).abs() - b.half_sizes[1],
+        d.dot(b.axes[2]).abs() - b.half_sizes[2],
+    );
+    let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).magnitude();
+    outside + q.x.max(q.y).max(q.z).min(0.0)
+}
+
+#[derive(Clone, Copy)]
+enum SatAxis {
+    FaceA(usize),
+    FaceB(usize),
+    Edges(usize, usize),
+}
+
+/// Contact between two boxes already known to be touching.  The separating
+/// axis with the least overlap gives the normal and depth; faces are clipped
+/// against each other for the points, or for two crossing edges the closest
+/// points between them are used (Ericson pp.101-5 and 5.1.9, Box2D's
+/// b2CollidePolygons).
+fn box_box_manifold(a: &Box, b: &Box) -> Manifold {
+    let d = b.c - a.c;
+    let mut best = (f32::MAX, Vec3::zero(), SatAxis::FaceA(0));
+    for i in 0..3 {
+        let l = a.axes[i];
+        let overlap = a.half_sizes[i] + b.radius_along(l) - d.dot(l).abs();
+        if overlap < best.0 {
+            best = (overlap, l, SatAxis::FaceA(i));
+        }
+    }
+    for j in 0..3 {
+        let l = b.axes[j];
+        let overlap = a.radius_along(l) + b.half_sizes[j] - d.dot(l).abs();
+        if overlap < best.0 {
+            best = (overlap, l, SatAxis::FaceB(j));
+        }
+    }
+    for i in 0..3 {
+        for j in 0..3 {
+            let l = a.axes[i].cross(b.axes[j]);
+            // Nearly parallel edges are covered by the face axes
+            if l.magnitude2() < EPS * EPS {
+                continue;
+            }
+            let l = l.normalize();
+            let overlap = a.radius_along(l) + b.radius_along(l) - d.dot(l).abs();
+            // Only take an edge axis if it's clearly better than the faces,
+            // so that resting contacts don't flicker between the two
+            if overlap < best.0 - EPS {
+                best = (overlap, l, SatAxis::Edges(i, j));
+            }
+        }
+    }
+    let (overlap, l, axis) = best;
+    // Push a away from b
+    let normal = -l * d.dot(l).signum();
+    let depth = overlap.max(0.0);
+    match axis {
+        SatAxis::FaceA(i) => Manifold::new(normal, depth, &clip_faces(a, i, -normal, b)),
+        SatAxis::FaceB(j) => Manifold::new(normal, depth, &clip_faces(b, j, normal, a)),
+        SatAxis::Edges(i, j) => {
+            // The edges of a and b that face each other
+            let mut ca = a.c;
+            let mut cb = b.c;
+            for k in 0..3 {
+                if k != i {
+                    ca -= a.axes[k] * a.half_sizes[k] * a.axes[k].dot(normal).signum();
+                }
+                if k != j {
+                    cb += b.axes[k] * b.half_sizes[k] * b.axes[k].dot(normal).signum();
+                }
+            }
+            let (pa, pb) = closest_points_on_segments(
+                (ca, a.axes[i], a.half_sizes[i]),
+                (cb, b.axes[j], b.half_sizes[j]),
+            );
+            Manifold::new(normal, depth, &[pa.midpoint(pb)])
+        }
+    }
+}
+
+/// Clip the face of `inc` that faces `refb` against the face of `refb` along
+/// axis `ri` with outward normal `n`, and keep the deepest of what's left.
+fn clip_faces(refb: &Box, ri: usize, n: Vec3, inc: &Box) -> Vec<Pos3> {
+    // Incident face: the one most opposed to n
+    let k = (0..3)
+        .max_by(|&x, &y| {
+            inc.axes[x]
+                .dot(n)
+                .abs()
+                .total_cmp(&inc.axes[y].dot(n).abs())
+        })
+        .unwrap();
+    let fc = inc.c - inc.axes[k] * inc.half_sizes[k] * inc.axes[k].dot(n).signum();
+    let u = inc.axes[(k + 1) % 3] * inc.half_sizes[(k + 1) % 3];
+    let v = inc.axes[(k + 2) % 3] * inc.half_sizes[(k + 2) % 3];
+    let mut poly = vec![fc + u + v, fc - u + v, fc - u - v, fc + u - v];
+
+    // Clip against the four sides of the reference face
+    for &side in &[(ri + 1) % 3, (ri + 2) % 3] {
+        for &sign in &[1.0, -1.0] {
+            let axis = refb.axes[side] * sign;
+            let offset = refb.c.dot(axis) + refb.half_sizes[side];
+            poly = clip_polygon(&poly, axis, offset);
+        }
+    }
+
+    // How far above the reference face each point is
+    let face = refb.c.dot(n) + refb.half_sizes[ri];
+    if poly.is_empty() {
+        // Only the padding in `touching` joined these, so use the closest
+        // corner of the incident box
+        let corner = inc
+            .vertices()
+            .iter()
+            .cloned()
+            .min_by(|p, q| p.dot(n).total_cmp(&q.dot(n)))
+            .unwrap();
+        return vec![corner - n * ((corner.dot(n) - face) / 2.0)];
+    }
+    let deepest = poly
+        .iter()
+        .map(|p| p.dot(n) - face)
+        .fold(f32::MAX, f32::min);
+    let cutoff = deepest.max(0.0) + EPS / 10.0;
+    let points: Vec<Pos3> = poly
+        .iter()
+        .map(|&p| (p, p.dot(n) - face))
+        .filter(|&(_, sep)| sep <= cutoff)
+        .map(|(p, sep)| p - n * (sep / 2.0))
+        .collect();
+    reduce_points(&points, n)
+}
+
+/// Sutherland-Hodgman: keep the part of `poly` where `p . axis <= offset`.
+fn clip_polygon(poly: &[Pos3], axis: Vec3, offset: f32) -> Vec<Pos3> {
+    let mut out = vec![];
+    for (i, &p) in poly.iter().enumerate() {
+        let q = poly[(i + 1) % poly.len()];
+        let (dp, dq) = (p.dot(axis) - offset, q.dot(axis) - offset);
+        if dp <= 0.0 {
+            out.push(p);
+        }
+        if (dp < 0.0 && dq > 0.0) || (dp > 0.0 && dq < 0.0) {
+            out.push(p + (q - p) * (dp / (dp - dq)));
+        }
+    }
+    out
+}
+
+/// Pick at most four points that span the same area: the extremes along two
+/// diagonal directions in the plane with normal `n`.
+fn reduce_points(points: &[Pos3], n: Vec3) -> Vec<Pos3> {
+    if points.len() <= 4 {
+        return points.to_vec();
+    }
+    let t = if n.x.abs() < 0.9 {
+        Vec3::unit_x()
+    } else {
+        Vec3::unit_y()
+    };
+    let u = n.cross(t).normalize();
+    let v = n.cross(u);
+    let mut picked: Vec<usize> = vec![];
+    for dir in &[u + v, u - v, -u - v, -u + v] {
+        let i = (0..points.len())
+            .max_by(|&i, &j| {
+                points[i]
+                    .to_vec()
+                    .dot(*dir)
+                    .total_cmp(&points[j].to_vec().dot(*dir))
+            })
+            .unwrap();
+        if !picked.contains(&i) {
+            picked.push(i);
+        }
+    }
+    picked.iter().map(|&i| points[i]).collect()
+}
+
+/// Closest points between two segments, each given as center, unit
+/// direction and half length (Ericson 5.1.9).
+fn closest_points_on_segments(
+    (c1, u1, h1): (Pos3, Vec3, f32),
+    (c2, u2, h2): (Pos3, Vec3, f32),
+) -> (Pos3, Pos3) {
+    let r = c1 - c2;
+    let b = u1.dot(u2);
+    let c = u1.dot(r);
+    let f = u2.dot(r);
+    let denom = 1.0 - b * b;
+    let s = if denom > f32::EPSILON {
+        ((b * f - c) / denom).max(-h1).min(h1)
+    } else {
+        0.0
+    };
+    let t = (b * s + f).max(-h2).min(h2);
+    let s = (b * t - c).max(-h1).min(h1);
+    (c1 + u1 * s, c2 + u2 * t)
+}
+
+/// Where a cast first hits a shape: `t` is how far along its (unit)
+/// direction the cast went, `point` is where it touched the shape, and
+/// `normal` is the shape's outward surface normal there.  Casts that start
+/// out touching the shape hit at `t == 0` with the normal facing back along
+/// the cast.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CastHit {
+    pub point: Pos3,
+    pub t: f32,
+    pub normal: Vec3,
+}
+
+impl CastHit {
+    fn inside(point: Pos3, dir: Vec3) -> Self {
+        Self {
+            point,
+            t: 0.0,
+            normal: -dir,
         }
     }
 }
 
-type CastHit = Option<(Pos3, f32)>;
+/// Moving `self` in a straight line, where does it first hit `s`?
+pub trait Cast<S: Shape> {
+    fn cast(&self, s: &S) -> Option<CastHit>;
+}
+
+/// `shape` moving along the unit vector `dir`, for sphere casts and box
+/// sweeps.  A `Ray` is the same thing for a point.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sweep<S: Shape> {
+    pub shape: S,
+    pub dir: Vec3,
+}
+
+impl<S: Shape> Shape for Sweep<S> {
+    fn translate(&mut self, v: Vec3) {
+        self.shape.translate(v);
+    }
+}
+
+/// `Cast` seen from the shape being hit, so that shapes of different types
+/// can be cast against together (see `collision::cast_nearest`).
+pub trait Target<C> {
+    fn hit_by(&self, c: &C) -> Option<CastHit>;
+}
+
+impl<C: Cast<S>, S: Shape> Target<C> for S {
+    fn hit_by(&self, c: &C) -> Option<CastHit> {
+        c.cast(self)
+    }
+}
 
-trait Cast<S: Shape> {
-    fn cast(&self, s: &S) -> CastHit;
+// A zero-length direction can give a NaN time of impact, which is no hit
+fn nearest(hits: impl IntoIterator<Item = Option<CastHit>>) -> Option<CastHit> {
+    hits.into_iter()
+        .flatten()
+        .filter(|h| h.t.is_finite())
+        .min_by(|a, b| a.t.total_cmp(&b.t))
 }
 
 impl Cast<Sphere> for Ray {
-    fn cast(&self, s: &Sphere) -> CastHit {
+    fn cast(&self, s: &Sphere) -> Option<CastHit> {
         let m = self.p - s.c;
         let b = self.dir.dot(m);
         let c = m.dot(m) - s.r * s.r;
@@ -405,62 +1097,85 @@ impl Cast<Sphere> for Ray {
         if (c > 0.0 && b > 0.0) || discr < 0.0 {
             return None;
         }
-        let t = (-b - discr.sqrt()).max(0.0);
-        Some((self.p + t * self.dir, t))
+        if c <= 0.0 {
+            return Some(CastHit::inside(self.p, self.dir));
+        }
+        let t = -b - discr.sqrt();
+        let point = self.p + t * self.dir;
+        Some(CastHit {
+            point,
+            t,
+            normal: (point - s.c) / s.r,
+        })
     }
 }
 impl Cast<Plane> for Ray {
-    fn cast(&self, b: &Plane) -> CastHit {
+    fn cast(&self, b: &Plane) -> Option<CastHit> {
         let denom = self.dir.dot(b.n);
         if denom == 0.0 {
             return None;
         }
         let t = (b.d - self.p.dot(b.n)) / denom;
         if t >= 0.0 {
-            Some((self.p + self.dir * t, t))
+            // Planes are hit from either side
+            Some(CastHit {
+                point: self.p + self.dir * t,
+                t,
+                normal: if denom < 0.0 { b.n } else { -b.n },
+            })
         } else {
             None
         }
     }
 }
 impl Cast<Box> for Ray {
-    fn cast(&self, b: &Box) -> CastHit {
+    fn cast(&self, b: &Box) -> Option<CastHit> {
         let mut tmin = 0.0_f32;
         let mut tmax = f32::MAX;
+        let mut normal = -self.dir;
         let delta = b.c - self.p;
         for i in 0..3 {
             let axis = b.axes[i];
             let e = axis.dot(delta);
-            let mut f = self.dir.dot(axis);
+            let f = self.dir.dot(axis);
             if f.abs() < f32::EPSILON {
                 if -e - b.half_sizes[i] > 0.0 || -e + b.half_sizes[i] < 0.0 {
                     return None;
                 }
-                f = f32::EPSILON;
+                continue;
             }
-            let mut t1 = (e + b.half_sizes[i]) / f;
-            let mut t2 = (e - b.half_sizes[i]) / f;
+            let mut t1 = (e - b.half_sizes[i]) / f;
+            let mut t2 = (e + b.half_sizes[i]) / f;
             if t1 > t2 {
                 std::mem::swap(&mut t1, &mut t2);
             }
-            tmin = tmin.max(t1);
+            if t1 > tmin {
+                tmin = t1;
+                // Entering through the face that faces the ray
+                normal = if f > 0.0 { -axis } else { axis };
+            }
             tmax = tmax.min(t2);
             if tmin > tmax {
                 return None;
             }
         }
-        Some((self.p + self.dir * tmin, tmin))
+        Some(CastHit {
+            point: self.p + self.dir * tmin,
+            t: tmin,
+            normal,
+        })
     }
 }
 impl Cast<AABB> for Ray {
-    fn cast(&self, b: &AABB) -> CastHit {
+    fn cast(&self, b: &AABB) -> Option<CastHit> {
         let mut tmin = 0.0_f32;
         let mut tmax = f32::MAX;
+        let mut normal = -self.dir;
         let min = b.c - b.half_sizes;
         let max = b.c + b.half_sizes;
         for i in 0..3 {
             if self.dir[i].abs() < f32::EPSILON {
-                if self.p[i] < min[i] {
+                if self.p[i] < min[i] || self.p[i] > max[i] {
                     return None;
                 }
                 continue;
@@ -471,12 +1186,262 @@ impl Cast<AABB> for Ray {
             if t1 > t2 {
                 std::mem::swap(&mut t1, &mut t2);
             }
-            tmin = tmin.max(t1);
+            if t1 > tmin {
+                tmin = t1;
+                normal = Vec3::zero();
+                normal[i] = -self.dir[i].signum();
+            }
             tmax = tmax.min(t2);
             if tmin > tmax {
                 return None;
             }
         }
-        Some((self.p + self.dir * tmin, tmin))
+        Some(CastHit {
+            point: self.p + self.dir * tmin,
+            t: tmin,
+            normal,
+        })
+    }
+}
+impl Cast<Capsule> for Ray {
+    fn cast(&self, c: &Capsule) -> Option<CastHit> {
+        // The nearer of the end caps and the cylinder between them
+        let caps = [
+            self.cast(&Sphere { c: c.a, r: c.r }),
+            self.cast(&Sphere { c: c.b, r: c.r }),
+        ];
+        let ab = c.b - c.a;
+        let len = ab.magnitude();
+        if len < f32::EPSILON {
+            return nearest(caps);
+        }
+        let u = ab / len;
+        let m = self.p - c.a;
+        // Work in the plane across the axis, where the cylinder is a circle
+        let mp = m - u * m.dot(u);
+        let dp = self.dir - u * self.dir.dot(u);
+        let a = dp.dot(dp);
+        let b = mp.dot(dp);
+        let k = mp.dot(mp) - c.r * c.r;
+        let along = |t: f32| (m + self.dir * t).dot(u);
+        let body = if k <= 0.0 {
+            // Inside the infinite cylinder: hit at once if between the caps
+            Some(CastHit::inside(self.p, self.dir)).filter(|_| (0.0..=len).contains(&along(0.0)))
+        } else if a < f32::EPSILON || b * b - a * k < 0.0 {
+            None
+        } else {
+            let t = (-b - (b * b - a * k).sqrt()) / a;
+            Some(CastHit {
+                point: self.p + self.dir * t,
+                t,
+                normal: (mp + dp * t) / c.r,
+            })
+            .filter(|_| t >= 0.0 && (0.0..=len).contains(&along(t)))
+        };
+        nearest(caps.iter().copied().chain(std::iter::once(body)))
+    }
+}
+
+impl Cast<AnyShape> for Ray {
+    fn cast(&self, s: &AnyShape) -> Option<CastHit> {
+        match s {
+            AnyShape::Sphere(s) => self.cast(s),
+            AnyShape::Box(b) => self.cast(b),
+            AnyShape::Plane(p) => self.cast(p),
+            AnyShape::Capsule(c) => self.cast(c),
+        }
+    }
+}
+
+impl Sweep<Sphere> {
+    fn ray(&self) -> Ray {
+        Ray {
+            p: self.shape.c,
+            dir: self.dir,
+        }
+    }
+    /// Casting a sphere is casting its center against the target grown by
+    /// its radius; the contact is a radius back towards the target.
+    fn inflated<S: Shape>(&self, grown: &S) -> Option<CastHit>
+    where
+        Ray: Cast<S>,
+    {
+        self.ray().cast(grown).map(|hit| CastHit {
+            point: hit.point - hit.normal * self.shape.r,
+            ..hit
+        })
+    }
+}
+
+impl Cast<Sphere> for Sweep<Sphere> {
+    fn cast(&self, s: &Sphere) -> Option<CastHit> {
+        self.inflated(&Sphere {
+            r: s.r + self.shape.r,
+            ..*s
+        })
+    }
+}
+impl Cast<Plane> for Sweep<Sphere> {
+    fn cast(&self, p: &Plane) -> Option<CastHit> {
+        let dist = self.shape.c.dot(p.n) - p.d;
+        if dist.abs() <= self.shape.r {
+            return Some(CastHit::inside(self.shape.c - p.n * dist, self.dir));
+        }
+        // Grow the plane towards the sphere's side of it
+        self.inflated(&Plane {
+            n: p.n,
+            d: p.d + self.shape.r * dist.signum(),
+        })
+    }
+}
+impl Cast<Box> for Sweep<Sphere> {
+    fn cast(&self, b: &Box) -> Option<CastHit> {
+        // A box grown by a radius is three slabs, each grown along one axis,
+        // with capsules rounding off its twelve edges
+        let r = self.shape.r;
+        let slabs = (0..3).map(|i| {
+            let mut grown = *b;
+            grown.half_sizes[i] += r;
+            self.inflated(&grown)
+        });
+        let v = b.vertices();
+        // Pairs of vertices differing in one axis (see `Box::vertices`)
+        let edges = (0..8).flat_map(|i| {
+            (0..3)
+                .map(move |k| (i, i | (1 << k)))
+                .filter(|&(i, j)| i != j)
+        });
+        let rounds = edges.map(|(i, j)| {
+            self.inflated(&Capsule {
+                a: v[i],
+                b: v[j],
+                r,
+            })
+        });
+        nearest(slabs.chain(rounds))
+    }
+}
+impl Cast<AABB> for Sweep<Sphere> {
+    fn cast(&self, b: &AABB) -> Option<CastHit> {
+        self.cast(&b.to_box())
+    }
+}
+impl Cast<Capsule> for Sweep<Sphere> {
+    fn cast(&self, c: &Capsule) -> Option<CastHit> {
+        self.inflated(&Capsule {
+            r: c.r + self.shape.r,
+            ..*c
+        })
+    }
+}
+
+impl Cast<Box> for Sweep<Box> {
+    fn cast(&self, b: &Box) -> Option<CastHit> {
+        // The separating axis test, but finding the span of time over which
+        // each axis fails to separate the boxes.  They first touch when the
+        // last axis stops separating them.
+        let a = &self.shape;
+        let mut axes = vec![];
+        for i in 0..3 {
+            axes.push(a.axes[i]);
+            axes.push(b.axes[i]);
+            for j in 0..3 {
+                let l = a.axes[i].cross(b.axes[j]);
+                if l.magnitude2() > EPS * EPS {
+                    axes.push(l.normalize());
+                }
+            }
+        }
+        let (mut enter, mut exit) = (f32::NEG_INFINITY, f32::INFINITY);
+        let mut normal = -self.dir;
+        for l in axes {
+            let reach = a.radius_along(l) + b.radius_along(l);
+            let s = (a.c - b.c).dot(l);
+            let v = self.dir.dot(l);
+            if v.abs() < f32::EPSILON {
+                if s.abs() > reach {
+                    return None;
+                }
+                continue;
+            }
+            let (t0, t1) = ((-reach - s) / v, (reach - s) / v);
+            let (t0, t1) = (t0.min(t1), t0.max(t1));
+            if t0 > enter {
+                enter = t0;
+                // a comes in from the side it starts on
+                normal = l * s.signum();
+            }
+            exit = exit.min(t1);
+        }
+        if exit < enter.max(0.0) {
+            return None;
+        }
+        if enter <= 0.0 {
+            let point = a.c.midpoint(b.c);
+            return Some(CastHit::inside(point, self.dir));
+        }
+        let mut moved = *a;
+        moved.translate(self.dir * enter);
+        let m = box_box_manifold(&moved, b);
+        let n = m.points().len() as f32;
+        let point = m
+            .points()
+            .iter()
+            .fold(Pos3::origin(), |p, q| p + q.to_vec() / n);
+        Some(CastHit {
+            point,
+            t: enter,
+            normal,
+        })
+    }
+}
+impl Cast<AABB> for Sweep<Box> {
+    fn cast(&self, b: &AABB) -> Option<CastHit> {
+        self.cast(&b.to_box())
+    }
+}
+impl Cast<Plane> for Sweep<Box> {
+    fn cast(&self, p: &Plane) -> Option<CastHit> {
+        let b = &self.shape;
+        let dist = b.c.dot(p.n) - p.d;
+        let reach = b.radius_along(p.n);
+        // The plane's normal on the box's side of it
+        let n = if dist < 0.0 { -p.n } else { p.n };
+        let t = if dist.abs() <= reach {
+            0.0
+        } else if self.dir.dot(n) < 0.0 {
+            (dist.abs() - reach) / -self.dir.dot(n)
+        } else {
+            return None;
+        };
+        // The vertex that gets there first
+        let lowest = b
+            .vertices()
+            .iter()
+            .copied()
+            .min_by(|u, v| u.dot(n).total_cmp(&v.dot(n)))
+            .unwrap();
+        if t == 0.0 {
+            return Some(CastHit::inside(lowest, self.dir));
+        }
+        Some(CastHit {
+            point: lowest + self.dir * t,
+            t,
+            normal: n,
+        })
+    }
+}
+impl Cast<Sphere> for Sweep<Box> {
+    fn cast(&self, s: &Sphere) -> Option<CastHit> {
+        // The same as the sphere coming the other way to meet the box
+        let back = Sweep {
+            shape: *s,
+            dir: -self.dir,
+        };
+        back.cast(&self.shape).map(|hit| CastHit {
+            point: hit.point + self.dir * hit.t,
+            t: hit.t,
+            normal: if hit.t == 0.0 { -self.dir } else { -hit.normal },
+        })
     }
 }
@@ -0,0 +1,502 @@
+//! Loading glTF 2.0 models, either as `.gltf` JSON with the buffers in
+//! separate files or embedded as base64 data URIs, or as binary `.glb`.
+//! The `gltf` crate does the parsing; this turns what it finds into
+//! `ModelData`.  Only triangle meshes are read; cameras, lights, morph
+//! targets, sparse accessors and animations are left out.
+
+use crate::anim::{Pose, Rig, Skeleton, SkeletonBone, BONE_MAX};
+use crate::geom::*;
+use crate::model::{
+    compute_normals, compute_tangents, default_material, ImageSource, MaterialData, MeshData,
+    ModelData, ModelVertex,
+};
+use anyhow::*;
+use gltf::accessor::{DataType, Dimensions};
+use gltf::{buffer, Accessor, Document, Node, Primitive, Semantic};
+use percent_encoding::percent_decode_str;
+use std::path::{Path, PathBuf};
+
+/// Load the `.gltf` or `.glb` file at `path`.  Buffers and images that
+/// aren't embedded are looked for next to it.
+pub fn load(path: &Path) -> Result<ModelData> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Couldn't open model {}", path.display()))?;
+    let dir = path.parent().context("Directory has no parent")?;
+    parse(&bytes, dir)
+}
+
+/// Read a glTF model from the contents of a `.gltf` or `.glb` file, looking
+/// for any files it refers to in `dir`.
+pub fn parse(bytes: &[u8], dir: &Path) -> Result<ModelData> {
+    let gltf::Gltf { document, mut blob } =
+        gltf::Gltf::from_slice(bytes).context("Couldn't read glTF")?;
+    let mut buffers = vec![];
+    for b in document.buffers() {
+        let data = match b.source() {
+            buffer::Source::Uri(uri) => read_uri(uri, dir)?,
+            buffer::Source::Bin => blob.take().context("No binary chunk for buffer 0")?,
+        };
+        ensure!(
+            data.len() >= b.length(),
+            "Buffer {} is {} bytes, not {}",
+            b.index(),
+            data.len(),
+            b.length()
+        );
+        buffers.push(data);
+    }
+    Loader { document, buffers }.model(dir)
+}
+
+/// The bytes behind a buffer or image URI: a base64 `data:` URI, or a file
+/// relative to `dir`.
+fn read_uri(uri: &str, dir: &Path) -> Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, payload) = data
+            .split_once(";base64,")
+            .context("Only base64 data URIs are supported")?;
+        return base64::decode(payload).context("Bad base64 in data URI");
+    }
+    let path = file_path(uri, dir);
+    std::fs::read(&path).with_context(|| format!("Couldn't open {}", path.display()))
+}
+
+/// The file a relative URI refers to, from `dir`.
+fn file_path(uri: &str, dir: &Path) -> PathBuf {
+    dir.join(percent_decode_str(uri).decode_utf8_lossy().as_ref())
+}
+
+/// Where a skin's joints ended up in the model's skeleton.
+struct Joints {
+    /// The bone each joint became
+    bones: Vec<u8>,
+    /// Each joint's bone's rest pose, times the joint's inverse bind matrix:
+    /// what puts a vertex bound to just that joint where it is when the
+    /// skeleton is at rest
+    binds: Vec<Mat4>,
+}
+
+struct Loader {
+    document: Document,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl Loader {
+    /// Make sure `accessor` holds `dims` of one of `types` and lies wholly
+    /// inside its buffer view, since the `gltf` crate's readers take both
+    /// for granted.
+    fn check(&self, accessor: &Accessor, dims: Dimensions, types: &[DataType]) -> Result<()> {
+        let i = accessor.index();
+        ensure!(accessor.sparse().is_none(), "Accessor {} is sparse", i);
+        ensure!(
+            accessor.dimensions() == dims && types.contains(&accessor.data_type()),
+            "Accessor {} holds {:?} of {:?}, not {:?}",
+            i,
+            accessor.dimensions(),
+            accessor.data_type(),
+            dims
+        );
+        ensure!(accessor.count() > 0, "Accessor {} is empty", i);
+        let view = accessor.view().context("Accessor has no buffer view")?;
+        let buffer = &self.buffers[view.buffer().index()];
+        ensure!(
+            view.offset() + view.length() <= buffer.len(),
+            "Buffer view {} runs past its buffer",
+            view.index()
+        );
+        let end = view
+            .stride()
+            .unwrap_or_else(|| accessor.size())
+            .checked_mul(accessor.count() - 1)
+            .and_then(|n| n.checked_add(accessor.offset() + accessor.size()));
+        ensure!(
+            matches!(end, Some(end) if end <= view.length()),
+            "Accessor {} runs past its buffer view",
+            i
+        );
+        Ok(())
+    }
+
+    fn model(&self, dir: &Path) -> Result<ModelData> {
+        let mut materials = self
+            .document
+            .materials()
+            .map(|m| self.material(&m, dir))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Parents of every node, to find skeleton roots.  Checking that
+        // nodes form trees makes sure every walk through them ends.
+        let count = self.document.nodes().len();
+        let mut parents = vec![None; count];
+        for n in self.document.nodes() {
+            for c in n.children() {
+                ensure!(
+                    parents[c.index()].replace(n.index()).is_none(),
+                    "Node {} has more than one parent",
+                    c.index()
+                );
+            }
+        }
+        for i in 0..count {
+            let mut above = parents[i];
+            for _ in 0..=count {
+                match above {
+                    Some(p) => above = parents[p],
+                    None => break,
+                }
+            }
+            ensure!(above.is_none(), "The nodes above node {} loop", i);
+        }
+        let roots: Vec<Node> = match self
+            .document
+            .default_scene()
+            .or_else(|| self.document.scenes().next())
+        {
+            Some(s) => s.nodes().collect(),
+            None => self
+                .document
+                .nodes()
+                .filter(|n| parents[n.index()].is_none())
+                .collect(),
+        };
+
+        let mut skins: Vec<usize> = self
+            .document
+            .nodes()
+            .filter(|n| n.mesh().is_some())
+            .filter_map(|n| n.skin())
+            .map(|s| s.index())
+            .collect();
+        skins.sort_unstable();
+        skins.dedup();
+        let (skeleton, joints) = if skins.is_empty() {
+            (None, vec![])
+        } else {
+            let (skeleton, joints) = self.skeleton(&skins, &parents)?;
+            (Some(skeleton), joints)
+        };
+
+        let mut meshes = vec![];
+        // Depth first, in the order the scene lists them
+        let mut stack: Vec<(Node, Mat4)> =
+            roots.into_iter().rev().map(|r| (r, Mat4::one())).collect();
+        while let Some((node, parent)) = stack.pop() {
+            let world = parent * Mat4::from(node.transform().matrix());
+            if let Some(mesh) = node.mesh() {
+                // Skinned meshes are placed by their bones, not the node
+                let skin = node.skin().map(|s| &joints[s.index()]);
+                let count = mesh.primitives().len();
+                for prim in mesh.primitives() {
+                    if prim.mode() != gltf::mesh::Mode::Triangles {
+                        continue;
+                    }
+                    let p = prim.index();
+                    let name = match (mesh.name(), count) {
+                        (Some(name), 1) => name.to_string(),
+                        (Some(name), _) => format!("{}.{}", name, p),
+                        (None, _) => format!("mesh{}.{}", mesh.index(), p),
+                    };
+                    let material = match prim.material().index() {
+                        Some(id) => id,
+                        None => default_material(&mut materials),
+                    };
+                    let (vertices, indices) = self.primitive(&prim, world, skin)?;
+                    meshes.push(MeshData {
+                        name,
+                        vertices,
+                        indices,
+                        material,
+                    });
+                }
+            }
+            let children: Vec<Node> = node.children().collect();
+            stack.extend(children.into_iter().rev().map(|c| (c, world)));
+        }
+
+        Ok(ModelData {
+            meshes,
+            materials,
+            rig: skeleton.map(|skeleton| Rig {
+                skeleton,
+                clips: vec![],
+            }),
+        })
+    }
+
+    fn material(&self, m: &gltf::Material, dir: &Path) -> Result<MaterialData> {
+        let pbr = m.pbr_metallic_roughness();
+        let map = |t: Option<gltf::Texture>| t.map(|t| self.image(t.source(), dir)).transpose();
+        Ok(MaterialData {
+            name: match (m.name(), m.index()) {
+                (Some(name), _) => name.to_string(),
+                (None, i) => format!("material{}", i.unwrap_or(0)),
+            },
+            base_color: pbr.base_color_factor(),
+            diffuse: map(pbr.base_color_texture().map(|t| t.texture()))?,
+            normal: map(m.normal_texture().map(|t| t.texture()))?,
+            normal_scale: m.normal_texture().map_or(1.0, |t| t.scale()),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            metallic_roughness: map(pbr.metallic_roughness_texture().map(|t| t.texture()))?,
+            emissive: m.emissive_factor(),
+            emissive_map: map(m.emissive_texture().map(|t| t.texture()))?,
+        })
+    }
+
+    /// Where `image` comes from.
+    fn image(&self, image: gltf::Image, dir: &Path) -> Result<ImageSource> {
+        Ok(match image.source() {
+            gltf::image::Source::Uri { uri, .. } if uri.starts_with("data:") => {
+                ImageSource::Bytes(read_uri(uri, dir)?)
+            }
+            gltf::image::Source::Uri { uri, .. } => ImageSource::Path(file_path(uri, dir)),
+            gltf::image::Source::View { view, .. } => {
+                let buffer = &self.buffers[view.buffer().index()];
+                let end = view.offset() + view.length();
+                let bytes = buffer.get(view.offset()..end).context("Bad view")?;
+                ImageSource::Bytes(bytes.to_vec())
+            }
+        })
+    }
+
+    /// One skeleton with a bone for each joint of `skins`, and where each
+    /// skin's joints ended up in it, by skin index.  Joints' rest poses are
+    /// taken from the nodes; their inverse bind matrices are baked into the
+    /// vertices bound to them instead.
+    fn skeleton(
+        &self,
+        skins: &[usize],
+        parents: &[Option<usize>],
+    ) -> Result<(Skeleton, Vec<Joints>)> {
+        let nodes: Vec<Node> = self.document.nodes().collect();
+        let skins: Vec<gltf::Skin> = self
+            .document
+            .skins()
+            .filter(|s| skins.contains(&s.index()))
+            .collect();
+        // Every joint of every skin, once each
+        let mut joints: Vec<usize> = vec![];
+        for j in skins.iter().flat_map(|s| s.joints()) {
+            if !joints.contains(&j.index()) {
+                joints.push(j.index());
+            }
+        }
+        if joints.len() > BONE_MAX {
+            bail!("Too many joints: {} (at most {})", joints.len(), BONE_MAX);
+        }
+        // The nearest joint above each joint, if any
+        let parent_joint = |j: usize| -> Option<usize> {
+            let mut p = parents[j];
+            while let Some(n) = p {
+                if let Some(k) = joints.iter().position(|&x| x == n) {
+                    return Some(k);
+                }
+                p = parents[n];
+            }
+            None
+        };
+        // Where non-joint ancestors put a root joint
+        let placed = |j: usize| -> Pose {
+            let mut pose = node_pose(&nodes[j]);
+            let mut p = parents[j];
+            while let Some(n) = p {
+                pose = node_pose(&nodes[n]) * pose;
+                p = parents[n];
+            }
+            pose
+        };
+        // Parents go before their children
+        let mut order: Vec<usize> = vec![];
+        let mut bone_of = vec![None; joints.len()];
+        while order.len() < joints.len() {
+            for (k, &j) in joints.iter().enumerate() {
+                let ready = match parent_joint(j) {
+                    Some(pk) => bone_of[pk].is_some(),
+                    None => true,
+                };
+                if bone_of[k].is_none() && ready {
+                    bone_of[k] = Some(order.len());
+                    order.push(k);
+                }
+            }
+        }
+        let bones = order
+            .iter()
+            .map(|&k| {
+                let j = joints[k];
+                let parent = parent_joint(j).map(|pk| bone_of[pk].unwrap());
+                SkeletonBone {
+                    name: nodes[j]
+                        .name()
+                        .map_or_else(|| format!("joint{}", j), String::from),
+                    parent,
+                    rest: match parent {
+                        Some(_) => node_pose(&nodes[j]),
+                        None => placed(j),
+                    },
+                }
+            })
+            .collect();
+        let skeleton = Skeleton { bones };
+        let rest = skeleton.model_poses(&skeleton.rest());
+
+        let mut by_skin: Vec<Joints> = self
+            .document
+            .skins()
+            .map(|_| Joints {
+                bones: vec![],
+                binds: vec![],
+            })
+            .collect();
+        for skin in skins {
+            if let Some(a) = skin.inverse_bind_matrices() {
+                self.check(&a, Dimensions::Mat4, &[DataType::F32])?;
+                ensure!(
+                    a.count() == skin.joints().count(),
+                    "Skin {} has {} inverse bind matrices for {} joints",
+                    skin.index(),
+                    a.count(),
+                    skin.joints().count()
+                );
+            }
+            let inverse_binds: Vec<Mat4> = match skin
+                .reader(|b| self.buffers.get(b.index()).map(Vec::as_slice))
+                .read_inverse_bind_matrices()
+            {
+                Some(m) => m.map(Mat4::from).collect(),
+                // Joints are bound where they are without them
+                None => skin.joints().map(|_| Mat4::one()).collect(),
+            };
+            let found = &mut by_skin[skin.index()];
+            for (j, inverse_bind) in skin.joints().zip(inverse_binds) {
+                let k = joints.iter().position(|&x| x == j.index()).unwrap();
+                let bone = bone_of[k].unwrap();
+                let pose = rest[bone];
+                found.bones.push(bone as u8);
+                found.binds.push(
+                    Mat4::from_translation(pose.translation)
+                        * Mat4::from(pose.rotation)
+                        * inverse_bind,
+                );
+            }
+        }
+        Ok((skeleton, by_skin))
+    }
+
+    /// The vertices and indices of `prim`, placed by `place`, or if it's
+    /// skinned by `skin`, with joint indices turned into bone indices.
+    fn primitive(
+        &self,
+        prim: &Primitive,
+        place: Mat4,
+        skin: Option<&Joints>,
+    ) -> Result<(Vec<ModelVertex>, Vec<u32>)> {
+        use DataType::*;
+        let count = prim
+            .get(&Semantic::Positions)
+            .context("Primitive has no positions")?
+            .count();
+        for (semantic, accessor) in prim.attributes() {
+            let (dims, types): (_, &[_]) = match semantic {
+                Semantic::Positions | Semantic::Normals => (Dimensions::Vec3, &[F32]),
+                Semantic::Tangents => (Dimensions::Vec4, &[F32]),
+                Semantic::TexCoords(0) => (Dimensions::Vec2, &[F32, U8, U16]),
+                Semantic::Joints(0) => (Dimensions::Vec4, &[U8, U16]),
+                Semantic::Weights(0) => (Dimensions::Vec4, &[F32, U8, U16]),
+                _ => continue,
+            };
+            self.check(&accessor, dims, types)?;
+            ensure!(
+                accessor.count() == count,
+                "{:?} has {} elements, not {}",
+                semantic,
+                accessor.count(),
+                count
+            );
+        }
+        if let Some(a) = prim.indices() {
+            self.check(&a, Dimensions::Scalar, &[U8, U16, U32])?;
+        }
+
+        let reader = prim.reader(|b| self.buffers.get(b.index()).map(Vec::as_slice));
+        let positions: Vec<[f32; 3]> = reader.read_positions().unwrap().collect();
+        let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(Iterator::collect);
+        let tex_coords: Option<Vec<[f32; 2]>> =
+            reader.read_tex_coords(0).map(|t| t.into_f32().collect());
+        let tangents: Option<Vec<[f32; 4]>> = reader.read_tangents().map(Iterator::collect);
+        let bones = match skin {
+            Some(skin) => {
+                let ids = reader
+                    .read_joints(0)
+                    .context("Skinned mesh has no joints")?;
+                let weights = reader
+                    .read_weights(0)
+                    .context("Skinned mesh has no weights")?;
+                Some((
+                    skin,
+                    ids.into_u16().zip(weights.into_f32()).collect::<Vec<_>>(),
+                ))
+            }
+            None => None,
+        };
+
+        let mut vertices = Vec::with_capacity(count);
+        for i in 0..count {
+            let mut v = ModelVertex::new([0.0; 3], [0.0; 2], [0.0; 3]);
+            let mut place = place;
+            if let Some((skin, bones)) = &bones {
+                let (ids, weights) = bones[i];
+                let total: f32 = weights.iter().sum();
+                let mut blend = Mat4::zero();
+                for k in 0..4 {
+                    let j = ids[k] as usize;
+                    v.bone_ids[k] = *skin.bones.get(j).context("Vertex joint out of range")?;
+                    v.bone_weights[k] = if total > 0.0 {
+                        weights[k] / total
+                    } else {
+                        weights[k]
+                    };
+                    blend += skin.binds[j] * v.bone_weights[k];
+                }
+                place = if total > 0.0 { blend } else { Mat4::one() };
+            }
+            let linear =
+                Mat3::from_cols(place.x.truncate(), place.y.truncate(), place.z.truncate());
+            let normal_matrix = linear.invert().unwrap_or(linear).transpose();
+            v.position = place.transform_point(Pos3::from(positions[i])).into();
+            if let Some(uvs) = &tex_coords {
+                v.tex_coords = uvs[i];
+            }
+            if let Some(normals) = &normals {
+                v.normal = (normal_matrix * Vec3::from(normals[i])).normalize().into();
+            }
+            if let Some(tangents) = &tangents {
+                let [x, y, z, w] = tangents[i];
+                let dir = (linear * Vec3::new(x, y, z)).normalize();
+                v.tangent = [dir.x, dir.y, dir.z, w];
+            }
+            vertices.push(v);
+        }
+
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..count as u32).collect(),
+        };
+        if let Some(&i) = indices.iter().find(|&&i| i as usize >= count) {
+            bail!("Index {} is past the {} vertices", i, count);
+        }
+        if normals.is_none() {
+            compute_normals(&mut vertices, &indices);
+        }
+        if tangents.is_none() {
+            compute_tangents(&mut vertices, &indices);
+        }
+        Ok((vertices, indices))
+    }
+}
+
+/// Where `node` is relative to its parent, leaving out any scale.
+fn node_pose(node: &Node) -> Pose {
+    let (t, [x, y, z, w], _) = node.transform().decomposed();
+    Pose::new(Vec3::from(t), Quat::new(w, x, y, z).normalize())
+}
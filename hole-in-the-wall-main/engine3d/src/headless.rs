@@ -0,0 +1,103 @@
+use crate::events::{Input, Recording, Script};
+use crate::render::InstanceGroups;
+use crate::{Engine, Game};
+use std::path::Path;
+
+/// Drives a `Game` with the same fixed-`DT` loop as `run`, but without a
+/// window or a GPU.  Models are given refs but never loaded, and each frame
+/// is "rendered" only as far as collecting the game's `InstanceGroups`,
+/// unless the engine was made `offscreen`, when models are loaded and
+/// frames can be drawn for real with `screenshot`.  Headless engines always
+/// start from seed 0 unless told otherwise.
+pub struct Headless<G: Game> {
+    pub engine: Engine,
+    pub game: G,
+    pub rules: G::StaticData,
+    instance_groups: InstanceGroups,
+}
+
+impl<G: Game> Headless<G> {
+    pub fn new(asset_root: &Path) -> Self {
+        Self::with_engine(Engine::headless(asset_root, 0))
+    }
+
+    /// Like `new`, but drawing `width` by `height` frames offscreen on
+    /// whatever adapter there is, such as a software one.  `None` if there
+    /// isn't one.
+    pub fn offscreen(asset_root: &Path, width: u32, height: u32) -> Option<Self> {
+        Some(Self::with_engine(Engine::offscreen(
+            asset_root, 0, width, height,
+        )?))
+    }
+
+    /// Start from a recording's seed and replay its input.  Anything passed
+    /// to `step` or `run` is applied on top of the recorded input.
+    pub fn replay(asset_root: &Path, recording: Recording) -> Self {
+        let mut engine = Engine::headless(asset_root, recording.seed);
+        engine.events.replay(recording.script);
+        Self::with_engine(engine)
+    }
+
+    fn with_engine(mut engine: Engine) -> Self {
+        let (game, rules) = G::start(&mut engine);
+        let mut headless = Self {
+            engine,
+            game,
+            rules,
+            instance_groups: InstanceGroups::new(),
+        };
+        headless.collect_instances();
+        headless
+    }
+
+    /// Simulate one frame, applying `inputs` before the update.
+    pub fn step(&mut self, inputs: &[Input]) {
+        for input in inputs {
+            self.engine.events.input(*input);
+        }
+        self.game.update(&self.rules, &mut self.engine);
+        self.engine.events.next_frame();
+        self.engine.frame += 1;
+        self.collect_instances();
+    }
+
+    /// Simulate `frames` frames, taking input from `script`.  Script frames
+    /// are counted from the start of this call.
+    pub fn run(&mut self, script: &Script, frames: usize) {
+        for f in 0..frames {
+            self.step(script.inputs_at(f));
+        }
+    }
+
+    /// What the game rendered after the most recent frame.
+    pub fn instance_groups(&self) -> &InstanceGroups {
+        &self.instance_groups
+    }
+
+    /// Draw the game as it is now.  `None` unless the engine was made
+    /// `offscreen`.
+    pub fn screenshot(&mut self) -> Option<image::RgbaImage> {
+        let render = self.engine.render.as_mut()?;
+        Some(render.capture(
+            &self.engine.camera,
+            &self.game,
+            &self.rules,
+            &mut self.engine.assets,
+        ))
+    }
+
+    fn collect_instances(&mut self) {
+        self.instance_groups.clear();
+        self.game.render(&self.rules, &mut self.instance_groups);
+    }
+}
+
+pub fn run_headless<R, G: Game<StaticData = R>>(
+    asset_root: &Path,
+    script: &Script,
+    frames: usize,
+) -> Headless<G> {
+    let mut headless = Headless::new(asset_root);
+    headless.run(script, frames);
+    headless
+}
@@ -0,0 +1,172 @@
+use crate::geom::*;
+use crate::physics::{effective_inv_mass, RigidBody};
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+
+/// How much of a joint's drift the solver tries to take out per step.
+const BAUMGARTE: f32 = 0.2;
+
+/// What a joint lets its bodies do relative to each other.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum JointKind {
+    /// The anchors stay together, and the bodies can turn any way about
+    /// them.
+    BallSocket,
+    /// The anchors stay together, and the bodies can only turn about the
+    /// hinge's axis, given in each body's own frame.
+    Hinge {
+        #[serde(with = "Vec3Def")]
+        axis_a: Vec3,
+        #[serde(with = "Vec3Def")]
+        axis_b: Vec3,
+    },
+    /// The anchors stay `length` apart, as if on either end of a rod.
+    Distance { length: f32 },
+    /// The anchors stay together and the bodies can't turn at all: `a`
+    /// stays turned by `rot` from `b`.
+    Fixed {
+        #[serde(with = "QuatDef")]
+        rot: Quat,
+    },
+}
+
+/// A constraint between two bodies, or between a body and the world.  Each
+/// anchor is in its body's own frame, measured from its center of mass;
+/// with no `b`, `anchor_b` is a point in the world.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Joint<T> {
+    pub a: T,
+    pub b: Option<T>,
+    #[serde(with = "Vec3Def")]
+    pub anchor_a: Vec3,
+    #[serde(with = "Vec3Def")]
+    pub anchor_b: Vec3,
+    pub kind: JointKind,
+}
+
+impl<T: PartialEq> Joint<T> {
+    /// Fails if `b` is `a`, since a body can't be held to itself.
+    pub fn new(
+        a: T,
+        anchor_a: Vec3,
+        b: Option<T>,
+        anchor_b: Vec3,
+        kind: JointKind,
+    ) -> Result<Self> {
+        ensure!(b.as_ref() != Some(&a), "Can't joint a body to itself");
+        Ok(Self {
+            a,
+            b,
+            anchor_a,
+            anchor_b,
+            kind,
+        })
+    }
+}
+
+/// `r × v` as a matrix, so that `skew(r) * v == r.cross(v)`.
+fn skew(r: Vec3) -> Mat3 {
+    Mat3::new(0.0, r.z, -r.y, -r.z, 0.0, r.x, r.y, -r.x, 0.0)
+}
+
+/// How hard impulses in every direction at offset `r` are resisted.
+fn point_inv_mass(b: &RigidBody, r: Vec3) -> Mat3 {
+    let rx = skew(r);
+    Mat3::from_value(b.inv_mass) - rx * b.inv_inertia_world() * rx
+}
+
+/// Push the points at `ra` on a and `rb` on b, which are `err` apart, back
+/// together over a step of `dt`.
+fn solve_point(a: &mut RigidBody, ra: Vec3, b: &mut RigidBody, rb: Vec3, err: Vec3, dt: f32) {
+    let vrel = a.velocity_at(ra) - b.velocity_at(rb);
+    let k = point_inv_mass(a, ra) + point_inv_mass(b, rb);
+    if let Some(k_inv) = k.invert() {
+        let j = k_inv * -(vrel + err * (BAUMGARTE / dt));
+        a.apply_impulse(j, ra);
+        b.apply_impulse(-j, rb);
+    }
+}
+
+/// Stop a and b turning relative to each other about each of `dirs`, and
+/// turn them back by as much of the rotation `err` as is along each over a
+/// step of `dt`.
+fn solve_turn(a: &mut RigidBody, b: &mut RigidBody, dirs: &[Vec3], err: Vec3, dt: f32) {
+    let inv_inertia = a.inv_inertia_world() + b.inv_inertia_world();
+    for &d in dirs {
+        let k = d.dot(inv_inertia * d);
+        if k <= 0.0 {
+            continue;
+        }
+        let w = (a.omega - b.omega).dot(d);
+        let j = (err.dot(d) * (BAUMGARTE / dt) - w) / k;
+        a.omega += a.inv_inertia_world() * d * j;
+        b.omega -= b.inv_inertia_world() * d * j;
+    }
+}
+
+/// Two directions at right angles to `n` and each other.
+fn perpendiculars(n: Vec3) -> (Vec3, Vec3) {
+    let other = if n.x.abs() < 0.6 {
+        Vec3::unit_x()
+    } else {
+        Vec3::unit_y()
+    };
+    let t1 = n.cross(other).normalize();
+    (t1, n.cross(t1))
+}
+
+/// Apply the impulses that bring a and b, whose centers of mass are at `ca`
+/// and `cb`, back in line with `joint` over a step of `dt`.  Either can be
+/// `RigidBody::fixed`.
+pub fn solve_joint<T>(
+    joint: &Joint<T>,
+    a: &mut RigidBody,
+    ca: Pos3,
+    b: &mut RigidBody,
+    cb: Pos3,
+    dt: f32,
+) {
+    let ra = a.rot * joint.anchor_a;
+    let rb = b.rot * joint.anchor_b;
+    let err = (ca + ra) - (cb + rb);
+    match joint.kind {
+        JointKind::BallSocket => solve_point(a, ra, b, rb, err, dt),
+        JointKind::Hinge { axis_a, axis_b } => {
+            let (axis_a, axis_b) = ((a.rot * axis_a).normalize(), (b.rot * axis_b).normalize());
+            let (t1, t2) = perpendiculars(axis_a);
+            // Turning a about axis_a × axis_b lines its axis back up with b's
+            solve_turn(a, b, &[t1, t2], axis_a.cross(axis_b), dt);
+            solve_point(a, ra, b, rb, err, dt);
+        }
+        JointKind::Distance { length } => {
+            let dist = err.magnitude();
+            if dist <= f32::EPSILON {
+                return;
+            }
+            let n = err / dist;
+            let vn = (a.velocity_at(ra) - b.velocity_at(rb)).dot(n);
+            let k = effective_inv_mass(a, ra, n) + effective_inv_mass(b, rb, n);
+            if k <= 0.0 {
+                return;
+            }
+            let j = -(vn + (dist - length) * (BAUMGARTE / dt)) / k;
+            a.apply_impulse(n * j, ra);
+            b.apply_impulse(-n * j, rb);
+        }
+        JointKind::Fixed { rot } => {
+            // How far a has to turn to be back at `rot` from b, as a
+            // rotation vector (good enough for the small drifts there are)
+            let mut off = b.rot * rot * a.rot.conjugate();
+            if off.s < 0.0 {
+                off = -off;
+            }
+            // Holding the anchors together turns the bodies and turning
+            // them moves the anchors, so go over both twice
+            let dirs = [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()];
+            for _ in 0..2 {
+                solve_turn(a, b, &dirs, off.v * 2.0, dt);
+                solve_point(a, ra, b, rb, err, dt);
+            }
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use std::path::Path;
+use anyhow::Result;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
@@ -7,18 +11,28 @@ use winit::{
 pub mod anim;
 // pub mod audio;
 pub mod camera;
+pub mod clusters;
 pub mod collision;
+pub mod ecs;
 pub mod events;
+pub mod fracture;
 pub mod geom;
+pub mod gltf;
+pub mod joints;
 pub mod model;
+pub mod physics;
 pub mod text;
 pub mod texture;
-use events::Events;
+use events::{Events, Recording};
 pub mod render;
+pub mod scene;
+pub mod screenshot;
 use render::{InstanceGroups, Render};
 pub mod assets;
 use assets::Assets;
+pub mod headless;
 pub mod lights;
+pub mod shadows;
 
 pub const DT: f32 = 1.0 / 60.0;
 
@@ -28,59 +42,182 @@ pub trait Game: Sized {
     fn update(&mut self, rules: &Self::StaticData, engine: &mut Engine);
     fn handle_collision(&mut self);
     fn render(&self, rules: &Self::StaticData, igs: &mut InstanceGroups);
-    fn load_game(&mut self);
+    fn load_game(&mut self, engine: &mut Engine);
+}
+
+/// How far the engine's random sequence has got, so that a saved game can
+/// pick it up where it left off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct RngState {
+    pub seed: u64,
+    pub word_pos: u128,
 }
 
 pub struct Engine {
     pub frame: usize,
     pub assets: Assets,
-    render: Render,
+    // None when running headless, unless drawing offscreen
+    render: Option<Render>,
+    camera: camera::GameCamera,
     pub events: Events,
+    seed: u64,
+    rng: ChaCha8Rng,
 }
 
 impl Engine {
+    pub(crate) fn headless(asset_root: &Path, seed: u64) -> Self {
+        Self {
+            frame: 0,
+            assets: Assets::new(asset_root),
+            render: None,
+            camera: camera::GameCamera::new(1.0),
+            events: Events::default(),
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+    /// Like `headless`, but drawing `width` by `height` frames offscreen.
+    /// `None` if there's no adapter to draw with.
+    pub(crate) fn offscreen(asset_root: &Path, seed: u64, width: u32, height: u32) -> Option<Self> {
+        let camera = camera::GameCamera::new(width as f32 / height as f32);
+        let render = futures::executor::block_on(Render::offscreen(width, height, &camera))?;
+        Some(Self {
+            render: Some(render),
+            camera,
+            ..Self::headless(asset_root, seed)
+        })
+    }
+    /// Whether there's no window, and so no audio or input either.
+    pub fn is_headless(&self) -> bool {
+        self.render.as_ref().is_none_or(|r| !r.has_window())
+    }
+    /// The seed the engine's random sequence started from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    /// Restart the engine's random sequence from `seed`.
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+    /// Game code should draw all of its randomness from here, so that
+    /// recordings replay exactly.
+    pub fn rng(&mut self) -> &mut ChaCha8Rng {
+        &mut self.rng
+    }
+    pub fn rng_state(&self) -> RngState {
+        RngState {
+            seed: self.seed,
+            word_pos: self.rng.get_word_pos(),
+        }
+    }
+    pub fn restore_rng(&mut self, state: RngState) {
+        self.reseed(state.seed);
+        self.rng.set_word_pos(state.word_pos);
+    }
     pub fn load_model(&mut self, model: impl AsRef<Path>) -> assets::ModelRef {
-        self.assets.load_model(
-            &self.render.device,
-            &self.render.queue,
-            &self.render.texture_layout,
-            model,
-        )
+        match &self.render {
+            Some(render) => {
+                self.assets
+                    .load_model(&render.device, &render.queue, &render.texture_layout, model)
+            }
+            // Nothing to upload without a GPU, but the game still needs a stable ref
+            None => self.assets.model_ref_for(model),
+        }
     }
     pub fn camera_mut(&mut self) -> &mut camera::GameCamera {
-        &mut self.render.camera
+        &mut self.camera
+    }
+    /// The ray from the camera through the mouse cursor, for picking.  None
+    /// when there's no window to point at.
+    pub fn mouse_ray(&self) -> Option<geom::Ray> {
+        let size = self.render.as_ref()?.size;
+        let (x, y) = self.events.mouse_pos();
+        Some(self.camera.ray_through(
+            2.0 * x / size.width as f32 - 1.0,
+            1.0 - 2.0 * y / size.height as f32,
+        ))
     }
     pub fn set_ambient(&mut self, amb: f32) {
-        self.render.set_ambient(amb);
+        if let Some(render) = &mut self.render {
+            render.set_ambient(amb);
+        }
     }
     pub fn set_lights(&mut self, lights: impl IntoIterator<Item = lights::Light>) {
-        self.render.set_lights(lights.into_iter().collect());
+        if let Some(render) = &mut self.render {
+            render.set_lights(lights.into_iter().collect());
+        }
+    }
+    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.camera.aspect = new_size.width as f32 / new_size.height as f32;
+        if let Some(render) = &mut self.render {
+            render.resize(new_size);
+        }
     }
 }
 
+#[derive(Clone, Default, Debug)]
+pub struct RunOptions {
+    /// Seed for the engine's RNG; picked at random if not given.
+    pub seed: Option<u64>,
+    /// Record input to this file when the game exits.
+    pub record: Option<PathBuf>,
+    /// Replay input and seed from this recording instead of the window.
+    pub replay: Option<PathBuf>,
+}
+
 pub fn run<R, G: Game<StaticData = R>>(
     window_builder: winit::window::WindowBuilder,
     asset_root: &Path,
-) {
+) -> Result<()> {
+    run_with::<R, G>(window_builder, asset_root, RunOptions::default())
+}
+
+/// Like `run`, but as `options` say.  Fails if the recording to replay
+/// can't be read, or the one to record can't be written on exit.
+pub fn run_with<R, G: Game<StaticData = R>>(
+    window_builder: winit::window::WindowBuilder,
+    asset_root: &Path,
+    options: RunOptions,
+) -> Result<()> {
     use std::time::Instant;
+    // Before opening a window, in case there's nothing to replay
+    let replay = options.replay.as_ref().map(Recording::load).transpose()?;
     let mut event_loop = EventLoop::new();
     let window = window_builder.build(&event_loop).unwrap();
     let assets = Assets::new(asset_root);
+    let size = window.inner_size();
+    let camera = camera::GameCamera::new(size.width as f32 / size.height as f32);
     use futures::executor::block_on;
-    let render = block_on(Render::new(&window));
+    let render = block_on(Render::new(&window, &camera));
     let events = Events::default();
     let mut engine = Engine {
         assets,
-        render,
+        render: Some(render),
+        camera,
         events,
         frame: 0,
+        seed: 0,
+        rng: ChaCha8Rng::seed_from_u64(0),
     };
+    match replay {
+        Some(recording) => {
+            engine.reseed(recording.seed);
+            engine.events.replay(recording.script);
+        }
+        None => engine.reseed(options.seed.unwrap_or_else(rand::random)),
+    }
+    if options.record.is_some() {
+        engine.events.record();
+    }
     let (mut game, mut rules) = G::start(&mut engine);
+    // Set by F12, to save the next frame drawn
+    let mut screenshot = false;
     // How many unsimulated frames have we saved up?
     let mut available_time: f32 = 0.0;
     let mut since = Instant::now();
 
-    event_loop.run_return(move |event, _, control_flow| {
+    event_loop.run_return(|event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
         match event {
             Event::MainEventsCleared => window.request_redraw(),
@@ -100,27 +237,42 @@ pub fn run<R, G: Game<StaticData = R>>(
                         } => {
                             *control_flow = ControlFlow::Exit;
                         }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F12),
+                            ..
+                        } => {
+                            screenshot = true;
+                        }
                         _ => {}
                     },
                     WindowEvent::Resized(physical_size) => {
-                        engine.render.resize(*physical_size);
+                        engine.resize(*physical_size);
                     }
                     WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        engine.render.resize(**new_inner_size);
+                        engine.resize(**new_inner_size);
                     }
                     _ => {}
                 }
             }
             Event::RedrawRequested(_) => {
-                match engine.render.render(&game, &rules, &mut engine.assets) {
+                let render = engine.render.as_mut().unwrap();
+                match render.render(&engine.camera, &game, &rules, &mut engine.assets) {
                     Ok(_) => {}
                     // Recreate the swap_chain if lost
-                    Err(wgpu::SwapChainError::Lost) => engine.render.resize(engine.render.size),
+                    Err(wgpu::SwapChainError::Lost) => render.resize(render.size),
                     // The system is out of memory, we should probably quit
                     Err(wgpu::SwapChainError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                     // All other errors (Outdated, Timeout) should be resolved by the next frame
                     Err(e) => eprintln!("{:?}", e),
                 }
+                if std::mem::take(&mut screenshot) {
+                    let image = render.capture(&engine.camera, &game, &rules, &mut engine.assets);
+                    match screenshot::save(&image, "screenshots") {
+                        Ok(path) => println!("Saved {}", path.display()),
+                        Err(e) => eprintln!("{:?}", e),
+                    }
+                }
                 // The renderer "produces" time...
                 available_time += since.elapsed().as_secs_f32();
                 since = Instant::now();
@@ -138,4 +290,13 @@ pub fn run<R, G: Game<StaticData = R>>(
             engine.frame += 1;
         }
     });
+
+    if let Some(path) = &options.record {
+        let recording = Recording {
+            seed: engine.seed(),
+            script: engine.events.take_recording().unwrap_or_default(),
+        };
+        recording.save(path)?;
+    }
+    Ok(())
 }
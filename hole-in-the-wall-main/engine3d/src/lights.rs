@@ -1,39 +1,207 @@
 use crate::geom::*;
+use cgmath::Rad;
 
-#[derive(Debug, Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
-#[repr(C)]
+/// Where a light shines from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightKind {
+    /// From infinitely far away, like the sun, all along `dir`.
+    Directional { dir: Vec3 },
+    /// From `pos`, every way at once.
+    Point { pos: Pos3 },
+    /// From `pos` along `dir`, at full strength within `inner` of it and
+    /// fading out to nothing at `outer`.
+    Spot {
+        pos: Pos3,
+        dir: Vec3,
+        inner: Rad<f32>,
+        outer: Rad<f32>,
+    },
+}
+
+/// How point lights and spotlights dim with distance `d`: by
+/// `1 / (1 + linear * d + quadratic * d²)`.  The default doesn't dim at
+/// all.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Attenuation {
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Light {
-    pub pos: [f32; 4],
-    // pub dir:[f32;4],
-    pub color: [f32; 4],
+    pub kind: LightKind,
+    pub color: Vec3,
+    /// How far a point light or spotlight reaches, fading smoothly to
+    /// nothing there; 0 for no limit.
+    pub range: f32,
+    pub attenuation: Attenuation,
+    /// Whether the light casts shadows (see `shadows`).
+    pub shadows: bool,
 }
+
 impl Light {
+    fn new(kind: LightKind, color: Vec3) -> Self {
+        Self {
+            kind,
+            color,
+            range: 0.0,
+            attenuation: Attenuation::default(),
+            shadows: false,
+        }
+    }
     pub fn point(pos: Pos3, color: Vec3) -> Self {
+        Self::new(LightKind::Point { pos }, color)
+    }
+    pub fn directed(dir: Vec3, color: Vec3) -> Self {
+        Self::new(LightKind::Directional { dir }, color)
+    }
+    pub fn spot(
+        pos: Pos3,
+        dir: Vec3,
+        inner: impl Into<Rad<f32>>,
+        outer: impl Into<Rad<f32>>,
+        color: Vec3,
+    ) -> Self {
+        let (inner, outer) = (inner.into(), outer.into());
+        Self::new(
+            LightKind::Spot {
+                pos,
+                dir,
+                inner,
+                outer,
+            },
+            color,
+        )
+    }
+    pub fn with_range(self, range: f32) -> Self {
+        Self { range, ..self }
+    }
+    pub fn with_attenuation(self, linear: f32, quadratic: f32) -> Self {
         Self {
-            pos: [pos.x, pos.y, pos.z, 1.0],
-            // dir:[0.0,0.0,0.0,0.0],
-            color: [color.x, color.y, color.z, 0.0],
+            attenuation: Attenuation { linear, quadratic },
+            ..self
         }
     }
-    // pub fn directed(dir:Vec3, color:Vec3) -> Self {
-    //     Self {
-    //         pos:[0.0,0.0,0.0,0.0],
-    //         dir:[dir.x,dir.y,dir.z,1.0],
-    //         color:[color.x,color.y,color.z],
-    //     }
-    // }
-    // pub fn spot(pos:Pos3, dir:Vec3, color:Vec3) -> Self {
-    //     Self {
-    //         pos:[pos.x,pos.y,pos.z,1.0],
-    //         dir:[dir.x,dir.y,dir.z,1.0],
-    //         color:[color.x,color.y,color.z],
-    //     }
-    // }
-
-    pub fn position(&self) -> Pos3 {
-        Pos3::new(self.pos[0], self.pos[1], self.pos[2])
+    pub fn with_shadows(self) -> Self {
+        Self {
+            shadows: true,
+            ..self
+        }
+    }
+
+    pub fn position(&self) -> Option<Pos3> {
+        match self.kind {
+            LightKind::Directional { .. } => None,
+            LightKind::Point { pos } | LightKind::Spot { pos, .. } => Some(pos),
+        }
     }
     pub fn color(&self) -> Vec3 {
-        Vec3::new(self.color[0], self.color[1], self.color[2])
+        self.color
     }
+
+    pub fn to_raw(&self) -> LightRaw {
+        let (kind, pos, dir, cone) = match self.kind {
+            LightKind::Directional { dir } => {
+                (LightRaw::DIRECTIONAL, Pos3::origin(), dir, [0.0; 2])
+            }
+            LightKind::Point { pos } => (LightRaw::POINT, pos, Vec3::zero(), [0.0; 2]),
+            LightKind::Spot {
+                pos,
+                dir,
+                inner,
+                outer,
+            } => (LightRaw::SPOT, pos, dir, cone(inner, outer)),
+        };
+        let dir = if dir == Vec3::zero() {
+            dir
+        } else {
+            dir.normalize()
+        };
+        LightRaw {
+            pos: pos.into(),
+            kind,
+            dir: dir.into(),
+            range: self.range,
+            color: self.color.into(),
+            shadow: -1,
+            cone,
+            attenuation: [self.attenuation.linear, self.attenuation.quadratic],
+        }
+    }
+
+    /// The direction from `at` towards the light, and how much of its
+    /// light reaches `at`.
+    pub fn incoming(&self, at: Pos3) -> (Vec3, Vec3) {
+        self.to_raw().incoming(at)
+    }
+    /// The light falling on a surface at `at` facing `normal`.
+    pub fn irradiance(&self, at: Pos3, normal: Vec3) -> Vec3 {
+        let (to_light, color) = self.incoming(at);
+        color * normal.dot(to_light).max(0.0)
+    }
+}
+
+/// A light as `shader.frag` sees it, laid out for an std140 or std430
+/// array: each vec3 shares its 16 bytes with the scalar after it.
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Zeroable, bytemuck::Pod)]
+#[repr(C)]
+pub struct LightRaw {
+    pub pos: [f32; 3],
+    pub kind: u32,
+    pub dir: [f32; 3],
+    pub range: f32,
+    pub color: [f32; 3],
+    /// The first layer of the light's shadow maps, or -1 if it has none
+    pub shadow: i32,
+    /// Cosines of the inner and outer angles of a spotlight
+    pub cone: [f32; 2],
+    /// Linear and quadratic attenuation
+    pub attenuation: [f32; 2],
+}
+
+impl LightRaw {
+    /// A zeroed light, which gives off nothing.
+    pub const UNUSED: u32 = 0;
+    pub const DIRECTIONAL: u32 = 1;
+    pub const POINT: u32 = 2;
+    pub const SPOT: u32 = 3;
+
+    /// Mirrors `incoming` in `shader.frag`, so keep the two in step.
+    pub fn incoming(&self, at: Pos3) -> (Vec3, Vec3) {
+        let color = Vec3::from(self.color);
+        let dir = Vec3::from(self.dir);
+        match self.kind {
+            Self::UNUSED => (Vec3::zero(), Vec3::zero()),
+            Self::DIRECTIONAL => (-dir, color),
+            _ => {
+                let d = Pos3::from(self.pos) - at;
+                let dist = d.magnitude();
+                let to_light = d / dist.max(0.0001);
+                let [linear, quadratic] = self.attenuation;
+                let mut falloff = 1.0 / (1.0 + linear * dist + quadratic * dist * dist);
+                if self.range > 0.0 {
+                    let r = (1.0 - (dist / self.range).powi(4)).clamp(0.0, 1.0);
+                    falloff *= r * r;
+                }
+                if self.kind == Self::SPOT {
+                    falloff *= smoothstep(self.cone[1], self.cone[0], (-to_light).dot(dir));
+                }
+                (to_light, color * falloff)
+            }
+        }
+    }
+}
+
+/// The cosines of a spotlight's inner and outer angles, as the shader fades
+/// between them.  The fade needs the outer edge strictly outside the inner,
+/// so an `outer` inside `inner` is widened to just past it.
+pub(crate) fn cone(inner: Rad<f32>, outer: Rad<f32>) -> [f32; 2] {
+    let cos_inner = inner.0.cos();
+    [cos_inner, outer.0.cos().min(cos_inner - 1e-4)]
+}
+
+/// GLSL's `smoothstep`.
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
 }
@@ -1,8 +1,10 @@
 use anyhow::*;
 use std::ops::Range;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use wgpu::util::DeviceExt;
 
+use crate::anim::Rig;
+use crate::geom::*;
 use crate::texture;
 
 pub trait Vertex {
@@ -10,15 +12,32 @@ pub trait Vertex {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ModelVertex {
-    position: [f32; 3],
-    tex_coords: [f32; 2],
-    normal: [f32; 3],
-    bone_ids: [u8; 4], // 32 bits, fits into last slot of previous line
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    pub bone_ids: [u8; 4], // 32 bits, fits into last slot of previous line
     // Not relevant for static geometry, wasteful!
     // But, this means we just need one layout...
-    bone_weights: [f32; 4], // 32*4 bits
+    pub bone_weights: [f32; 4], // 32*4 bits
+    // xyz along increasing u, w the handedness of the bitangent
+    pub tangent: [f32; 4],
+}
+
+impl ModelVertex {
+    /// A vertex that only its first bone moves, with its tangent worked out
+    /// later by `compute_tangents`.
+    pub fn new(position: [f32; 3], tex_coords: [f32; 2], normal: [f32; 3]) -> Self {
+        Self {
+            position,
+            tex_coords,
+            normal,
+            bone_ids: [0, 0, 0, 0],
+            bone_weights: [1.0, 0.0, 0.0, 0.0],
+            tangent: [1.0, 0.0, 0.0, 1.0],
+        }
+    }
 }
 
 impl Vertex for ModelVertex {
@@ -54,17 +73,244 @@ impl Vertex for ModelVertex {
                     shader_location: 4,
                     format: wgpu::VertexFormat::Float4,
                 },
+                // 5 to 8 are the instance's model matrix
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 12]>() + mem::size_of::<[u8; 4]>())
+                        as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float4,
+                },
             ],
         }
     }
 }
 
+/// Face normals of the triangles in `indices`, averaged at each vertex,
+/// for models that don't come with normals.
+pub fn compute_normals(vertices: &mut [ModelVertex], indices: &[u32]) {
+    let mut normals = vec![Vec3::zero(); vertices.len()];
+    for tri in indices.chunks_exact(3) {
+        let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| Vec3::from(vertices[i as usize].position));
+        // Weighted by area, since the cross product is twice the area
+        let n = (b - a).cross(c - a);
+        for &i in tri {
+            normals[i as usize] += n;
+        }
+    }
+    for (v, n) in vertices.iter_mut().zip(normals) {
+        if n.magnitude2() > 0.0 {
+            v.normal = n.normalize().into();
+        }
+    }
+}
+
+/// Tangents along increasing u for every vertex, from the triangles in
+/// `indices` and the vertices' normals and texture coordinates.
+pub fn compute_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+    let mut tangents = vec![Vec3::zero(); vertices.len()];
+    let mut bitangents = vec![Vec3::zero(); vertices.len()];
+    for tri in indices.chunks_exact(3) {
+        let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| vertices[i as usize]);
+        let (e1, e2) = (
+            Vec3::from(b.position) - Vec3::from(a.position),
+            Vec3::from(c.position) - Vec3::from(a.position),
+        );
+        let (du1, dv1) = (
+            b.tex_coords[0] - a.tex_coords[0],
+            b.tex_coords[1] - a.tex_coords[1],
+        );
+        let (du2, dv2) = (
+            c.tex_coords[0] - a.tex_coords[0],
+            c.tex_coords[1] - a.tex_coords[1],
+        );
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < 1e-12 {
+            continue;
+        }
+        let t = (e1 * dv2 - e2 * dv1) / det;
+        let bt = (e2 * du1 - e1 * du2) / det;
+        for &i in tri {
+            tangents[i as usize] += t;
+            bitangents[i as usize] += bt;
+        }
+    }
+    for (i, v) in vertices.iter_mut().enumerate() {
+        let n = Vec3::from(v.normal);
+        // Gram-Schmidt against the normal, falling back on anything
+        // perpendicular to it where the texture coordinates don't say
+        let mut t = tangents[i] - n * n.dot(tangents[i]);
+        if t.magnitude2() < 1e-12 {
+            let other = if n.x.abs() < 0.9 {
+                Vec3::unit_x()
+            } else {
+                Vec3::unit_y()
+            };
+            t = other - n * n.dot(other);
+        }
+        let t = t.normalize();
+        let w = if n.cross(t).dot(bitangents[i]) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        v.tangent = [t.x, t.y, t.z, w];
+    }
+}
+
+/// A material's scalar factors as the fragment shader sees them.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub base_color: [f32; 4],
+    // w is unused
+    pub emissive: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    _padding: f32,
+}
+
+impl From<&MaterialData> for MaterialUniform {
+    fn from(m: &MaterialData) -> Self {
+        let [r, g, b] = m.emissive;
+        Self {
+            base_color: m.base_color,
+            emissive: [r, g, b, 0.0],
+            metallic: m.metallic,
+            roughness: m.roughness,
+            normal_scale: m.normal_scale,
+            _padding: 0.0,
+        }
+    }
+}
+
 pub struct Material {
     pub name: String,
     pub diffuse_texture: texture::Texture,
+    pub normal_texture: texture::Texture,
+    pub metallic_roughness_texture: texture::Texture,
+    pub emissive_texture: texture::Texture,
+    pub uniform_buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
 }
 
+impl Material {
+    /// The layout of every material's bind group: the base color, normal,
+    /// metallic-roughness and emissive maps, one sampler for all of them,
+    /// and the `MaterialUniform`.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                texture(2),
+                texture(3),
+                texture(4),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("texture_bind_group_layout"),
+        })
+    }
+
+    /// Put `mat` on the GPU, with 1x1 stand-ins for any maps it's missing.
+    pub fn upload(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        mat: &MaterialData,
+    ) -> Result<Self> {
+        let load = |source: &Option<ImageSource>, fallback: [u8; 4], linear: bool| {
+            let label = &mat.name;
+            match source {
+                Some(ImageSource::Path(p)) => texture::Texture::load(device, queue, p, linear)
+                    .with_context(|| format!("Couldn't load texture {}", p.display())),
+                Some(ImageSource::Bytes(b)) => {
+                    texture::Texture::from_bytes(device, queue, b, label, linear)
+                }
+                None => {
+                    let img = image::RgbaImage::from_pixel(1, 1, image::Rgba(fallback));
+                    let img = image::DynamicImage::ImageRgba8(img);
+                    texture::Texture::from_image(device, queue, &img, Some(label), linear)
+                }
+            }
+        };
+        // The factors are multiplied by the maps, so missing maps are white,
+        // apart from normal maps, which point straight out
+        let diffuse_texture = load(&mat.diffuse, [255; 4], false)?;
+        let normal_texture = load(&mat.normal, [128, 128, 255, 255], true)?;
+        let metallic_roughness_texture = load(&mat.metallic_roughness, [255; 4], true)?;
+        let emissive_texture = load(&mat.emissive_map, [255; 4], false)?;
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Material Buffer", mat.name)),
+            contents: bytemuck::cast_slice(&[MaterialUniform::from(mat)]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        fn view(binding: u32, texture: &texture::Texture) -> wgpu::BindGroupEntry<'_> {
+            wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            }
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                view(0, &diffuse_texture),
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+                view(2, &normal_texture),
+                view(3, &metallic_roughness_texture),
+                view(4, &emissive_texture),
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: None,
+        });
+
+        Ok(Self {
+            name: mat.name.clone(),
+            diffuse_texture,
+            normal_texture,
+            metallic_roughness_texture,
+            emissive_texture,
+            uniform_buffer,
+            bind_group,
+        })
+    }
+}
+
 pub struct Mesh {
     pub name: String,
     pub vertex_buffer: wgpu::Buffer,
@@ -76,6 +322,222 @@ pub struct Mesh {
 pub struct Model {
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
+    /// The skeleton and clips from the model's `.rig.json`, or from its
+    /// skin, if it has either.
+    pub rig: Option<Rig>,
+}
+
+/// Where a texture's image comes from: a file, or bytes embedded in the
+/// model.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ImageSource {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+/// A material as loaded, before anything is on the GPU.  It follows
+/// glTF's metallic-roughness model: each factor multiplies its map, if
+/// there is one.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MaterialData {
+    pub name: String,
+    /// The color of the surface, multiplying the diffuse texture's.
+    pub base_color: [f32; 4],
+    pub diffuse: Option<ImageSource>,
+    /// A tangent-space normal map, and how much to bend normals by it.
+    pub normal: Option<ImageSource>,
+    pub normal_scale: f32,
+    /// 0 for plastic and stone through to 1 for bare metal.
+    pub metallic: f32,
+    /// 0 for a mirror finish through to 1 for fully matte.
+    pub roughness: f32,
+    /// Roughness in its green channel and metalness in its blue.
+    pub metallic_roughness: Option<ImageSource>,
+    /// The light the surface gives off by itself.
+    pub emissive: [f32; 3],
+    pub emissive_map: Option<ImageSource>,
+}
+
+impl Default for MaterialData {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            diffuse: None,
+            normal: None,
+            normal_scale: 1.0,
+            metallic: 0.0,
+            roughness: 1.0,
+            metallic_roughness: None,
+            emissive: [0.0, 0.0, 0.0],
+            emissive_map: None,
+        }
+    }
+}
+
+/// A mesh as loaded, before anything is on the GPU.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+    pub material: usize,
+}
+
+/// A model as loaded from disk, before anything is on the GPU, so it can be
+/// checked without one.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+    pub rig: Option<Rig>,
+}
+
+impl ModelData {
+    /// Load a Wavefront OBJ, or a glTF 2.0 `.gltf` or `.glb`, by the file's
+    /// extension.  A `.rig.json` next to the model replaces any skin it
+    /// has, with its vertices weighted to the nearest bones.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let mut data = match ext.to_ascii_lowercase().as_str() {
+            "gltf" | "glb" => crate::gltf::load(path)?,
+            _ => Self::load_obj(path)?,
+        };
+        let rig_path = Rig::path_for(path);
+        if rig_path.exists() {
+            let rig = Rig::load(rig_path)?;
+            let segments = rig.skeleton.segments();
+            for v in data.meshes.iter_mut().flat_map(|m| m.vertices.iter_mut()) {
+                let (ids, weights) = segments.weights(Pos3::from(v.position));
+                v.bone_ids = ids;
+                v.bone_weights = weights;
+            }
+            data.rig = Some(rig);
+        }
+        Ok(data)
+    }
+
+    fn load_obj(path: &Path) -> Result<Self> {
+        let (obj_models, obj_materials) = tobj::load_obj(path, true)?;
+
+        // We're assuming that the texture files are stored with the obj file
+        let containing_folder = path.parent().context("Directory has no parent")?;
+
+        let mut materials: Vec<MaterialData> = obj_materials
+            .into_iter()
+            .map(|mat| obj_material(mat, containing_folder))
+            .collect();
+
+        let mut meshes = Vec::new();
+        for m in obj_models {
+            let mesh = &m.mesh;
+            let mut vertices = Vec::new();
+            for i in 0..mesh.positions.len() / 3 {
+                let position = [
+                    mesh.positions[i * 3],
+                    mesh.positions[i * 3 + 1],
+                    mesh.positions[i * 3 + 2],
+                ];
+                let tex_coords = match mesh.texcoords.get(i * 2..i * 2 + 2) {
+                    Some(uv) => [uv[0] * 1.4, uv[1] * 0.8],
+                    None => [0.0, 0.0],
+                };
+                let normal = match mesh.normals.get(i * 3..i * 3 + 3) {
+                    Some(n) => [n[0], n[1], n[2]],
+                    None => [0.0, 0.0, 0.0],
+                };
+                vertices.push(ModelVertex::new(position, tex_coords, normal));
+            }
+            if let Some(&i) = mesh.indices.iter().find(|&&i| i as usize >= vertices.len()) {
+                bail!("{} indexes vertex {} of {}", m.name, i, vertices.len());
+            }
+            if mesh.normals.len() < mesh.positions.len() {
+                compute_normals(&mut vertices, &mesh.indices);
+            }
+            compute_tangents(&mut vertices, &mesh.indices);
+            let material = match mesh.material_id {
+                Some(id) if id < materials.len() => id,
+                _ => default_material(&mut materials),
+            };
+            meshes.push(MeshData {
+                name: m.name,
+                vertices,
+                indices: mesh.indices.clone(),
+                material,
+            });
+        }
+
+        Ok(Self {
+            meshes,
+            materials,
+            rig: None,
+        })
+    }
+}
+
+/// An MTL material in our terms.  Roughness and metalness come from the
+/// `Pr` and `Pm` extensions if they're there, or else roughness from the
+/// shininess as Blender writes it.
+fn obj_material(mat: tobj::Material, folder: &Path) -> MaterialData {
+    let map = |name: &str| {
+        if name.is_empty() {
+            None
+        } else {
+            Some(ImageSource::Path(folder.join(name)))
+        }
+    };
+    let param = |key: &str| -> Option<Vec<f32>> {
+        let values = mat.unknown_param.get(key)?;
+        values.split_whitespace().map(|v| v.parse().ok()).collect()
+    };
+    let diffuse = map(&mat.diffuse_texture);
+    // Kd tints flat materials; textured ones look like their texture
+    let [r, g, b] = if diffuse.is_some() {
+        [1.0, 1.0, 1.0]
+    } else {
+        mat.diffuse
+    };
+    let emissive = match param("Ke").as_deref() {
+        Some(&[r, g, b]) => [r, g, b],
+        _ => [0.0, 0.0, 0.0],
+    };
+    let emissive_map = map(mat.unknown_param.get("map_Ke").map_or("", |s| s.trim()));
+    MaterialData {
+        base_color: [r, g, b, mat.dissolve],
+        diffuse,
+        normal: map(&mat.normal_texture),
+        metallic: match param("Pm").as_deref() {
+            Some(&[m]) => m.clamp(0.0, 1.0),
+            _ => 0.0,
+        },
+        roughness: match param("Pr").as_deref() {
+            Some(&[r]) => r.clamp(0.0, 1.0),
+            _ => 1.0 - (mat.shininess / 1000.0).clamp(0.0, 1.0).sqrt(),
+        },
+        // A white emissive map would light up a black Ke
+        emissive: if emissive_map.is_some() && emissive == [0.0; 3] {
+            [1.0, 1.0, 1.0]
+        } else {
+            emissive
+        },
+        emissive_map,
+        name: mat.name,
+        ..MaterialData::default()
+    }
+}
+
+/// The index of a plain white material in `materials`, adding one if
+/// there isn't one yet.
+pub(crate) fn default_material(materials: &mut Vec<MaterialData>) -> usize {
+    let default = MaterialData::default();
+    match materials.iter().position(|m| *m == default) {
+        Some(i) => i,
+        None => {
+            materials.push(default);
+            materials.len() - 1
+        }
+    }
 }
 
 impl Model {
@@ -85,68 +547,35 @@ impl Model {
         layout: &wgpu::BindGroupLayout,
         path: P,
     ) -> Result<Self> {
-        let (obj_models, obj_materials) = tobj::load_obj(path.as_ref(), true)?;
-
-        // We're assuming that the texture files are stored with the obj file
-        let containing_folder = path.as_ref().parent().context("Directory has no parent")?;
-
-        let mut materials = Vec::new();
-        for mat in obj_materials {
-            let diffuse_path = mat.diffuse_texture;
-            let diffuse_texture =
-                texture::Texture::load(device, queue, containing_folder.join(diffuse_path))?;
-
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
-                ],
-                label: None,
-            });
+        let data = ModelData::load(path.as_ref())
+            .with_context(|| format!("Couldn't load model {}", path.as_ref().display()))?;
+        Self::upload(device, queue, layout, data, &format!("{:?}", path.as_ref()))
+    }
 
-            materials.push(Material {
-                name: mat.name,
-                diffuse_texture,
-                bind_group,
-            });
-        }
+    /// Put `data` on the GPU.
+    pub fn upload(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        data: ModelData,
+        label: &str,
+    ) -> Result<Self> {
+        let materials = data
+            .materials
+            .iter()
+            .map(|mat| Material::upload(device, queue, layout, mat))
+            .collect::<Result<Vec<_>>>()?;
 
         let mut meshes = Vec::new();
-        for m in obj_models {
-            let mut vertices = Vec::new();
-            for i in 0..m.mesh.positions.len() / 3 {
-                vertices.push(ModelVertex {
-                    position: [
-                        m.mesh.positions[i * 3],
-                        m.mesh.positions[i * 3 + 1],
-                        m.mesh.positions[i * 3 + 2],
-                    ],
-                    tex_coords: [m.mesh.texcoords[i * 2] * 1.4, m.mesh.texcoords[i * 2 + 1] * 0.8],
-                    normal: [
-                        m.mesh.normals[i * 3],
-                        m.mesh.normals[i * 3 + 1],
-                        m.mesh.normals[i * 3 + 2],
-                    ],
-                    bone_ids: [0, 0, 0, 0],
-                    bone_weights: [1.0, 0.0, 0.0, 0.0],
-                });
-            }
-
+        for m in data.meshes {
             let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Vertex Buffer", path.as_ref())),
-                contents: bytemuck::cast_slice(&vertices),
+                label: Some(&format!("{} Vertex Buffer", label)),
+                contents: bytemuck::cast_slice(&m.vertices),
                 usage: wgpu::BufferUsage::VERTEX,
             });
             let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", path.as_ref())),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
+                label: Some(&format!("{} Index Buffer", label)),
+                contents: bytemuck::cast_slice(&m.indices),
                 usage: wgpu::BufferUsage::INDEX,
             });
 
@@ -154,12 +583,16 @@ impl Model {
                 name: m.name,
                 vertex_buffer,
                 index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
-                material: m.mesh.material_id.unwrap_or(0),
+                num_elements: m.indices.len() as u32,
+                material: m.material,
             });
         }
 
-        Ok(Self { meshes, materials })
+        Ok(Self {
+            meshes,
+            materials,
+            rig: data.rig,
+        })
     }
 }
 
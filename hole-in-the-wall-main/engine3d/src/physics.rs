@@ -0,0 +1,416 @@
+use crate::collision::Contact;
+use crate::geom::*;
+use crate::joints::{solve_joint, Joint};
+use serde::{Deserialize, Serialize};
+
+/// How many times the solvers sweep over their contacts per step, so that
+/// impulses from one contact can propagate to its neighbours.
+pub const SOLVER_ITERATIONS: usize = 4;
+
+/// How much of the remaining penetration the solvers try to remove per step.
+const BAUMGARTE: f32 = 0.2;
+/// Penetration the solvers leave alone, so resting contacts stay touching.
+const SLOP: f32 = EPS / 2.0;
+/// Impacts slower than this don't bounce, so that resting bodies settle
+/// instead of hopping on the velocity gravity gives them each step.
+const BOUNCE_SPEED: f32 = 0.5;
+
+/// What a surface is made of, as far as bouncing and sliding go.  The
+/// default is frictionless and doesn't bounce.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct Material {
+    /// How hard it is to get the surface sliding over another, as a
+    /// fraction of how hard the two are pressed together.
+    pub static_friction: f32,
+    /// How much the surface drags once it is sliding.
+    pub dynamic_friction: f32,
+    /// How much of the speed it hits something at it bounces back with,
+    /// from 0 to 1.
+    pub restitution: f32,
+}
+
+impl Material {
+    pub const fn new(static_friction: f32, dynamic_friction: f32, restitution: f32) -> Self {
+        Self {
+            static_friction,
+            dynamic_friction,
+            restitution,
+        }
+    }
+
+    /// The material for a contact between `self` and `other`: friction is
+    /// the geometric mean of the two, so anything on ice slides, and the
+    /// bouncier of the two decides the bounce.
+    pub fn combine(self, other: Material) -> Material {
+        Material {
+            static_friction: (self.static_friction * other.static_friction).sqrt(),
+            dynamic_friction: (self.dynamic_friction * other.dynamic_friction).sqrt(),
+            restitution: self.restitution.max(other.restitution),
+        }
+    }
+}
+
+/// Shapes that a `RigidBody` can move and turn.
+pub trait Solid: Shape {
+    fn center(&self) -> Pos3;
+    fn orientation(&self) -> Quat;
+    fn set_pose(&mut self, c: Pos3, rot: Quat);
+    /// Principal moments of inertia of a solid of uniform density, in the
+    /// shape's own frame.
+    fn inertia(&self, mass: f32) -> Vec3;
+}
+
+impl Solid for Box {
+    fn center(&self) -> Pos3 {
+        self.c
+    }
+    fn orientation(&self) -> Quat {
+        Quat::from(self.axes)
+    }
+    fn set_pose(&mut self, c: Pos3, rot: Quat) {
+        self.c = c;
+        self.axes = Mat3::from(rot);
+    }
+    fn inertia(&self, mass: f32) -> Vec3 {
+        let h = self.half_sizes.mul_element_wise(self.half_sizes);
+        Vec3::new(h.y + h.z, h.x + h.z, h.x + h.y) * (mass / 3.0)
+    }
+}
+
+impl Solid for Sphere {
+    fn center(&self) -> Pos3 {
+        self.c
+    }
+    fn orientation(&self) -> Quat {
+        Quat::one()
+    }
+    fn set_pose(&mut self, c: Pos3, _rot: Quat) {
+        self.c = c;
+    }
+    fn inertia(&self, mass: f32) -> Vec3 {
+        let i = 0.4 * mass * self.r * self.r;
+        Vec3::new(i, i, i)
+    }
+}
+
+/// The dynamic state of a solid body.  Position lives in the body's shape;
+/// the rotation is kept here as a quaternion and written back into the shape
+/// on every `integrate`.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RigidBody {
+    pub inv_mass: f32,
+    #[serde(with = "Vec3Def")]
+    pub inv_inertia: Vec3, // inverse principal moments, in the body's frame
+    #[serde(with = "Vec3Def")]
+    pub vel: Vec3,
+    #[serde(with = "Vec3Def")]
+    pub omega: Vec3, // angular velocity, in world space
+    #[serde(with = "QuatDef")]
+    pub rot: Quat,
+}
+
+impl RigidBody {
+    /// A body of `mass` at rest.  Without any mass it's `fixed`, since
+    /// nothing could ever stop it.
+    pub fn new<S: Solid>(shape: &S, mass: f32) -> Self {
+        if mass <= 0.0 {
+            return Self::fixed(shape);
+        }
+        let inertia = shape.inertia(mass);
+        Self {
+            inv_mass: 1.0 / mass,
+            inv_inertia: Vec3::new(1.0 / inertia.x, 1.0 / inertia.y, 1.0 / inertia.z),
+            vel: Vec3::zero(),
+            omega: Vec3::zero(),
+            rot: shape.orientation(),
+        }
+    }
+
+    /// A body that impulses can't move, though it can still be given a
+    /// velocity of its own.
+    pub fn fixed<S: Solid>(shape: &S) -> Self {
+        Self {
+            inv_mass: 0.0,
+            inv_inertia: Vec3::zero(),
+            vel: Vec3::zero(),
+            omega: Vec3::zero(),
+            rot: shape.orientation(),
+        }
+    }
+
+    pub fn inv_inertia_world(&self) -> Mat3 {
+        let r = Mat3::from(self.rot);
+        r * Mat3::from_diagonal(self.inv_inertia) * r.transpose()
+    }
+
+    /// Velocity of the point at offset `r` from the center of mass.
+    pub fn velocity_at(&self, r: Vec3) -> Vec3 {
+        self.vel + self.omega.cross(r)
+    }
+
+    /// Apply impulse `j` at offset `r` from the center of mass.
+    pub fn apply_impulse(&mut self, j: Vec3, r: Vec3) {
+        self.vel += j * self.inv_mass;
+        self.omega += self.inv_inertia_world() * r.cross(j);
+    }
+
+    pub fn integrate<S: Solid>(&mut self, shape: &mut S, dt: f32) {
+        let drot = 0.5 * dt * Quat::new(0.0, self.omega.x, self.omega.y, self.omega.z) * self.rot;
+        self.rot = (self.rot + drot).normalize();
+        shape.set_pose(shape.center() + self.vel * dt, self.rot);
+    }
+}
+
+pub fn integrate<S: Solid>(shapes: &mut [S], bodies: &mut [RigidBody], dt: f32) {
+    for (s, b) in shapes.iter_mut().zip(bodies.iter_mut()) {
+        b.integrate(s, dt);
+    }
+}
+
+/// How hard a unit impulse along `n` at offset `r` is resisted.
+pub(crate) fn effective_inv_mass(b: &RigidBody, r: Vec3, n: Vec3) -> f32 {
+    b.inv_mass + n.dot((b.inv_inertia_world() * r.cross(n)).cross(r))
+}
+
+/// Velocity of the point at `ra` on a relative to the point at `rb` on b,
+/// and how hard a unit impulse between them along `dir` is resisted.
+fn relative(a: &RigidBody, ra: Vec3, b: &Option<(&mut RigidBody, Vec3)>, dir: Vec3) -> (Vec3, f32) {
+    let mut vrel = a.velocity_at(ra);
+    let mut k = effective_inv_mass(a, ra, dir);
+    if let Some((b, rb)) = b {
+        vrel -= b.velocity_at(*rb);
+        k += effective_inv_mass(b, *rb, dir);
+    }
+    (vrel, k)
+}
+
+/// What the solver has done at one contact point so far this step.  The
+/// impulses add up over the sweeps, so that a later sweep can take back
+/// some of an earlier one's push when the contacts around it have done the
+/// job, instead of every sweep only ever adding to it.
+#[derive(Clone, Copy)]
+struct Pushed {
+    /// How fast the point should be separating, from the first sweep
+    target: Option<f32>,
+    /// The impulse along the normal so far, which can't pull
+    normal: f32,
+    /// The friction impulse on a so far
+    friction: Vec3,
+}
+
+impl Default for Pushed {
+    fn default() -> Self {
+        Self {
+            target: None,
+            normal: 0.0,
+            friction: Vec3::zero(),
+        }
+    }
+}
+
+/// Apply equal and opposite impulses at a point of contact `m` (whose normal
+/// points from b towards a), so that a and b stop moving into each other
+/// there, or separate fast enough to bounce or get rid of overlap beyond
+/// `SLOP` over a step of `dt`, and then friction against however they are
+/// sliding over each other.
+fn resolve(
+    a: &mut RigidBody,
+    ra: Vec3,
+    mut b: Option<(&mut RigidBody, Vec3)>,
+    m: &Manifold,
+    material: &Material,
+    pushed: &mut Pushed,
+    dt: f32,
+) {
+    let n = m.normal;
+    let (vrel, k) = relative(a, ra, &b, n);
+    if k <= 0.0 {
+        return;
+    }
+    let vn = vrel.dot(n);
+    // Bounce back, or at least start separating fast enough to get rid of
+    // some of the penetration this step
+    let target = *pushed.target.get_or_insert_with(|| {
+        let bias = BAUMGARTE / dt * (m.depth - SLOP).max(0.0);
+        let bounce = if -vn > BOUNCE_SPEED {
+            -material.restitution * vn
+        } else {
+            0.0
+        };
+        bounce.max(bias)
+    });
+    let normal = (pushed.normal + (target - vn) / k).max(0.0);
+    let jn = normal - pushed.normal;
+    pushed.normal = normal;
+    a.apply_impulse(n * jn, ra);
+    if let Some((b, rb)) = &mut b {
+        b.apply_impulse(-n * jn, *rb);
+    }
+
+    // Friction can take out at most as much sliding as the contact's own
+    // impulse allows: all of it if that's within static friction, or a
+    // dynamic friction's worth if not
+    let (vrel, _) = relative(a, ra, &b, n);
+    let vt = vrel - n * vrel.dot(n);
+    let slide = vt.magnitude();
+    if slide <= EPS {
+        return;
+    }
+    let t = vt / slide;
+    let (_, kt) = relative(a, ra, &b, t);
+    if kt <= 0.0 {
+        return;
+    }
+    let stop = pushed.friction - t * (slide / kt);
+    let friction = if stop.magnitude() <= material.static_friction * normal {
+        stop
+    } else {
+        let most = material.dynamic_friction * normal;
+        if stop.magnitude() > most {
+            stop * (most / stop.magnitude())
+        } else {
+            stop
+        }
+    };
+    let jt = friction - pushed.friction;
+    pushed.friction = friction;
+    a.apply_impulse(jt, ra);
+    if let Some((b, rb)) = b {
+        b.apply_impulse(-jt, rb);
+    }
+}
+
+fn pair_mut<T>(xs: &mut [T], i: usize, j: usize) -> (&mut T, &mut T) {
+    assert_ne!(i, j);
+    if i < j {
+        let (lo, hi) = xs.split_at_mut(j);
+        (&mut lo[i], &mut hi[0])
+    } else {
+        let (lo, hi) = xs.split_at_mut(i);
+        (&mut hi[0], &mut lo[j])
+    }
+}
+
+/// Solve contacts from `gather_contacts_aa` between bodies of one group,
+/// over a step of `dt`.
+pub fn solve_dyns<S: Solid>(
+    shapes: &[S],
+    bodies: &mut [RigidBody],
+    contacts: &[Contact<usize>],
+    material: Material,
+    dt: f32,
+) {
+    let mut pushed = vec![[Pushed::default(); 4]; contacts.len()];
+    for _ in 0..SOLVER_ITERATIONS {
+        for (c, pushed) in contacts.iter().zip(pushed.iter_mut()) {
+            let m = &c.manifold;
+            let (ca, cb) = (shapes[c.a].center(), shapes[c.b].center());
+            for (&p, pushed) in m.points().iter().zip(pushed.iter_mut()) {
+                let (a, b) = pair_mut(bodies, c.a, c.b);
+                let b = (b, p - cb);
+                resolve(a, p - ca, Some(b), m, &material, pushed, dt);
+            }
+        }
+    }
+}
+
+/// Solve contacts from `gather_contacts_ab` between two groups of bodies,
+/// over a step of `dt`.
+pub fn solve_dyn_dyn<S1: Solid, S2: Solid>(
+    ashapes: &[S1],
+    abodies: &mut [RigidBody],
+    bshapes: &[S2],
+    bbodies: &mut [RigidBody],
+    contacts: &[Contact<usize>],
+    material: Material,
+    dt: f32,
+) {
+    let mut pushed = vec![[Pushed::default(); 4]; contacts.len()];
+    for _ in 0..SOLVER_ITERATIONS {
+        for (c, pushed) in contacts.iter().zip(pushed.iter_mut()) {
+            let m = &c.manifold;
+            let (ca, cb) = (ashapes[c.a].center(), bshapes[c.b].center());
+            for (&p, pushed) in m.points().iter().zip(pushed.iter_mut()) {
+                let b = (&mut bbodies[c.b], p - cb);
+                let a = &mut abodies[c.a];
+                resolve(a, p - ca, Some(b), m, &material, pushed, dt);
+            }
+        }
+    }
+}
+
+/// Solve contacts from `gather_contacts_ab` against static scenery, such as
+/// planes, which is treated as immovable, over a step of `dt`.
+pub fn solve_dyn_stat<S: Solid>(
+    shapes: &[S],
+    bodies: &mut [RigidBody],
+    contacts: &[Contact<usize>],
+    material: Material,
+    dt: f32,
+) {
+    let mut pushed = vec![[Pushed::default(); 4]; contacts.len()];
+    for _ in 0..SOLVER_ITERATIONS {
+        for (c, pushed) in contacts.iter().zip(pushed.iter_mut()) {
+            let m = &c.manifold;
+            let ca = shapes[c.a].center();
+            for (&p, pushed) in m.points().iter().zip(pushed.iter_mut()) {
+                let a = &mut bodies[c.a];
+                resolve(a, p - ca, None, m, &material, pushed, dt);
+            }
+        }
+    }
+}
+
+/// Solve contacts and joints between bodies of any kind over a step of `dt`,
+/// given each body's center of mass, with the material for each contact picked by `material`.
+/// Joints and contacts are swept over together, so that pushing a jointed
+/// body out of something drags whatever it's joined to along.  Joints come
+/// last in each sweep, so that the contacts' impulses don't leave jointed
+/// bodies pulling apart.  Bodies that shouldn't move can be
+/// `RigidBody::fixed`.
+pub fn solve_contacts(
+    centers: &[Pos3],
+    bodies: &mut [RigidBody],
+    contacts: &[Contact<usize>],
+    joints: &[Joint<usize>],
+    dt: f32,
+    material: impl Fn(&Contact<usize>) -> Material,
+) {
+    // What joints without a second body are pinned to
+    let mut world = RigidBody {
+        inv_mass: 0.0,
+        inv_inertia: Vec3::zero(),
+        vel: Vec3::zero(),
+        omega: Vec3::zero(),
+        rot: Quat::one(),
+    };
+    let mut pushed = vec![[Pushed::default(); 4]; contacts.len()];
+    for _ in 0..SOLVER_ITERATIONS {
+        for (c, pushed) in contacts.iter().zip(pushed.iter_mut()) {
+            let m = &c.manifold;
+            let mat = material(c);
+            for (&p, pushed) in m.points().iter().zip(pushed.iter_mut()) {
+                let (a, b) = pair_mut(bodies, c.a, c.b);
+                let b = (b, p - centers[c.b]);
+                resolve(a, p - centers[c.a], Some(b), m, &mat, pushed, dt);
+            }
+        }
+        for j in joints.iter() {
+            match j.b {
+                Some(b) => {
+                    let (ca, cb) = (centers[j.a], centers[b]);
+                    let (a, b) = pair_mut(bodies, j.a, b);
+                    solve_joint(j, a, ca, b, cb, dt);
+                }
+                None => solve_joint(
+                    j,
+                    &mut bodies[j.a],
+                    centers[j.a],
+                    &mut world,
+                    Pos3::origin(),
+                    dt,
+                ),
+            }
+        }
+    }
+}
@@ -1,7 +1,9 @@
-use crate::anim::{self, DrawAnimated};
+use crate::anim::{self, DrawAnimated, BONE_MAX};
 use crate::assets::{Assets, ModelRef};
 use crate::camera::GameCamera;
+use crate::clusters::{ClusterGrid, ClusterUniform, Clusters, CLUSTER_DIMS};
 use crate::model::*;
+use crate::shadows::{self, SHADOW_MAP_SIZE};
 use crate::text;
 use crate::texture;
 use crate::Game;
@@ -9,39 +11,75 @@ use cgmath::SquareMatrix;
 use std::collections::BTreeMap;
 use wgpu::util::DeviceExt;
 
-const BONE_MAX: usize = 128;
-const LIGHT_MAX: usize = 10;
+/// How many bytes one instance's bones take up in the bone buffer (a
+/// multiple of the 256 bytes dynamic offsets have to be aligned to).
+const BONES_SIZE: wgpu::BufferAddress =
+    (BONE_MAX * std::mem::size_of::<anim::Bone>()) as wgpu::BufferAddress;
+const LIGHT_SIZE: wgpu::BufferAddress =
+    std::mem::size_of::<crate::lights::LightRaw>() as wgpu::BufferAddress;
+const CLUSTER_COUNT: usize = (CLUSTER_DIMS[0] * CLUSTER_DIMS[1] * CLUSTER_DIMS[2]) as usize;
+const CLUSTER_RANGES_SIZE: wgpu::BufferAddress =
+    (CLUSTER_COUNT * std::mem::size_of::<[u32; 2]>()) as wgpu::BufferAddress;
+const SHADOW_MATRIX_SIZE: wgpu::BufferAddress =
+    std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress;
+/// How many bytes one shadow map's uniforms take up in the shadow uniform
+/// buffer (the 256 bytes dynamic offsets have to be aligned to).
+const SHADOW_UNIFORMS_SIZE: wgpu::BufferAddress = 256;
 
 use winit::window::Window;
 pub(crate) struct Render {
-    surface: wgpu::Surface,
+    // None when drawing offscreen, without a window
+    surface: Option<wgpu::Surface>,
     pub(crate) device: wgpu::Device,
     pub(crate) queue: wgpu::Queue,
     pub(crate) staging_belt: wgpu::util::StagingBelt,
     sc_desc: wgpu::SwapChainDescriptor,
-    swap_chain: wgpu::SwapChain,
+    swap_chain: Option<wgpu::SwapChain>,
     pub(crate) size: winit::dpi::PhysicalSize<u32>,
     static_render_pipeline: wgpu::RenderPipeline,
     animated_render_pipeline: wgpu::RenderPipeline,
+    shadow_pipeline: wgpu::RenderPipeline,
     pub(crate) texture_layout: wgpu::BindGroupLayout,
-    pub(crate) camera: GameCamera,
     uniforms: Uniforms,
     uniform_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
     bone_buffer: wgpu::Buffer,
+    // how many instances' bones fit in the bone buffer
+    bone_capacity: usize,
+    bone_bind_group_layout: wgpu::BindGroupLayout,
     bone_bind_group: wgpu::BindGroup,
     pub(crate) ambient: f32,
     light_ambient_buffer: wgpu::Buffer,
     lights: Vec<crate::lights::Light>,
+    // how many lights fit in the light buffer
+    light_capacity: usize,
     light_buffer: wgpu::Buffer,
+    cluster_grid: ClusterGrid,
+    cluster_buffer: wgpu::Buffer,
+    cluster_range_buffer: wgpu::Buffer,
+    // how many light indices fit in the cluster index buffer
+    cluster_index_capacity: usize,
+    cluster_index_buffer: wgpu::Buffer,
+    light_bind_group_layout: wgpu::BindGroupLayout,
     light_bind_group: wgpu::BindGroup,
+    // how many shadow maps there's room for, and how many are drawn
+    shadow_capacity: usize,
+    shadow_layers: usize,
+    shadow_maps: texture::Texture,
+    shadow_layer_views: Vec<wgpu::TextureView>,
+    shadow_matrix_buffer: wgpu::Buffer,
+    shadow_uniform_buffer: wgpu::Buffer,
+    shadow_uniform_bind_group_layout: wgpu::BindGroupLayout,
+    shadow_uniform_bind_group: wgpu::BindGroup,
+    // the shadow pass has no materials, but shader.vert's uniforms are set 1
+    shadow_empty_bind_group: wgpu::BindGroup,
     depth_texture: texture::Texture,
     instance_groups: InstanceGroups,
     // game_text: text::GameText,
 }
 
 impl Render {
-    pub(crate) async fn new(window: &Window) -> Self {
+    pub(crate) async fn new(window: &Window, camera: &GameCamera) -> Self {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
@@ -67,9 +105,6 @@ impl Render {
             .await
             .unwrap();
 
-        // create staging belt
-        let staging_belt = wgpu::util::StagingBelt::new(1024);
-
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
             format: adapter.get_swap_chain_preferred_format(&surface),
@@ -78,46 +113,61 @@ impl Render {
             present_mode: wgpu::PresentMode::Fifo,
         };
 
-        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler {
-                            comparison: false,
-                            filtering: true,
-                        },
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
+        Self::with_device(Some(surface), device, queue, sc_desc, camera)
+    }
 
-        let camera = GameCamera {
-            eye: (0.0, 5.0, -10.0).into(),
-            target: (0.0, 0.0, 0.0).into(),
-            up: cgmath::Vector3::unit_y(),
-            aspect: sc_desc.width as f32 / sc_desc.height as f32,
-            fovy: 45.0,
-            znear: 0.1,
-            zfar: 200.0,
+    /// A renderer without a window, drawing `width` by `height` images for
+    /// `capture` on whatever adapter there is, software ones included.
+    /// `None` if there's no adapter at all.
+    pub(crate) async fn offscreen(width: u32, height: u32, camera: &GameCamera) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::BackendBit::all());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+            })
+            .await?;
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::empty(),
+                    limits: wgpu::Limits::default(),
+                },
+                None, // Trace path
+            )
+            .await
+            .ok()?;
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
         };
+        Some(Self::with_device(None, device, queue, sc_desc, camera))
+    }
+
+    fn with_device(
+        surface: Option<wgpu::Surface>,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        sc_desc: wgpu::SwapChainDescriptor,
+        camera: &GameCamera,
+    ) -> Self {
+        let size = winit::dpi::PhysicalSize::new(sc_desc.width, sc_desc.height);
+
+        // create staging belt
+        let staging_belt = wgpu::util::StagingBelt::new(1024);
+
+        let swap_chain = surface
+            .as_ref()
+            .map(|surface| device.create_swap_chain(surface, &sc_desc));
+
+        let texture_bind_group_layout = Material::bind_group_layout(&device);
 
         let mut uniforms = Uniforms::new();
-        uniforms.update_view_proj(&camera);
+        uniforms.update_view_proj(camera);
 
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniform Buffer"),
@@ -148,47 +198,43 @@ impl Render {
             }],
             label: Some("uniform_bind_group"),
         });
-        use crate::geom::*;
-        let lights = vec![crate::lights::Light::point(
-            Pos3::new(0.0, 10.0, 0.0),
-            Vec3::new(1.0, 1.0, 1.0),
-        )];
-        let light_uniform_size =
-            (LIGHT_MAX * std::mem::size_of::<crate::lights::Light>()) as wgpu::BufferAddress;
-        let light_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Lights buffer"),
-            size: light_uniform_size,
-            usage: wgpu::BufferUsage::UNIFORM
-                | wgpu::BufferUsage::COPY_SRC
-                | wgpu::BufferUsage::COPY_DST,
-            mapped_at_creation: false,
-        });
         let light_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
+                    Self::light_layout_entry(
+                        0,
+                        wgpu::BufferBindingType::Storage { read_only: true },
+                    ),
+                    Self::light_layout_entry(1, wgpu::BufferBindingType::Uniform),
+                    Self::light_layout_entry(2, wgpu::BufferBindingType::Uniform),
+                    Self::light_layout_entry(
+                        3,
+                        wgpu::BufferBindingType::Storage { read_only: true },
+                    ),
+                    Self::light_layout_entry(
+                        4,
+                        wgpu::BufferBindingType::Storage { read_only: true },
+                    ),
+                    Self::light_layout_entry(
+                        5,
+                        wgpu::BufferBindingType::Storage { read_only: true },
+                    ),
                     wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(
-                                LIGHT_MAX as u64
-                                    * std::mem::size_of::<crate::lights::Light>()
-                                        as wgpu::BufferAddress,
-                            ),
+                        binding: 6,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            sample_type: wgpu::TextureSampleType::Depth,
                         },
                         count: None,
                     },
                     wgpu::BindGroupLayoutEntry {
-                        binding: 1,
+                        binding: 7,
                         visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(
-                                std::mem::size_of::<f32>() as wgpu::BufferAddress
-                            ),
+                        ty: wgpu::BindingType::Sampler {
+                            comparison: true,
+                            filtering: true,
                         },
                         count: None,
                     },
@@ -196,6 +242,11 @@ impl Render {
                 label: Some("light_bind_group_layout"),
             });
 
+        // The game sets its own lights; until then there are none
+        let lights = vec![];
+        let light_capacity = 1;
+        let light_buffer = Self::create_light_buffer(&device, "Lights buffer", LIGHT_SIZE);
+
         let ambient = 1.0;
 
         let light_ambient_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -206,57 +257,105 @@ impl Render {
                 | wgpu::BufferUsage::COPY_DST,
         });
 
-        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &light_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: light_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: light_ambient_buffer.as_entire_binding(),
-                },
-            ],
-            label: Some("light_bind_group"),
+        let cluster_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cluster uniform buffer"),
+            contents: bytemuck::cast_slice(&[ClusterUniform::new(
+                camera,
+                CLUSTER_DIMS,
+                size.width,
+                size.height,
+            )]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
         });
+        let cluster_range_buffer =
+            Self::create_light_buffer(&device, "Cluster ranges buffer", CLUSTER_RANGES_SIZE);
+        // Enough for every cluster to have a light to begin with
+        let cluster_index_capacity = CLUSTER_COUNT;
+        let cluster_index_buffer = Self::create_light_buffer(
+            &device,
+            "Cluster lights buffer",
+            (cluster_index_capacity * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+        );
 
-        let bone_uniform_size =
-            (BONE_MAX * std::mem::size_of::<crate::anim::Bone>()) as wgpu::BufferAddress;
-        let bone_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Bones buffer"),
-            size: bone_uniform_size,
-            usage: wgpu::BufferUsage::UNIFORM
-                | wgpu::BufferUsage::COPY_SRC
-                | wgpu::BufferUsage::COPY_DST,
-            mapped_at_creation: false,
-        });
-        let bone_bind_group_layout =
+        // No light casts shadows to begin with, but the bind group still
+        // needs a shadow map
+        let shadow_capacity = 1;
+        let shadow_maps = texture::Texture::create_shadow_maps(
+            &device,
+            SHADOW_MAP_SIZE,
+            shadow_capacity as u32,
+            "shadow_maps",
+        );
+        let shadow_layer_views = vec![shadow_maps.layer_view(0)];
+        let shadow_matrix_buffer =
+            Self::create_light_buffer(&device, "Shadow matrices buffer", SHADOW_MATRIX_SIZE);
+
+        let light_bind_group = Self::create_light_bind_group(
+            &device,
+            &light_bind_group_layout,
+            [
+                &light_buffer,
+                &light_ambient_buffer,
+                &cluster_buffer,
+                &cluster_range_buffer,
+                &cluster_index_buffer,
+                &shadow_matrix_buffer,
+            ],
+            &shadow_maps,
+        );
+
+        let shadow_uniform_buffer = Self::create_shadow_uniform_buffer(&device, shadow_capacity);
+        let shadow_uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
                     visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
+                        has_dynamic_offset: true,
                         min_binding_size: wgpu::BufferSize::new(
-                            BONE_MAX as u64
-                                * std::mem::size_of::<anim::Bone>() as wgpu::BufferAddress,
+                            std::mem::size_of::<Uniforms>() as wgpu::BufferAddress
                         ),
                     },
                     count: None,
                 }],
+                label: Some("shadow_uniform_bind_group_layout"),
+            });
+        let shadow_uniform_bind_group = Self::create_shadow_uniform_bind_group(
+            &device,
+            &shadow_uniform_bind_group_layout,
+            &shadow_uniform_buffer,
+        );
+        let shadow_empty_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[],
+                label: Some("shadow_empty_bind_group_layout"),
+            });
+        let shadow_empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &shadow_empty_bind_group_layout,
+            entries: &[],
+            label: Some("shadow_empty_bind_group"),
+        });
+
+        let bone_capacity = 1;
+        let bone_buffer = Self::create_bone_buffer(&device, bone_capacity);
+        let bone_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(BONES_SIZE),
+                    },
+                    count: None,
+                }],
                 label: Some("bone_bind_group_layout"),
             });
 
-        let bone_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bone_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: bone_buffer.as_entire_binding(),
-            }],
-            label: Some("bone_bind_group"),
-        });
+        let bone_bind_group =
+            Self::create_bone_bind_group(&device, &bone_bind_group_layout, &bone_buffer);
 
         let static_vs_module =
             device.create_shader_module(&wgpu::include_spirv!("shader.vert.spv"));
@@ -376,6 +475,58 @@ impl Render {
             })
         };
 
+        // Depth only, from each light's point of view: shader.vert with the
+        // light's matrix as its projection
+        let shadow_pipeline = {
+            let shadow_pipeline_layout =
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Shadow Pipeline Layout"),
+                    bind_group_layouts: &[
+                        &shadow_empty_bind_group_layout,
+                        &shadow_uniform_bind_group_layout,
+                    ],
+                    push_constant_ranges: &[],
+                });
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Shadow Pipeline"),
+                layout: Some(&shadow_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &static_vs_module,
+                    entry_point: "main",
+                    buffers: &[ModelVertex::desc(), InstanceRaw::desc()],
+                },
+                fragment: None,
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    // Thin things like the floor have to cast shadows too
+                    cull_mode: wgpu::CullMode::None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    // Push the shadow maps back a little, more on slopes, so
+                    // surfaces don't shadow themselves
+                    bias: wgpu::DepthBiasState {
+                        constant: 2,
+                        slope_scale: 2.0,
+                        clamp: 0.0,
+                    },
+                    clamp_depth: false,
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+            })
+        };
+
         // let game_text = text::GameText::new("content/SourceSans3-Regular.ttf", &device);
 
         Self {
@@ -388,17 +539,35 @@ impl Render {
             size,
             static_render_pipeline,
             animated_render_pipeline,
-            camera,
+            shadow_pipeline,
             uniform_buffer,
             uniform_bind_group,
             uniforms,
             ambient,
             light_ambient_buffer,
             lights,
+            light_capacity,
             light_buffer,
+            cluster_grid: ClusterGrid::new(camera, CLUSTER_DIMS),
+            cluster_buffer,
+            cluster_range_buffer,
+            cluster_index_capacity,
+            cluster_index_buffer,
+            light_bind_group_layout,
             light_bind_group,
+            shadow_capacity,
+            shadow_layers: 0,
+            shadow_maps,
+            shadow_layer_views,
+            shadow_matrix_buffer,
+            shadow_uniform_buffer,
+            shadow_uniform_bind_group_layout,
+            shadow_uniform_bind_group,
+            shadow_empty_bind_group,
             bone_bind_group,
+            bone_bind_group_layout,
             bone_buffer,
+            bone_capacity,
             texture_layout: texture_bind_group_layout,
             depth_texture,
             instance_groups: InstanceGroups::new(),
@@ -412,62 +581,459 @@ impl Render {
             .write_buffer(&self.light_ambient_buffer, 0, bytemuck::cast_slice(&[amb]));
     }
 
+    fn create_bone_buffer(device: &wgpu::Device, instances: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Bones buffer"),
+            size: BONES_SIZE * instances as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM
+                | wgpu::BufferUsage::COPY_SRC
+                | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bone_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer {
+                    buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(BONES_SIZE),
+                },
+            }],
+            label: Some("bone_bind_group"),
+        })
+    }
+
     pub(crate) fn set_lights(&mut self, ls: Vec<crate::lights::Light>) {
-        assert!(ls.len() < LIGHT_MAX);
         self.lights = ls;
+        if self.lights.len() > self.light_capacity {
+            self.light_capacity = self.lights.len().next_power_of_two();
+            self.light_buffer = Self::create_light_buffer(
+                &self.device,
+                "Lights buffer",
+                LIGHT_SIZE * self.light_capacity as wgpu::BufferAddress,
+            );
+            self.rebind_lights();
+        }
+        let raw: Vec<_> = self
+            .lights
+            .iter()
+            .zip(shadows::first_layers(&self.lights))
+            .map(|(l, shadow)| crate::lights::LightRaw {
+                shadow,
+                ..l.to_raw()
+            })
+            .collect();
+        if !raw.is_empty() {
+            self.queue
+                .write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&raw));
+        }
+    }
+
+    fn light_layout_entry(binding: u32, ty: wgpu::BufferBindingType) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+
+    fn create_light_buffer(
+        device: &wgpu::Device,
+        label: &str,
+        size: wgpu::BufferAddress,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// The lights, ambient light, cluster uniform, cluster ranges, cluster
+    /// light indices and shadow matrices, in binding order, then the shadow
+    /// maps.
+    fn create_light_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffers: [&wgpu::Buffer; 6],
+        shadow_maps: &texture::Texture,
+    ) -> wgpu::BindGroup {
+        let mut entries: Vec<_> = buffers
+            .iter()
+            .enumerate()
+            .map(|(i, b)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource: b.as_entire_binding(),
+            })
+            .collect();
+        entries.push(wgpu::BindGroupEntry {
+            binding: 6,
+            resource: wgpu::BindingResource::TextureView(&shadow_maps.view),
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 7,
+            resource: wgpu::BindingResource::Sampler(&shadow_maps.sampler),
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &entries,
+            label: Some("light_bind_group"),
+        })
+    }
+
+    /// Make a new light bind group after one of its buffers grew.
+    fn rebind_lights(&mut self) {
+        self.light_bind_group = Self::create_light_bind_group(
+            &self.device,
+            &self.light_bind_group_layout,
+            [
+                &self.light_buffer,
+                &self.light_ambient_buffer,
+                &self.cluster_buffer,
+                &self.cluster_range_buffer,
+                &self.cluster_index_buffer,
+                &self.shadow_matrix_buffer,
+            ],
+            &self.shadow_maps,
+        );
+    }
+
+    /// Bin the lights into the clusters of the camera's view.
+    fn update_clusters(&mut self, camera: &GameCamera) {
+        let uniform = ClusterUniform::new(camera, CLUSTER_DIMS, self.size.width, self.size.height);
+        if !self.cluster_grid.fits(camera) {
+            self.cluster_grid = ClusterGrid::new(camera, CLUSTER_DIMS);
+        }
+        let clusters = Clusters::build(&self.cluster_grid, camera, &self.lights);
+        if clusters.indices.len() > self.cluster_index_capacity {
+            self.cluster_index_capacity = clusters.indices.len().next_power_of_two();
+            self.cluster_index_buffer = Self::create_light_buffer(
+                &self.device,
+                "Cluster lights buffer",
+                (self.cluster_index_capacity * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            );
+            self.rebind_lights();
+        }
+        self.queue
+            .write_buffer(&self.cluster_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        self.queue.write_buffer(
+            &self.cluster_range_buffer,
+            0,
+            bytemuck::cast_slice(&clusters.ranges),
+        );
+        if !clusters.indices.is_empty() {
+            self.queue.write_buffer(
+                &self.cluster_index_buffer,
+                0,
+                bytemuck::cast_slice(&clusters.indices),
+            );
+        }
+    }
+
+    fn create_shadow_uniform_buffer(device: &wgpu::Device, layers: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow uniforms buffer"),
+            size: SHADOW_UNIFORMS_SIZE * layers as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_shadow_uniform_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer {
+                    buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(
+                        std::mem::size_of::<Uniforms>() as wgpu::BufferAddress
+                    ),
+                },
+            }],
+            label: Some("shadow_uniform_bind_group"),
+        })
+    }
+
+    /// Point each shadow map at the camera's view, making room for more
+    /// maps if the lights need them.
+    fn update_shadows(&mut self, camera: &GameCamera) {
+        let matrices = shadows::matrices(&self.lights, camera);
+        self.shadow_layers = matrices.len();
+        if matrices.is_empty() {
+            return;
+        }
+        if matrices.len() > self.shadow_capacity {
+            self.shadow_capacity = matrices.len().next_power_of_two();
+            self.shadow_maps = texture::Texture::create_shadow_maps(
+                &self.device,
+                SHADOW_MAP_SIZE,
+                self.shadow_capacity as u32,
+                "shadow_maps",
+            );
+            self.shadow_layer_views = (0..self.shadow_capacity as u32)
+                .map(|i| self.shadow_maps.layer_view(i))
+                .collect();
+            self.shadow_matrix_buffer = Self::create_light_buffer(
+                &self.device,
+                "Shadow matrices buffer",
+                SHADOW_MATRIX_SIZE * self.shadow_capacity as wgpu::BufferAddress,
+            );
+            self.shadow_uniform_buffer =
+                Self::create_shadow_uniform_buffer(&self.device, self.shadow_capacity);
+            self.shadow_uniform_bind_group = Self::create_shadow_uniform_bind_group(
+                &self.device,
+                &self.shadow_uniform_bind_group_layout,
+                &self.shadow_uniform_buffer,
+            );
+            self.rebind_lights();
+        }
+        let raw: Vec<[[f32; 4]; 4]> = matrices.iter().map(|&m| m.into()).collect();
         self.queue
-            .write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&self.lights));
+            .write_buffer(&self.shadow_matrix_buffer, 0, bytemuck::cast_slice(&raw));
+        for (i, &m) in raw.iter().enumerate() {
+            let uniforms = Uniforms {
+                proj: m,
+                ..Uniforms::new()
+            };
+            self.queue.write_buffer(
+                &self.shadow_uniform_buffer,
+                i as wgpu::BufferAddress * SHADOW_UNIFORMS_SIZE,
+                bytemuck::cast_slice(&[uniforms]),
+            );
+        }
     }
 
     pub(crate) fn update_buffers<R, G: Game<StaticData = R>>(
         &mut self,
+        camera: &GameCamera,
         game: &G,
         rules: &R,
         assets: &mut Assets,
     ) {
-        self.uniforms.update_view_proj(&self.camera);
+        self.uniforms.update_view_proj(camera);
         self.queue.write_buffer(
             &self.uniform_buffer,
             0,
             bytemuck::cast_slice(&[self.uniforms]),
         );
+        self.update_clusters(camera);
+        self.update_shadows(camera);
         self.instance_groups.clear();
         game.render(rules, &mut self.instance_groups);
         self.instance_groups
             .update_buffers(&self.queue, &self.device, assets);
+
+        // Every animated instance's bones, one after another in the order
+        // they're drawn in
+        let bones: Vec<anim::Bone> = self
+            .instance_groups
+            .anim_groups
+            .values()
+            .flat_map(|(_irs, _buf, _cap, bones)| bones.iter().copied())
+            .collect();
+        let instances = bones.len() / BONE_MAX;
+        if instances > self.bone_capacity {
+            self.bone_capacity = instances.next_power_of_two();
+            self.bone_buffer = Self::create_bone_buffer(&self.device, self.bone_capacity);
+            self.bone_bind_group = Self::create_bone_bind_group(
+                &self.device,
+                &self.bone_bind_group_layout,
+                &self.bone_buffer,
+            );
+        }
+        self.queue
+            .write_buffer(&self.bone_buffer, 0, bytemuck::cast_slice(&bones));
     }
 
     pub(crate) fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         self.size = new_size;
         self.sc_desc.width = new_size.width;
         self.sc_desc.height = new_size.height;
-        self.camera.aspect = self.sc_desc.width as f32 / self.sc_desc.height as f32;
-        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        if let Some(surface) = &self.surface {
+            self.swap_chain = Some(self.device.create_swap_chain(surface, &self.sc_desc));
+        }
         self.depth_texture =
             texture::Texture::create_depth_texture(&self.device, &self.sc_desc, "depth_texture");
     }
 
     pub(crate) fn render<R, G: Game<StaticData = R>>(
         &mut self,
+        camera: &GameCamera,
         game: &G,
         rules: &R,
         assets: &mut Assets,
     ) -> Result<(), wgpu::SwapChainError> {
-        self.update_buffers(game, rules, assets);
+        self.update_buffers(camera, game, rules, assets);
+        let frame = self
+            .swap_chain
+            .as_mut()
+            .expect("offscreen renderers can only capture")
+            .get_current_frame()?
+            .output;
+        self.draw(&frame.view, assets);
+        Ok(())
+    }
+
+    /// Draw a frame into an image instead of the window.
+    pub(crate) fn capture<R, G: Game<StaticData = R>>(
+        &mut self,
+        camera: &GameCamera,
+        game: &G,
+        rules: &R,
+        assets: &mut Assets,
+    ) -> image::RgbaImage {
+        self.update_buffers(camera, game, rules, assets);
+        let (width, height) = (self.sc_desc.width, self.sc_desc.height);
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        };
+        let target = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("capture_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // The pipelines draw in the window's format
+            format: self.sc_desc.format,
+            // SAMPLED too, or GL keeps it in a renderbuffer it can't copy out
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT
+                | wgpu::TextureUsage::COPY_SRC
+                | wgpu::TextureUsage::SAMPLED,
+        });
+        self.draw(
+            &target.create_view(&wgpu::TextureViewDescriptor::default()),
+            assets,
+        );
+
+        // Rows of the copy have to be padded out to a multiple of 256 bytes
+        let row = width * 4;
+        let padded_row =
+            row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Capture buffer"),
+            size: (padded_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Capture Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture: &target,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::BufferCopyView {
+                buffer: &buffer,
+                layout: wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: padded_row,
+                    rows_per_image: height,
+                },
+            },
+            size,
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapped = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(mapped).unwrap();
+        let data = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((row * height) as usize);
+        for r in data.chunks(padded_row as usize) {
+            pixels.extend_from_slice(&r[..row as usize]);
+        }
+        drop(data);
+        buffer.unmap();
+        if let wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb =
+            self.sc_desc.format
+        {
+            for p in pixels.chunks_mut(4) {
+                p.swap(0, 2);
+            }
+        }
+        image::RgbaImage::from_raw(width, height, pixels).unwrap()
+    }
 
-        let frame = self.swap_chain.get_current_frame()?.output;
+    pub(crate) fn has_window(&self) -> bool {
+        self.surface.is_some()
+    }
 
+    /// Draw the shadow maps, then the scene into `view`.
+    fn draw(&mut self, view: &wgpu::TextureView, assets: &Assets) {
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
 
+        // Only static instances that cast shadows are drawn into the shadow
+        // maps; animated ones don't cast any
+        for layer in 0..self.shadow_layers {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                    attachment: &self.shadow_layer_views[layer],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            shadow_pass.set_pipeline(&self.shadow_pipeline);
+            shadow_pass.set_bind_group(0, &self.shadow_empty_bind_group, &[]);
+            shadow_pass.set_bind_group(
+                1,
+                &self.shadow_uniform_bind_group,
+                &[(layer as wgpu::BufferAddress * SHADOW_UNIFORMS_SIZE) as u32],
+            );
+            for (mr, (irs, buf, _cap)) in self.instance_groups.shadow_groups.iter() {
+                if irs.is_empty() {
+                    continue;
+                }
+                shadow_pass.set_vertex_buffer(1, buf.as_ref().unwrap().slice(..));
+                for mesh in &assets.get_model(*mr).unwrap().meshes {
+                    shadow_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    shadow_pass
+                        .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    shadow_pass.draw_indexed(0..mesh.num_elements, 0, 0..irs.len() as u32);
+                }
+            }
+        }
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &frame.view,
+                    attachment: view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -500,20 +1066,21 @@ impl Render {
                 );
             }
             render_pass.set_pipeline(&self.animated_render_pipeline);
-            for (mr, (irs, buf, _cap, bones)) in self.instance_groups.anim_groups.iter() {
+            let mut drawn = 0;
+            for (mr, (irs, buf, _cap, _bones)) in self.instance_groups.anim_groups.iter() {
                 let model = assets.get_model(*mr).unwrap();
-                for (i, (_ir, bones)) in irs.iter().zip(bones.chunks_exact(BONE_MAX)).enumerate() {
-                    let i = i as u64;
-                    self.queue
-                        .write_buffer(&self.bone_buffer, 0, bytemuck::cast_slice(&bones));
-                    render_pass.set_vertex_buffer(1, buf.as_ref().unwrap().slice(i..(i + 1)));
-                    // TODO set up bones for model here and bone bind group
+                let stride = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+                for i in 0..irs.len() as wgpu::BufferAddress {
+                    let instance = buf.as_ref().unwrap().slice(i * stride..(i + 1) * stride);
+                    render_pass.set_vertex_buffer(1, instance);
                     render_pass.draw_model_skinned(
                         model,
                         &self.uniform_bind_group,
                         &self.light_bind_group,
                         &self.bone_bind_group,
+                        (drawn * BONES_SIZE) as u32,
                     );
+                    drawn += 1;
                 }
             }
         }
@@ -526,20 +1093,20 @@ impl Render {
         //     &self.device,
         //     &mut self.staging_belt,
         //     &mut encoder,
-        //     &frame.view,
+        //     view,
         //     self.size,
         // );
 
         // submit
         self.staging_belt.finish();
         self.queue.submit(std::iter::once(encoder.finish()));
-
-        Ok(())
     }
 }
 
 pub struct InstanceGroups {
     static_groups: BTreeMap<ModelRef, (Vec<InstanceRaw>, Option<wgpu::Buffer>, usize)>,
+    // the static instances that also cast shadows
+    shadow_groups: BTreeMap<ModelRef, (Vec<InstanceRaw>, Option<wgpu::Buffer>, usize)>,
     anim_groups: BTreeMap<
         ModelRef,
         (
@@ -552,14 +1119,19 @@ pub struct InstanceGroups {
 }
 
 impl InstanceGroups {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             static_groups: BTreeMap::new(),
+            shadow_groups: BTreeMap::new(),
             anim_groups: BTreeMap::new(),
         }
     }
-    fn clear(&mut self) {
-        for (_mr, (irs, _buf, _cap)) in self.static_groups.iter_mut() {
+    pub(crate) fn clear(&mut self) {
+        for (_mr, (irs, _buf, _cap)) in self
+            .static_groups
+            .iter_mut()
+            .chain(self.shadow_groups.iter_mut())
+        {
             irs.clear();
         }
         for (_mr, (irs, _buf, _cap, bones)) in self.anim_groups.iter_mut() {
@@ -568,7 +1140,11 @@ impl InstanceGroups {
         }
     }
     fn update_buffers(&mut self, queue: &wgpu::Queue, device: &wgpu::Device, assets: &Assets) {
-        for (mr, (irs, buf, cap)) in self.static_groups.iter_mut() {
+        for (mr, (irs, buf, cap)) in self
+            .static_groups
+            .iter_mut()
+            .chain(self.shadow_groups.iter_mut())
+        {
             if buf.is_none() || *cap < irs.len() {
                 buf.replace(
                     device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -597,6 +1173,13 @@ impl InstanceGroups {
             }
         }
     }
+    /// The static instances submitted for `mr` since the last clear.
+    pub fn instances(&self, mr: ModelRef) -> &[InstanceRaw] {
+        self.static_groups
+            .get(&mr)
+            .map(|(irs, _buf, _cap)| irs.as_slice())
+            .unwrap_or(&[])
+    }
     pub fn render(&mut self, mr: ModelRef, ir: InstanceRaw) {
         self.render_batch(mr, std::iter::once(ir));
     }
@@ -608,6 +1191,23 @@ impl InstanceGroups {
             .0
             .extend(ir.into_iter())
     }
+    /// Draw `ir`, and have it cast shadows from lights that cast them.
+    pub fn render_casting_shadow(&mut self, mr: ModelRef, ir: InstanceRaw) {
+        self.render_batch_casting_shadow(mr, std::iter::once(ir));
+    }
+    pub fn render_batch_casting_shadow(
+        &mut self,
+        mr: ModelRef,
+        ir: impl IntoIterator<Item = InstanceRaw>,
+    ) {
+        let irs: Vec<InstanceRaw> = ir.into_iter().collect();
+        self.shadow_groups
+            .entry(mr)
+            .or_insert((vec![], None, 0))
+            .0
+            .extend_from_slice(&irs);
+        self.render_batch(mr, irs);
+    }
     pub fn render_anim(
         &mut self,
         mr: ModelRef,
@@ -616,6 +1216,8 @@ impl InstanceGroups {
     ) {
         self.render_anim_batch(mr, std::iter::once(ir), bones);
     }
+    /// Draw every instance in `ir` in the one pose given by `bone` (see
+    /// `anim::Skeleton::bones`).
     pub fn render_anim_batch(
         &mut self,
         mr: ModelRef,
@@ -624,12 +1226,15 @@ impl InstanceGroups {
     ) {
         let ref mut groups = self.anim_groups;
         let (irs, _buf, _cap, bones) = groups.entry(mr).or_insert((vec![], None, 0, vec![]));
-        irs.extend(ir.into_iter());
-        bones.extend(
-            bone.into_iter()
-                .chain(std::iter::repeat_with(anim::Bone::default))
-                .take(BONE_MAX),
-        );
+        let pose: Vec<anim::Bone> = bone
+            .into_iter()
+            .chain(std::iter::repeat_with(anim::Bone::default))
+            .take(BONE_MAX)
+            .collect();
+        for ir in ir {
+            irs.push(ir);
+            bones.extend_from_slice(&pose);
+        }
     }
 }
 
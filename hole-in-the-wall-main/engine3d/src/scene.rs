@@ -0,0 +1,226 @@
+use crate::assets::ModelRef;
+use crate::geom::*;
+use crate::render::{InstanceGroups, InstanceRaw};
+use std::cell::Cell;
+
+/// A handle to a node in a `Scene`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct NodeId(usize);
+
+#[derive(Clone, Debug)]
+struct Node {
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    translation: Vec3,
+    rotation: Quat,
+    scale: Vec3,
+    model: Option<ModelRef>,
+    visible: bool,
+    casts_shadow: bool,
+    // cached parent-to-world * local; only valid while `dirty` is false
+    world: Cell<Mat4>,
+    dirty: Cell<bool>,
+}
+
+impl Node {
+    fn local(&self) -> Mat4 {
+        Mat4::from_translation(self.translation)
+            * Mat4::from(self.rotation)
+            * Mat4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// A hierarchy of transforms.  Each node is placed relative to its parent,
+/// so moving a node moves everything under it, and nodes with a model are
+/// drawn wherever they end up.  World matrices are cached and only worked
+/// out again after a node or one of its ancestors changes.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    nodes: Vec<Option<Node>>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an empty node at the origin of its parent, or of the world.
+    pub fn add(&mut self, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Some(Node {
+            parent: None,
+            children: vec![],
+            translation: Vec3::zero(),
+            rotation: Quat::one(),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+            model: None,
+            visible: true,
+            casts_shadow: false,
+            world: Cell::new(Mat4::one()),
+            dirty: Cell::new(true),
+        }));
+        self.set_parent(id, parent);
+        id
+    }
+
+    /// Remove `id` and everything under it.
+    pub fn remove(&mut self, id: NodeId) {
+        self.set_parent(id, None);
+        let mut doomed = vec![id];
+        while let Some(id) = doomed.pop() {
+            if let Some(node) = self.nodes[id.0].take() {
+                doomed.extend(node.children);
+            }
+        }
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        matches!(self.nodes.get(id.0), Some(Some(_)))
+    }
+
+    fn node(&self, id: NodeId) -> &Node {
+        self.nodes[id.0].as_ref().expect("node was removed")
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        self.nodes[id.0].as_mut().expect("node was removed")
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.node(id).children
+    }
+
+    /// Move `id` (and everything under it) to a new parent, keeping its
+    /// local transform.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let mut p = parent;
+        while let Some(ancestor) = p {
+            assert_ne!(ancestor, id, "a node can't be its own ancestor");
+            p = self.node(ancestor).parent;
+        }
+        if let Some(old) = self.node(id).parent {
+            self.node_mut(old).children.retain(|&c| c != id);
+        }
+        if let Some(new) = parent {
+            self.node_mut(new).children.push(id);
+        }
+        self.node_mut(id).parent = parent;
+        self.mark_dirty(id);
+    }
+
+    /// Throw away the cached world matrices of `id` and everything under it.
+    fn mark_dirty(&self, id: NodeId) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = self.node(id);
+            // Anything under a dirty node is dirty already
+            if !node.dirty.replace(true) {
+                stack.extend(node.children.iter().copied());
+            }
+        }
+    }
+
+    pub fn translation(&self, id: NodeId) -> Vec3 {
+        self.node(id).translation
+    }
+    pub fn rotation(&self, id: NodeId) -> Quat {
+        self.node(id).rotation
+    }
+    pub fn scale(&self, id: NodeId) -> Vec3 {
+        self.node(id).scale
+    }
+    pub fn set_translation(&mut self, id: NodeId, translation: Vec3) {
+        self.node_mut(id).translation = translation;
+        self.mark_dirty(id);
+    }
+    pub fn set_rotation(&mut self, id: NodeId, rotation: Quat) {
+        self.node_mut(id).rotation = rotation;
+        self.mark_dirty(id);
+    }
+    pub fn set_scale(&mut self, id: NodeId, scale: Vec3) {
+        self.node_mut(id).scale = scale;
+        self.mark_dirty(id);
+    }
+
+    pub fn model(&self, id: NodeId) -> Option<ModelRef> {
+        self.node(id).model
+    }
+    pub fn set_model(&mut self, id: NodeId, model: Option<ModelRef>) {
+        self.node_mut(id).model = model;
+    }
+
+    /// Hidden nodes aren't drawn, and neither is anything under them.
+    pub fn set_visible(&mut self, id: NodeId, visible: bool) {
+        self.node_mut(id).visible = visible;
+    }
+    pub fn is_visible(&self, id: NodeId) -> bool {
+        let node = self.node(id);
+        match node.parent {
+            Some(p) => node.visible && self.is_visible(p),
+            None => node.visible,
+        }
+    }
+
+    /// Whether `id`'s model casts shadows from lights that cast them.
+    /// Unlike visibility, this isn't passed down to the nodes under it.
+    pub fn set_casts_shadow(&mut self, id: NodeId, casts_shadow: bool) {
+        self.node_mut(id).casts_shadow = casts_shadow;
+    }
+    pub fn casts_shadow(&self, id: NodeId) -> bool {
+        self.node(id).casts_shadow
+    }
+
+    /// `id`'s transform relative to its parent.
+    pub fn local_matrix(&self, id: NodeId) -> Mat4 {
+        self.node(id).local()
+    }
+
+    /// `id`'s transform relative to the world.
+    pub fn world_matrix(&self, id: NodeId) -> Mat4 {
+        let node = self.node(id);
+        if node.dirty.get() {
+            let parent = node.parent.map_or(Mat4::one(), |p| self.world_matrix(p));
+            node.world.set(parent * node.local());
+            node.dirty.set(false);
+        }
+        node.world.get()
+    }
+
+    /// Where `id`'s origin is in the world.
+    pub fn world_position(&self, id: NodeId) -> Pos3 {
+        Pos3::from_vec(self.world_matrix(id).w.truncate())
+    }
+
+    /// The model and instance of every visible node with a model.
+    pub fn instances(&self) -> impl Iterator<Item = (ModelRef, InstanceRaw)> + '_ {
+        self.drawn().map(|(_id, model, ir)| (model, ir))
+    }
+
+    fn drawn(&self) -> impl Iterator<Item = (NodeId, ModelRef, InstanceRaw)> + '_ {
+        self.nodes.iter().enumerate().filter_map(move |(i, node)| {
+            let model = node.as_ref()?.model?;
+            let id = NodeId(i);
+            if !self.is_visible(id) {
+                return None;
+            }
+            let ir = InstanceRaw {
+                model: self.world_matrix(id).into(),
+            };
+            Some((id, model, ir))
+        })
+    }
+
+    pub fn render(&self, igs: &mut InstanceGroups) {
+        for (id, model, ir) in self.drawn() {
+            if self.casts_shadow(id) {
+                igs.render_casting_shadow(model, ir);
+            } else {
+                igs.render(model, ir);
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Saving frames as images, and comparing them against golden images for
+//! regression tests.
+
+use anyhow::*;
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+
+/// Set this environment variable to overwrite golden images with what
+/// was drawn instead of comparing against them.
+pub const UPDATE_GOLDEN: &str = "UPDATE_GOLDEN";
+
+/// Save `image` in `dir` as the first of `screenshot-0.png`,
+/// `screenshot-1.png`, ... that isn't taken yet.
+pub fn save(image: &RgbaImage, dir: impl AsRef<Path>) -> Result<PathBuf> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir).with_context(|| format!("Couldn't make {}", dir.display()))?;
+    let path = (0..)
+        .map(|n| dir.join(format!("screenshot-{}.png", n)))
+        .find(|p| !p.exists())
+        .unwrap();
+    image
+        .save(&path)
+        .with_context(|| format!("Couldn't save {}", path.display()))?;
+    Ok(path)
+}
+
+/// How far apart two images of the same size are.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Diff {
+    /// How many pixels differ by more than the tolerance in some channel
+    pub pixels: usize,
+    /// The most any channel of any pixel differs by
+    pub max: u8,
+}
+
+/// Compare `a` and `b` pixel by pixel, ignoring channels that differ by
+/// `tolerance` or less.  `None` if they aren't the same size.
+pub fn diff(a: &RgbaImage, b: &RgbaImage, tolerance: u8) -> Option<Diff> {
+    if a.dimensions() != b.dimensions() {
+        return None;
+    }
+    let mut d = Diff { pixels: 0, max: 0 };
+    for (pa, pb) in a.pixels().zip(b.pixels()) {
+        let off =
+            pa.0.iter()
+                .zip(pb.0.iter())
+                .map(|(&x, &y)| x.abs_diff(y))
+                .max()
+                .unwrap();
+        d.max = d.max.max(off);
+        if off > tolerance {
+            d.pixels += 1;
+        }
+    }
+    Some(d)
+}
+
+/// Check `image` against the golden image at `golden`, allowing up to
+/// `max_pixels` pixels to be off by more than `tolerance`, since different
+/// adapters rasterize a little differently.  If `UPDATE_GOLDEN` is set, the
+/// golden image is written with `update_golden` instead.  If it's missing or
+/// doesn't match, what was drawn is saved next to it as `<name>.actual.png`.
+pub fn check_golden(
+    image: &RgbaImage,
+    golden: impl AsRef<Path>,
+    tolerance: u8,
+    max_pixels: usize,
+) -> Result<()> {
+    let golden = golden.as_ref();
+    if std::env::var_os(UPDATE_GOLDEN).is_some() {
+        return update_golden(image, golden);
+    }
+    let actual = golden.with_extension("actual.png");
+    if !golden.exists() {
+        if let Some(dir) = golden.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        image.save(&actual)?;
+        bail!(
+            "{} is missing (set {} to make it from {})",
+            golden.display(),
+            UPDATE_GOLDEN,
+            actual.display()
+        );
+    }
+    let expected = image::open(golden)
+        .with_context(|| format!("Couldn't open {}", golden.display()))?
+        .to_rgba8();
+    let matches = match diff(image, &expected, tolerance) {
+        Some(d) => d.pixels <= max_pixels,
+        None => false,
+    };
+    if !matches {
+        image.save(&actual)?;
+        bail!(
+            "{} doesn't match {} ({:?})",
+            actual.display(),
+            golden.display(),
+            diff(image, &expected, tolerance)
+        );
+    }
+    Ok(())
+}
+
+/// Save `image` as the golden image at `golden`, replacing any that's there.
+pub fn update_golden(image: &RgbaImage, golden: impl AsRef<Path>) -> Result<()> {
+    let golden = golden.as_ref();
+    if let Some(dir) = golden.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    image
+        .save(golden)
+        .with_context(|| format!("Couldn't save {}", golden.display()))
+}
@@ -3,54 +3,216 @@
 layout(location=0) in vec2 v_tex_coords;
 layout(location=1) in vec3 v_normal;
 layout(location=2) in vec3 v_position;
+layout(location=3) in vec4 v_tangent;
 
 layout(location=0) out vec4 f_color;
 
 layout(set = 0, binding = 0) uniform texture2D t_diffuse;
 layout(set = 0, binding = 1) uniform sampler s_diffuse;
+layout(set = 0, binding = 2) uniform texture2D t_normal;
+layout(set = 0, binding = 3) uniform texture2D t_metallic_roughness;
+layout(set = 0, binding = 4) uniform texture2D t_emissive;
+layout(set = 0, binding = 5)
+uniform Material {
+    vec4 m_base_color;
+    vec4 m_emissive;
+    float m_metallic;
+    float m_roughness;
+    float m_normal_scale;
+};
 layout(set=1, binding=0)
 uniform Uniforms {
-    vec3 u_view_position; // unused
+    vec4 u_view_pos;
     mat4 u_view;
     mat4 u_proj;
 };
 
+// Laid out like lights::LightRaw
 struct Light {
-  vec4 pos;
-  vec4 color;
-  // vec4 dir;
+  vec3 pos;
+  uint kind;
+  vec3 dir;
+  float range;
+  vec3 color;
+  int shadow;
+  vec2 cone;
+  vec2 attenuation;
 };
 
+const uint DIRECTIONAL = 1u;
+const uint POINT = 2u;
+const uint SPOT = 3u;
+
 layout(set=2, binding=0)
-uniform Lights {
-    Light lights[10];
+readonly buffer Lights {
+    Light lights[];
 };
 layout(set=2, binding=1)
 uniform LightsAmbient {
     float ambient;
 };
+// Laid out like clusters::ClusterUniform
+layout(set=2, binding=2)
+uniform ClusterInfo {
+    uvec4 c_dims;
+    vec2 c_screen;
+    float c_near;
+    float c_far;
+};
+// Where each cluster's lights start in cluster_lights, and how many
+layout(set=2, binding=3)
+readonly buffer ClusterRanges {
+    uvec2 cluster_ranges[];
+};
+layout(set=2, binding=4)
+readonly buffer ClusterLights {
+    uint cluster_lights[];
+};
+// One view-projection matrix per shadow map layer
+layout(set=2, binding=5)
+readonly buffer ShadowMatrices {
+    mat4 shadow_matrices[];
+};
+layout(set=2, binding=6) uniform texture2DArray t_shadow;
+layout(set=2, binding=7) uniform samplerShadow s_shadow;
+
+// Which cluster a fragment at pixel frag_coord, depth in front of the
+// camera, is in.  Clusters::cluster_at does the same on the CPU.
+uint cluster_index(vec2 frag_coord, float depth) {
+  vec2 dims = vec2(c_dims.xy);
+  uvec2 tile = uvec2(clamp(frag_coord / c_screen * dims, vec2(0.0), dims - 1.0));
+  float slice = log(max(depth, c_near) / c_near) / log(c_far / c_near) * float(c_dims.z);
+  uint z = uint(clamp(slice, 0.0, float(c_dims.z) - 1.0));
+  return tile.x + c_dims.x * (tile.y + c_dims.y * z);
+}
 
+// Which face of a point light's shadow cube looks along d.
+// shadows::cube_face does the same on the CPU.
+int cube_face(vec3 d) {
+  vec3 a = abs(d);
+  if (a.x >= a.y && a.x >= a.z) {
+    return d.x >= 0.0 ? 0 : 1;
+  } else if (a.y >= a.z) {
+    return d.y >= 0.0 ? 2 : 3;
+  }
+  return d.z >= 0.0 ? 4 : 5;
+}
+
+// How far along the normal to look up shadows from, so surfaces don't
+// shadow themselves
+const float SHADOW_NORMAL_OFFSET = 0.05;
+
+// How much of l's light isn't blocked on its way to p, averaged over the
+// 3x3 shadow map texels around it
+float shadow(Light l, vec3 p, vec3 normal) {
+  if (l.shadow < 0) {
+    return 1.0;
+  }
+  int layer = l.shadow;
+  if (l.kind == POINT) {
+    layer += cube_face(p - l.pos);
+  }
+  vec4 clip = shadow_matrices[layer] * vec4(p + normal * SHADOW_NORMAL_OFFSET, 1.0);
+  vec3 ndc = clip.xyz / clip.w;
+  // Nothing outside the map casts shadows
+  if (clip.w <= 0.0 || abs(ndc.x) > 1.0 || abs(ndc.y) > 1.0 || ndc.z > 1.0) {
+    return 1.0;
+  }
+  vec2 uv = vec2(ndc.x * 0.5 + 0.5, -ndc.y * 0.5 + 0.5);
+  vec2 texel = 1.0 / vec2(textureSize(sampler2DArrayShadow(t_shadow, s_shadow), 0).xy);
+  float lit = 0.0;
+  for (int x = -1; x <= 1; x++) {
+    for (int y = -1; y <= 1; y++) {
+      vec4 at = vec4(uv + vec2(x, y) * texel, float(layer), ndc.z);
+      lit += texture(sampler2DArrayShadow(t_shadow, s_shadow), at);
+    }
+  }
+  return lit / 9.0;
+}
+
+const float PI = 3.14159265359;
+
+// The direction from p towards the light, and how much of its light
+// reaches p.  LightRaw::incoming does the same on the CPU.
+vec3 incoming(Light l, vec3 p, out vec3 to_light) {
+  if (l.kind == DIRECTIONAL) {
+    to_light = -l.dir;
+    return l.color;
+  }
+  vec3 d = l.pos - p;
+  float dist = length(d);
+  to_light = d / max(dist, 0.0001);
+  float falloff = 1.0 / (1.0 + l.attenuation.x * dist + l.attenuation.y * dist * dist);
+  if (l.range > 0.0) {
+    float r = clamp(1.0 - pow(dist / l.range, 4.0), 0.0, 1.0);
+    falloff *= r * r;
+  }
+  if (l.kind == SPOT) {
+    falloff *= smoothstep(l.cone.y, l.cone.x, dot(-to_light, l.dir));
+  }
+  return l.color * falloff;
+}
+
+// How much light bounces off at each angle, for the halfway vector h
+// between the light and the eye
+vec3 fresnel(float cos_theta, vec3 f0) {
+  return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// How many microfacets face along h (GGX)
+float distribution(float n_dot_h, float roughness) {
+  float a2 = pow(roughness, 4.0);
+  float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+  return a2 / max(PI * d * d, 0.0001);
+}
+
+// How many of them aren't shadowed by their neighbours (Smith-Schlick)
+float geometry(float n_dot_v, float n_dot_l, float roughness) {
+  float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+  return n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+}
 
 void main() {
+  vec4 object_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords) * m_base_color;
+  vec3 albedo = object_color.xyz;
+  vec4 metallic_roughness = texture(sampler2D(t_metallic_roughness, s_diffuse), v_tex_coords);
+  float metallic = clamp(m_metallic * metallic_roughness.b, 0.0, 1.0);
+  float roughness = clamp(m_roughness * metallic_roughness.g, 0.04, 1.0);
+  vec3 emissive = m_emissive.xyz * texture(sampler2D(t_emissive, s_diffuse), v_tex_coords).xyz;
+
+  // Bend the normal by the normal map, in the surface's tangent space
   vec3 normal = normalize(v_normal);
-  vec4 object_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords);
-  vec3 view_dir = normalize(u_view_position - v_position);
+  vec3 surface_normal = normal;
+  vec3 tangent = normalize(v_tangent.xyz - normal * dot(normal, v_tangent.xyz));
+  vec3 bitangent = cross(normal, tangent) * v_tangent.w;
+  vec3 bump = texture(sampler2D(t_normal, s_diffuse), v_tex_coords).xyz * 2.0 - 1.0;
+  bump.xy *= m_normal_scale;
+  normal = normalize(mat3(tangent, bitangent, normal) * bump);
+
+  vec3 view_dir = normalize(u_view_pos.xyz - v_position);
+  float n_dot_v = max(dot(normal, view_dir), 0.0001);
+  // Insulators all reflect about 4% head on; metals tint their reflections
+  vec3 f0 = mix(vec3(0.04), albedo, metallic);
 
-  vec3 result = ambient*object_color.xyz;
-  for (int i = 0; i < 10; i++) {
+  vec3 result = ambient*albedo + emissive;
+  // Only the lights that reach this fragment's cluster
+  float depth = -(u_view * vec4(v_position, 1.0)).z;
+  uvec2 range = cluster_ranges[cluster_index(gl_FragCoord.xy, depth)];
+  for (uint i = range.x; i < range.x + range.y; i++) {
+    Light light = lights[cluster_lights[i]];
     float light_ambient = 0.1;
-    // Point-light specific; change if directional lights, spotlights are used
-    // to branch on e.g. position.w == 0 (directional) or direction.w == 0 (point) or else spot
-    vec3 light_color = lights[i].color.xyz;
-    vec3 light_position = lights[i].pos.xyz;
-    vec3 light_dir = normalize(light_position - v_position);
-    float diffuse_strength = max(dot(normal, light_dir), 0.0);
-    vec3 diffuse_color = light_color * diffuse_strength;
-    vec3 ambient_color = light_color * light_ambient;
+    vec3 light_dir;
+    vec3 light_color = incoming(light, v_position, light_dir);
+    float lit = shadow(light, v_position, surface_normal);
     vec3 half_dir = normalize(view_dir + light_dir);
-    float specular_strength = pow(max(dot(normal, half_dir), 0.0), 32);
-    vec3 specular_color = specular_strength * light_color;
-    result += (ambient_color + diffuse_color + specular_color) * object_color.xyz;
+    float n_dot_l = max(dot(normal, light_dir), 0.0);
+    vec3 f = fresnel(max(dot(half_dir, view_dir), 0.0), f0);
+    vec3 specular = distribution(max(dot(normal, half_dir), 0.0), roughness)
+      * geometry(n_dot_v, n_dot_l, roughness) * f / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+    // Whatever isn't reflected is scattered, unless it's metal
+    vec3 diffuse = (1.0 - f) * (1.0 - metallic) * albedo;
+    vec3 ambient_color = light_color * light_ambient * albedo;
+    result += ambient_color + (diffuse + specular * PI) * light_color * n_dot_l * lit;
   }
   f_color = vec4(result, object_color.a);
 }
@@ -7,11 +7,13 @@ layout(location=2) in vec3 a_normal;
 layout(location=0) out vec2 v_tex_coords;
 layout(location=1) out vec3 v_normal;
 layout(location=2) out vec3 v_position;
+layout(location=3) out vec4 v_tangent;
 
 layout(location=5) in vec4 model_matrix_0;
 layout(location=6) in vec4 model_matrix_1;
 layout(location=7) in vec4 model_matrix_2;
 layout(location=8) in vec4 model_matrix_3;
+layout(location=9) in vec4 a_tangent;
 
 layout(set=1, binding=0)
 uniform Uniforms {
@@ -30,6 +32,7 @@ void main() {
     mat3 normal_matrix = mat3(transpose(inverse(model_matrix)));
 
     v_normal = normal_matrix * a_normal.xyz;
+    v_tangent = vec4(mat3(model_matrix) * a_tangent.xyz, a_tangent.w);
     v_tex_coords = a_tex_coords;
     vec4 model_space = model_matrix * vec4(a_position.xyz, 1.0);
     v_position = model_space.xyz;
@@ -0,0 +1,171 @@
+//! Shadow maps.  Every light that casts shadows gets layers of a depth
+//! texture array, drawn from the light's point of view before the main
+//! pass: one for a directional light or a spotlight, and one for each face
+//! of a cube around a point light.  `shader.frag` checks each fragment
+//! against its light's layer to see whether anything is in the way.
+
+use crate::camera::GameCamera;
+use crate::geom::*;
+use crate::lights::{Light, LightKind};
+use crate::render::OPENGL_TO_WGPU_MATRIX;
+use cgmath::{Deg, Rad};
+
+/// How many texels across each shadow map is.
+pub const SHADOW_MAP_SIZE: u32 = 1024;
+/// How far in front of the camera directional lights cast shadows.
+pub const SHADOW_DISTANCE: f32 = 40.0;
+/// How close to a point light or spotlight its shadow maps start.
+const SHADOW_NEAR: f32 = 0.05;
+
+/// How many shadow maps `light` needs.
+pub fn layer_count(light: &Light) -> usize {
+    match light.kind {
+        _ if !light.shadows => 0,
+        LightKind::Point { .. } => 6,
+        LightKind::Directional { .. } | LightKind::Spot { .. } => 1,
+    }
+}
+
+/// The first shadow map layer of each of `lights`, or -1 for those without
+/// shadows.  Layers are handed out in order, as `matrices` lays them out.
+pub fn first_layers(lights: &[Light]) -> Vec<i32> {
+    let mut next = 0;
+    lights
+        .iter()
+        .map(|l| match layer_count(l) {
+            0 => -1,
+            n => {
+                next += n;
+                (next - n) as i32
+            }
+        })
+        .collect()
+}
+
+/// The view-projection matrix of every shadow map layer of `lights`, as
+/// seen by `camera`, in layer order.
+pub fn matrices(lights: &[Light], camera: &GameCamera) -> Vec<Mat4> {
+    lights
+        .iter()
+        .flat_map(|l| light_matrices(l, camera))
+        .collect()
+}
+
+/// The view-projection matrices of `light`'s shadow maps: from world space
+/// to wgpu's clip space, where depth runs from 0 to 1.
+pub fn light_matrices(light: &Light, camera: &GameCamera) -> Vec<Mat4> {
+    if !light.shadows {
+        return vec![];
+    }
+    let far = if light.range > 0.0 {
+        light.range
+    } else {
+        camera.zfar
+    };
+    let projected = |proj: Mat4, view: Mat4| OPENGL_TO_WGPU_MATRIX * proj * view;
+    match light.kind {
+        LightKind::Directional { dir } => {
+            let dir = dir.normalize();
+            let (center, radius) = view_sphere(camera, camera.zfar.min(SHADOW_DISTANCE));
+            // Only move the map a whole texel at a time, so that shadow
+            // edges don't crawl as the camera moves
+            let texel = 2.0 * radius / SHADOW_MAP_SIZE as f32;
+            let turn = Mat4::look_to_rh(Pos3::origin(), dir, up_for(dir));
+            let c = turn.transform_point(center);
+            let snapped = Pos3::new(
+                (c.x / texel).floor() * texel,
+                (c.y / texel).floor() * texel,
+                c.z,
+            );
+            let center = turn.transpose().transform_point(snapped);
+            // Back far enough to catch anything between the light and the
+            // view that could cast a shadow into it
+            let eye = center - dir * radius * 2.0;
+            let view = Mat4::look_to_rh(eye, dir, up_for(dir));
+            let proj = cgmath::ortho(-radius, radius, -radius, radius, 0.0, radius * 3.0);
+            vec![projected(proj, view)]
+        }
+        LightKind::Point { pos } => {
+            let proj = cgmath::perspective(Deg(90.0), 1.0, SHADOW_NEAR, far);
+            CUBE_FACES
+                .iter()
+                .map(|&dir| {
+                    let dir = Vec3::from(dir);
+                    projected(proj, Mat4::look_to_rh(pos, dir, up_for(dir)))
+                })
+                .collect()
+        }
+        LightKind::Spot {
+            pos, dir, outer, ..
+        } => {
+            let dir = dir.normalize();
+            let fovy = Rad((outer.0 * 2.0).min(170f32.to_radians()));
+            let proj = cgmath::perspective(fovy, 1.0, SHADOW_NEAR, far);
+            vec![projected(proj, Mat4::look_to_rh(pos, dir, up_for(dir)))]
+        }
+    }
+}
+
+/// The way each face of a point light's shadow cube looks, in layer order.
+const CUBE_FACES: [[f32; 3]; 6] = [
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+];
+
+/// Which face of a point light's shadow cube is looking along `dir`, by
+/// its largest axis.  Mirrors `cube_face` in `shader.frag`.
+pub fn cube_face(dir: Vec3) -> usize {
+    let a = Vec3::new(dir.x.abs(), dir.y.abs(), dir.z.abs());
+    if a.x >= a.y && a.x >= a.z {
+        if dir.x >= 0.0 {
+            0
+        } else {
+            1
+        }
+    } else if a.y >= a.z {
+        if dir.y >= 0.0 {
+            2
+        } else {
+            3
+        }
+    } else if dir.z >= 0.0 {
+        4
+    } else {
+        5
+    }
+}
+
+/// Any direction other than `dir` to use as up when looking along it.
+fn up_for(dir: Vec3) -> Vec3 {
+    if dir.y.abs() > 0.99 {
+        Vec3::unit_z()
+    } else {
+        Vec3::unit_y()
+    }
+}
+
+/// A sphere around the part of `camera`'s view up to `far` away.
+fn view_sphere(camera: &GameCamera, far: f32) -> (Pos3, f32) {
+    let (view, _) = camera.build_view_projection_matrix();
+    let to_world = view.invert().unwrap();
+    let half_h = (Deg(camera.fovy) / 2.0).tan();
+    let half_w = half_h * camera.aspect;
+    let mut corners = vec![];
+    for &d in &[camera.znear, far] {
+        for &(sx, sy) in &[(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            // The camera looks down -z
+            let p = Pos3::new(sx * d * half_w, sy * d * half_h, -d);
+            corners.push(to_world.transform_point(p));
+        }
+    }
+    let center = Pos3::centroid(&corners);
+    let radius = corners
+        .iter()
+        .map(|c| (c - center).magnitude())
+        .fold(0.0, f32::max);
+    (center, radius)
+}
@@ -0,0 +1,200 @@
+use engine3d::anim::{blend, Clip, Key, Pose, Rig, Skeleton, SkeletonBone, State, Track, BONE_MAX};
+use engine3d::geom::*;
+use std::path::Path;
+
+fn assert_close(a: Pos3, b: Pos3) {
+    assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+}
+
+fn about_z(degrees: f32) -> Quat {
+    Quat::from_angle_z(cgmath::Deg(degrees))
+}
+
+/// A shoulder at the origin, an elbow a unit along x from it, and a hand a
+/// unit further on.
+fn arm() -> Skeleton {
+    let bone = |name: &str, parent: Option<usize>, x: f32| SkeletonBone {
+        name: name.to_string(),
+        parent,
+        rest: Pose::new(Vec3::new(x, 0.0, 0.0), Quat::one()),
+    };
+    Skeleton {
+        bones: vec![
+            bone("shoulder", None, 0.0),
+            bone("elbow", Some(0), 1.0),
+            bone("hand", Some(1), 1.0),
+        ],
+    }
+}
+
+fn key(time: f32, degrees: f32) -> Key {
+    Key {
+        time,
+        pose: Pose::new(Vec3::new(1.0, 0.0, 0.0), about_z(degrees)),
+    }
+}
+
+/// Bends the elbow up a quarter turn over a second.
+fn wave() -> Clip {
+    Clip {
+        name: "wave".to_string(),
+        duration: 1.0,
+        looping: true,
+        tracks: vec![Track {
+            bone: "elbow".to_string(),
+            keys: vec![key(0.0, 0.0), key(1.0, 90.0)],
+        }],
+    }
+}
+
+fn hand(skeleton: &Skeleton, local: &[Pose]) -> Pos3 {
+    Pos3::from_vec(skeleton.model_poses(local)[2].translation)
+}
+
+#[test]
+fn clips_slerp_between_keys() {
+    let arm = arm();
+    let clip = wave();
+    let halfway = clip.sample(&arm, 0.5);
+    let h = 0.5f32.sqrt();
+    assert_close(hand(&arm, &halfway), Pos3::new(1.0 + h, h, 0.0));
+    // Bones without a track stay at rest
+    assert_eq!(halfway[0], arm.bones[0].rest);
+
+    // Looping clips wrap around; others hold their last key
+    assert_close(hand(&arm, &clip.sample(&arm, 1.5)), hand(&arm, &halfway));
+    let once = Clip {
+        looping: false,
+        ..wave()
+    };
+    assert_close(
+        hand(&arm, &once.sample(&arm, 3.0)),
+        Pos3::new(1.0, 1.0, 0.0),
+    );
+}
+
+#[test]
+fn skinning_carries_vertices_along_with_their_bones() {
+    let arm = arm();
+    for p in arm.skin(&arm.rest()) {
+        assert_close(
+            p.transform_point(Pos3::new(0.3, 0.2, 0.1)),
+            Pos3::new(0.3, 0.2, 0.1),
+        );
+    }
+
+    // Halfway along the forearm, with the elbow bent straight up
+    let once = Clip {
+        looping: false,
+        ..wave()
+    };
+    let bent = once.sample(&arm, 1.0);
+    let skin = arm.skin(&bent);
+    let forearm = Pos3::new(1.5, 0.0, 0.0);
+    assert_close(skin[1].transform_point(forearm), Pos3::new(1.0, 0.5, 0.0));
+    // The upper arm doesn't move
+    assert_close(skin[0].transform_point(forearm), forearm);
+
+    // That vertex mostly follows the forearm
+    let (ids, weights) = arm.segments().weights(forearm);
+    assert_eq!(ids[0], 1);
+    assert!(weights[0] > 0.9);
+    assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn states_fade_from_one_clip_to_the_next() {
+    let arm = arm();
+    let rig = Rig {
+        skeleton: arm.clone(),
+        clips: vec![
+            Clip {
+                name: "idle".to_string(),
+                duration: 1.0,
+                looping: true,
+                tracks: vec![],
+            },
+            wave(),
+        ],
+    };
+    let wave = rig.clip("wave").unwrap();
+    let mut state = State::new(rig.clip("idle").unwrap());
+    state.update(0.3);
+    state.play(wave, 0.5);
+    state.update(0.25);
+    // Halfway through the fade, halfway between standing still and a
+    // quarter of the way through waving
+    let expected = blend(&arm.rest(), &rig.clips[wave].sample(&arm, 0.25), 0.5);
+    assert_eq!(state.pose(&rig), expected);
+    assert_close(
+        hand(&arm, &state.pose(&rig)),
+        hand(&arm, &rig.clips[wave].sample(&arm, 0.125)),
+    );
+
+    state.update(0.5);
+    assert_eq!(state.pose(&rig), rig.clips[wave].sample(&arm, 0.75));
+    // Playing the same clip again doesn't start it over
+    state.play(wave, 0.5);
+    assert_eq!(state.time, 0.75);
+}
+
+#[test]
+fn rigs_load_from_json() {
+    let dir = std::env::temp_dir().join(format!("engine3d-anim-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let good = dir.join("arm.rig.json");
+    std::fs::write(
+        &good,
+        r#"{
+            "skeleton": { "bones": [
+                { "name": "shoulder" },
+                { "name": "elbow", "parent": 0,
+                  "rest": { "translation": { "x": 1.0, "y": 0.0, "z": 0.0 } } }
+            ] },
+            "clips": [
+                { "name": "wave", "duration": 1.0, "tracks": [
+                    { "bone": "elbow", "keys": [
+                        { "time": 0.0 },
+                        { "time": 1.0,
+                          "rotation": { "s": 0.7071068, "v": { "x": 0.0, "y": 0.0, "z": 0.7071068 } } }
+                    ] }
+                ] }
+            ]
+        }"#,
+    )
+    .unwrap();
+    let rig = Rig::load(&good).unwrap();
+    assert_eq!(rig.skeleton.len(), 2);
+    assert_eq!(
+        rig.skeleton.bones[1].rest.translation,
+        Vec3::new(1.0, 0.0, 0.0)
+    );
+    assert!(!rig.clips[0].looping);
+    let end = rig.clips[0].sample(&rig.skeleton, 1.0);
+    assert!((end[1].rotation - about_z(90.0)).magnitude() < 1e-5);
+    assert_eq!(
+        Rig::path_for("content/arm.obj"),
+        Path::new("content/arm.rig.json")
+    );
+
+    let bad = dir.join("backwards.rig.json");
+    std::fs::write(
+        &bad,
+        r#"{ "skeleton": { "bones": [ { "name": "elbow", "parent": 1 }, { "name": "shoulder" } ] } }"#,
+    )
+    .unwrap();
+    assert!(Rig::load(&bad).is_err());
+
+    // No more bones than the shader has room for
+    let bones: Vec<String> = (0..=BONE_MAX)
+        .map(|i| format!(r#"{{ "name": "b{}" }}"#, i))
+        .collect();
+    let big = dir.join("big.rig.json");
+    std::fs::write(
+        &big,
+        format!(r#"{{ "skeleton": {{ "bones": [{}] }} }}"#, bones.join(",")),
+    )
+    .unwrap();
+    assert!(Rig::load(&big).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,176 @@
+use engine3d::collision::*;
+use engine3d::geom::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+fn random_rotation(rng: &mut impl Rng) -> Mat3 {
+    let q = Quat::new(
+        rng.gen_range(-1.0..1.0),
+        rng.gen_range(-1.0..1.0),
+        rng.gen_range(-1.0..1.0),
+        rng.gen_range(-1.0..1.0),
+    );
+    Mat3::from(q.normalize())
+}
+
+fn random_boxes(rng: &mut impl Rng, n: usize, spread: f32) -> Vec<Box> {
+    (0..n)
+        .map(|i| Box {
+            c: Pos3::new(
+                rng.gen_range(-spread..spread),
+                rng.gen_range(-spread..spread),
+                rng.gen_range(-spread..spread),
+            ),
+            // Leave every third box axis-aligned, like the wall pieces
+            axes: if i % 3 == 0 {
+                Mat3::one()
+            } else {
+                random_rotation(rng)
+            },
+            half_sizes: Vec3::new(
+                rng.gen_range(0.1..2.0),
+                rng.gen_range(0.1..2.0),
+                rng.gen_range(0.1..2.0),
+            ),
+        })
+        .collect()
+}
+
+fn random_spheres(rng: &mut impl Rng, n: usize, spread: f32) -> Vec<Sphere> {
+    (0..n)
+        .map(|_| Sphere {
+            c: Pos3::new(
+                rng.gen_range(-spread..spread),
+                rng.gen_range(-spread..spread),
+                rng.gen_range(-spread..spread),
+            ),
+            r: rng.gen_range(0.1..2.0),
+        })
+        .collect()
+}
+
+fn key(contacts: &[Contact<usize>]) -> Vec<(usize, usize, Vec3)> {
+    contacts.iter().map(|c| (c.a, c.b, c.mtv)).collect()
+}
+
+#[test]
+fn boxes_match_brute_force() {
+    let mut rng = ChaCha8Rng::seed_from_u64(4);
+    for &spread in &[2.0, 8.0, 30.0] {
+        let boxes = random_boxes(&mut rng, 200, spread);
+        let mut fast = vec![];
+        let mut brute = vec![];
+        gather_contacts_aa(&boxes, &mut fast);
+        gather_contacts_aa_brute(&boxes, &mut brute);
+        assert!(!brute.is_empty());
+        assert_eq!(key(&fast), key(&brute));
+
+        let others = random_boxes(&mut rng, 150, spread);
+        fast.clear();
+        brute.clear();
+        gather_contacts_ab(&boxes, &others, &mut fast);
+        gather_contacts_ab_brute(&boxes, &others, &mut brute);
+        assert!(!brute.is_empty());
+        assert_eq!(key(&fast), key(&brute));
+    }
+}
+
+#[test]
+fn boxes_barely_touching_match_brute_force() {
+    // The OBB test pads its projections, so boxes a little way apart still
+    // touch.  Push pairs apart until they stop touching and check the
+    // broad phase keeps the last pair that did.
+    let mut rng = ChaCha8Rng::seed_from_u64(5);
+    let mut boxes = vec![];
+    for i in 0..200 {
+        let c = Pos3::new(i as f32 * 20.0, 0.0, 0.0);
+        let a = Box {
+            c,
+            axes: random_rotation(&mut rng),
+            half_sizes: Vec3::new(
+                rng.gen_range(0.1..2.0),
+                rng.gen_range(0.1..2.0),
+                rng.gen_range(0.1..2.0),
+            ),
+        };
+        let mut b = Box {
+            axes: random_rotation(&mut rng),
+            ..a
+        };
+        let dir = Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        )
+        .normalize();
+        let (mut near, mut far) = (0.0, 10.0);
+        for _ in 0..30 {
+            let mid = (near + far) / 2.0;
+            b.c = c + dir * mid;
+            if a.touching(&b) {
+                near = mid;
+            } else {
+                far = mid;
+            }
+        }
+        b.c = c + dir * near;
+        boxes.push(a);
+        boxes.push(b);
+    }
+    let mut fast = vec![];
+    let mut brute = vec![];
+    gather_contacts_aa(&boxes, &mut fast);
+    gather_contacts_aa_brute(&boxes, &mut brute);
+    assert_eq!(brute.len(), 200);
+    assert_eq!(key(&fast), key(&brute));
+}
+
+#[test]
+fn spheres_and_planes_match_brute_force() {
+    let mut rng = ChaCha8Rng::seed_from_u64(6);
+    let spheres = random_spheres(&mut rng, 300, 10.0);
+    let mut fast = vec![];
+    let mut brute = vec![];
+    gather_contacts_aa(&spheres, &mut fast);
+    gather_contacts_aa_brute(&spheres, &mut brute);
+    assert!(!brute.is_empty());
+    assert_eq!(key(&fast), key(&brute));
+
+    let planes = vec![
+        Plane {
+            n: Vec3::unit_y(),
+            d: 0.0,
+        },
+        Plane {
+            n: Vec3::new(1.0, 1.0, 0.0).normalize(),
+            d: 3.0,
+        },
+    ];
+    fast.clear();
+    brute.clear();
+    gather_contacts_ab(&spheres, &planes, &mut fast);
+    gather_contacts_ab_brute(&spheres, &planes, &mut brute);
+    assert!(!brute.is_empty());
+    assert_eq!(key(&fast), key(&brute));
+
+    let boxes = random_boxes(&mut rng, 100, 10.0);
+    fast.clear();
+    brute.clear();
+    gather_contacts_ab(&boxes, &planes, &mut fast);
+    gather_contacts_ab_brute(&boxes, &planes, &mut brute);
+    assert!(!brute.is_empty());
+    assert_eq!(key(&fast), key(&brute));
+}
+
+#[test]
+fn empty_inputs() {
+    let boxes: Vec<Box> = vec![];
+    let mut contacts = vec![];
+    gather_contacts_aa(&boxes, &mut contacts);
+    gather_contacts_ab(
+        &boxes,
+        &random_boxes(&mut ChaCha8Rng::seed_from_u64(7), 3, 1.0),
+        &mut contacts,
+    );
+    assert!(contacts.is_empty());
+}
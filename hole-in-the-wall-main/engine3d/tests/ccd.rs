@@ -0,0 +1,132 @@
+use engine3d::collision::*;
+use engine3d::geom::*;
+use engine3d::DT;
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+}
+
+fn cube(c: Pos3, half: f32) -> Box {
+    Box {
+        c,
+        axes: Mat3::one(),
+        half_sizes: Vec3::new(half, half, half),
+    }
+}
+
+#[test]
+fn fast_box_tunnels_past_discrete_checks_but_not_sweeps() {
+    let player = [cube(Pos3::new(0.0, 0.5, 0.0), 0.5)];
+    // A thin wall panel moving 10 units in one step, starting 4 units away
+    let wall = [Box {
+        c: Pos3::new(0.0, 1.0, 4.5),
+        axes: Mat3::one(),
+        half_sizes: Vec3::new(3.0, 3.0, 0.1),
+    }];
+    let wall_vels = [Vec3::new(0.0, 0.0, -10.0 / DT)];
+    let mut contacts = vec![];
+    gather_contacts_ab(&player, &wall, &mut contacts);
+    let mut moved = wall;
+    moved[0].translate(wall_vels[0] * DT);
+    gather_contacts_ab(&player, &moved, &mut contacts);
+    assert!(contacts.is_empty());
+
+    let mut impacts = vec![];
+    gather_impacts_dyn_dyn(
+        &player,
+        &[Vec3::zero()],
+        &wall,
+        &wall_vels,
+        DT,
+        &mut impacts,
+    );
+    assert_eq!(impacts.len(), 1);
+    let i = impacts[0];
+    assert_eq!((i.a, i.b), (0, 0));
+    // The wall's near face reaches the player's back face after 3.9 units
+    assert_close(i.toi, 0.39);
+    assert_close(i.hit.normal.z, -1.0);
+
+    // Moving with the wall, the player never gets hit
+    impacts.clear();
+    gather_impacts_dyn_dyn(&player, &wall_vels, &wall, &wall_vels, DT, &mut impacts);
+    assert!(impacts.is_empty());
+    // Too slow to get there this step
+    impacts.clear();
+    gather_impacts_dyn_dyn(
+        &player,
+        &[Vec3::zero()],
+        &wall,
+        &[Vec3::new(0.0, 0.0, -3.0 / DT)],
+        DT,
+        &mut impacts,
+    );
+    assert!(impacts.is_empty());
+}
+
+#[test]
+fn earliest_impact_among_many() {
+    let boxes: Vec<Box> = (0..5)
+        .map(|i| cube(Pos3::new(i as f32 * 3.0, 0.0, 0.0), 0.5))
+        .collect();
+    let bullets = [cube(Pos3::new(-2.0, 0.0, 0.0), 0.25)];
+    let mut impacts = vec![];
+    gather_impacts_dyn_dyn(
+        &bullets,
+        &[Vec3::new(20.0 / DT, 0.0, 0.0)],
+        &boxes,
+        &vec![Vec3::zero(); boxes.len()],
+        DT,
+        &mut impacts,
+    );
+    // Passes through every box, first reaching the nearest
+    assert_eq!(impacts.len(), 5);
+    let first = impacts
+        .iter()
+        .min_by(|a, b| a.toi.partial_cmp(&b.toi).unwrap())
+        .unwrap();
+    assert_eq!(first.b, 0);
+    assert_close(first.toi, 1.25 / 20.0);
+}
+
+#[test]
+fn fast_box_hits_plane() {
+    let floor = [Plane {
+        n: Vec3::unit_y(),
+        d: 0.0,
+    }];
+    let falling = [Box {
+        c: Pos3::new(0.0, 3.0, 0.0),
+        axes: Mat3::from(Quat::from_angle_z(cgmath::Deg(45.0))),
+        half_sizes: Vec3::new(1.0, 1.0, 1.0),
+    }];
+    let mut impacts = vec![];
+    gather_impacts_dyn_stat(
+        &falling,
+        &[Vec3::new(0.0, -8.0 / DT, 0.0)],
+        &floor,
+        DT,
+        &mut impacts,
+    );
+    assert_eq!(impacts.len(), 1);
+    assert_close(impacts[0].toi, (3.0 - 2.0_f32.sqrt()) / 8.0);
+    assert_close(impacts[0].hit.point.y, 0.0);
+
+    // Already resting on it
+    let resting = [cube(Pos3::new(0.0, 0.99, 0.0), 1.0)];
+    impacts.clear();
+    gather_impacts_dyn_stat(&resting, &[Vec3::zero()], &floor, DT, &mut impacts);
+    assert_eq!(impacts.len(), 1);
+    assert_eq!(impacts[0].toi, 0.0);
+
+    // Moving up and away
+    impacts.clear();
+    gather_impacts_dyn_stat(
+        &falling,
+        &[Vec3::new(0.0, 8.0 / DT, 0.0)],
+        &floor,
+        DT,
+        &mut impacts,
+    );
+    assert!(impacts.is_empty());
+}
@@ -0,0 +1,170 @@
+use engine3d::camera::GameCamera;
+use engine3d::clusters::{bounding_sphere, ClusterGrid, ClusterUniform, Clusters, CLUSTER_DIMS};
+use engine3d::geom::*;
+use engine3d::lights::Light;
+
+const WIDTH: u32 = 1600;
+const HEIGHT: u32 = 900;
+
+fn camera() -> GameCamera {
+    GameCamera::new(WIDTH as f32 / HEIGHT as f32)
+}
+
+/// The pixel `p` is drawn at, and how far in front of the camera it is.
+fn project(camera: &GameCamera, p: Pos3) -> (f32, f32, f32) {
+    let (view, proj) = camera.build_view_projection_matrix();
+    let eye = view.transform_point(p);
+    let clip = proj * eye.to_homogeneous();
+    let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+    (
+        (x + 1.0) / 2.0 * WIDTH as f32,
+        (1.0 - y) / 2.0 * HEIGHT as f32,
+        -eye.z,
+    )
+}
+
+fn lights() -> Vec<Light> {
+    let white = Vec3::new(1.0, 1.0, 1.0);
+    vec![
+        Light::directed(-Vec3::unit_y(), white),
+        Light::point(Pos3::origin(), white).with_range(1.0),
+        Light::point(Pos3::new(4.0, 1.0, 6.0), white).with_range(3.0),
+        // Behind the camera
+        Light::point(Pos3::new(0.0, 5.0, -20.0), white).with_range(2.0),
+        Light::spot(
+            Pos3::new(-3.0, 4.0, 2.0),
+            Vec3::new(0.3, -1.0, 0.0),
+            cgmath::Deg(15.0),
+            cgmath::Deg(25.0),
+            white,
+        )
+        .with_range(6.0),
+    ]
+}
+
+#[test]
+fn lights_are_binned_into_the_clusters_they_reach() {
+    let camera = camera();
+    let uniform = ClusterUniform::new(&camera, CLUSTER_DIMS, WIDTH, HEIGHT);
+    let clusters = Clusters::build(&ClusterGrid::new(&camera, CLUSTER_DIMS), &camera, &lights());
+    let count = (CLUSTER_DIMS[0] * CLUSTER_DIMS[1] * CLUSTER_DIMS[2]) as usize;
+    assert_eq!(clusters.ranges.len(), count);
+
+    // The sun reaches everywhere, and the light behind the camera nowhere
+    for c in 0..count {
+        assert_eq!(clusters.lights(c)[0], 0);
+        assert!(!clusters.lights(c).contains(&3));
+    }
+    // The small light at the origin is only in the clusters around it
+    let (x, y, depth) = project(&camera, Pos3::origin());
+    let here = clusters.cluster_at(&uniform, x, y, depth);
+    assert!(clusters.lights(here).contains(&1));
+    let with_it = (0..count)
+        .filter(|&c| clusters.lights(c).contains(&1))
+        .count();
+    assert!(with_it < 20, "{} clusters", with_it);
+    let corner = clusters.cluster_at(&uniform, 0.0, 0.0, depth);
+    assert!(!clusters.lights(corner).contains(&1));
+}
+
+#[test]
+fn every_lit_point_has_its_lights_in_its_cluster() {
+    let camera = camera();
+    let uniform = ClusterUniform::new(&camera, CLUSTER_DIMS, WIDTH, HEIGHT);
+    let lights = lights();
+    let clusters = Clusters::build(&ClusterGrid::new(&camera, CLUSTER_DIMS), &camera, &lights);
+    let mut checked = 0;
+    for i in -20..=20 {
+        for j in -5..=15 {
+            for k in -10..=20 {
+                let p = Pos3::new(i as f32 * 0.4, j as f32 * 0.4, k as f32 * 0.4);
+                let (x, y, depth) = project(&camera, p);
+                let on_screen = (0.0..WIDTH as f32).contains(&x)
+                    && (0.0..HEIGHT as f32).contains(&y)
+                    && depth > camera.znear;
+                if !on_screen {
+                    continue;
+                }
+                let cluster = clusters.cluster_at(&uniform, x, y, depth);
+                for (l, light) in lights.iter().enumerate() {
+                    if light.incoming(p).1 != Vec3::zero() {
+                        checked += 1;
+                        assert!(
+                            clusters.lights(cluster).contains(&(l as u32)),
+                            "light {} reaches {:?} but isn't in cluster {}",
+                            l,
+                            p,
+                            cluster
+                        );
+                    }
+                }
+            }
+        }
+    }
+    assert!(checked > 1000);
+}
+
+#[test]
+fn spotlight_bounds_hold_their_cones() {
+    for &(outer, range) in &[(10.0f32, 5.0), (30.0, 2.0), (60.0, 4.0), (85.0, 1.0)] {
+        let pos = Pos3::new(1.0, 2.0, 3.0);
+        let dir = Vec3::new(0.0, -1.0, 1.0).normalize();
+        let light = Light::spot(
+            pos,
+            dir,
+            cgmath::Deg(outer / 2.0),
+            cgmath::Deg(outer),
+            Vec3::new(1.0, 1.0, 1.0),
+        )
+        .with_range(range);
+        let (c, r) = bounding_sphere(&light).unwrap();
+        // Points all over the cone, out to its range
+        let (a, b) = (dir.cross(Vec3::unit_x()).normalize(), Vec3::unit_x());
+        for step in 0..=10 {
+            let angle = outer.to_radians() * step as f32 / 10.0;
+            for turn in 0..8 {
+                let t = turn as f32 * PI / 4.0;
+                let side = a * t.cos() + b * t.sin();
+                let p = pos + (dir * angle.cos() + side * angle.sin()) * range;
+                assert!((p - c).magnitude() <= r + 1e-4, "{} {}", outer, range);
+            }
+        }
+        assert!((pos - c).magnitude() <= r + 1e-4);
+    }
+    // Lights without a range reach everywhere
+    let bulb = Light::point(Pos3::origin(), Vec3::new(1.0, 1.0, 1.0));
+    assert_eq!(bounding_sphere(&bulb), None);
+}
+
+#[test]
+fn spotlights_narrower_outside_than_inside_are_bounded_as_drawn() {
+    let pos = Pos3::new(0.0, 4.0, 0.0);
+    let light = Light::spot(
+        pos,
+        -Vec3::unit_y(),
+        cgmath::Deg(40.0),
+        cgmath::Deg(10.0),
+        Vec3::new(1.0, 1.0, 1.0),
+    )
+    .with_range(5.0);
+    let (c, r) = bounding_sphere(&light).unwrap();
+    // The shader lights the whole inner cone
+    let (sin, cos) = 39.0f32.to_radians().sin_cos();
+    let edge = pos + Vec3::new(sin, -cos, 0.0) * 4.9;
+    assert_ne!(light.incoming(edge).1, Vec3::zero());
+    assert!((edge - c).magnitude() <= r + 1e-4);
+}
+
+#[test]
+fn cluster_grids_are_rebuilt_for_new_projections() {
+    let mut camera = camera();
+    let grid = ClusterGrid::new(&camera, CLUSTER_DIMS);
+    // Moving the camera doesn't change the clusters' boxes
+    camera.eye = Pos3::new(3.0, 2.0, 1.0);
+    assert!(grid.fits(&camera));
+    camera.aspect = 4.0 / 3.0;
+    assert!(!grid.fits(&camera));
+    camera.aspect = WIDTH as f32 / HEIGHT as f32;
+    camera.fovy += 10.0;
+    assert!(!grid.fits(&camera));
+}
@@ -0,0 +1,212 @@
+use engine3d::geom::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+const CASES: usize = 500;
+
+fn random_pos(rng: &mut impl Rng, spread: f32) -> Pos3 {
+    Pos3::new(
+        rng.gen_range(-spread..spread),
+        rng.gen_range(-spread..spread),
+        rng.gen_range(-spread..spread),
+    )
+}
+
+fn random_dir(rng: &mut impl Rng) -> Vec3 {
+    loop {
+        let v = random_pos(rng, 1.0).to_vec();
+        if v.magnitude2() > 0.01 {
+            return v.normalize();
+        }
+    }
+}
+
+fn random_sphere(rng: &mut impl Rng) -> Sphere {
+    Sphere {
+        c: random_pos(rng, 2.0),
+        r: rng.gen_range(0.1..1.5),
+    }
+}
+
+fn random_box(rng: &mut impl Rng) -> Box {
+    let q = Quat::new(
+        rng.gen_range(-1.0..1.0),
+        rng.gen_range(-1.0..1.0),
+        rng.gen_range(-1.0..1.0),
+        rng.gen_range(-1.0..1.0),
+    );
+    Box {
+        c: random_pos(rng, 2.0),
+        axes: Mat3::from(q.normalize()),
+        half_sizes: Vec3::new(
+            rng.gen_range(0.1..1.5),
+            rng.gen_range(0.1..1.5),
+            rng.gen_range(0.1..1.5),
+        ),
+    }
+}
+
+fn random_aabb(rng: &mut impl Rng) -> AABB {
+    AABB {
+        c: random_pos(rng, 2.0),
+        half_sizes: Vec3::new(
+            rng.gen_range(0.1..1.5),
+            rng.gen_range(0.1..1.5),
+            rng.gen_range(0.1..1.5),
+        ),
+    }
+}
+
+fn random_plane(rng: &mut impl Rng) -> Plane {
+    Plane {
+        n: random_dir(rng),
+        d: rng.gen_range(-1.0..1.0),
+    }
+}
+
+fn random_capsule(rng: &mut impl Rng) -> Capsule {
+    let a = random_pos(rng, 2.0);
+    Capsule {
+        a,
+        b: a + random_dir(rng) * rng.gen_range(0.0..2.0),
+        r: rng.gen_range(0.1..1.0),
+    }
+}
+
+/// Check that `a` and `b` agree on whether they touch and on which way to
+/// push each other.  Near-grazing contacts may fall either side of the
+/// line, so only deep enough ones must agree.  Returns whether they touched.
+fn check_pair<A, B>(a: &A, b: &B, tolerance: f32) -> bool
+where
+    A: Collide<B> + std::fmt::Debug,
+    B: Collide<A> + std::fmt::Debug,
+{
+    match (a.manifold(b), b.manifold(a)) {
+        (Some(ab), Some(ba)) => {
+            assert!(ab.depth >= 0.0 && ba.depth >= 0.0, "{:?} {:?}", a, b);
+            assert!((ab.depth - ba.depth).abs() < tolerance, "{:?} {:?}", a, b);
+            assert!((ab.normal + ba.normal).magnitude() < tolerance || ab.depth < tolerance);
+            let (da, db) = (a.disp(b).unwrap(), b.disp(a).unwrap());
+            assert!((da + db).magnitude() < tolerance, "{:?} {:?}", a, b);
+            assert!(!ab.points().is_empty() && !ba.points().is_empty());
+            true
+        }
+        (None, None) => false,
+        (Some(m), None) | (None, Some(m)) => {
+            assert!(m.depth < tolerance, "{:?} {:?}", a, b);
+            false
+        }
+    }
+}
+
+/// Run `check_pair` over random pairs, making sure enough of them touch to
+/// mean something.
+fn check_matrix<A, B>(
+    seed: u64,
+    gen_a: impl Fn(&mut ChaCha8Rng) -> A,
+    gen_b: impl Fn(&mut ChaCha8Rng) -> B,
+    tolerance: f32,
+) where
+    A: Collide<B> + std::fmt::Debug,
+    B: Collide<A> + std::fmt::Debug,
+{
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let touched = (0..CASES)
+        .filter(|_| {
+            let (a, b) = (gen_a(&mut rng), gen_b(&mut rng));
+            check_pair(&a, &b, tolerance)
+        })
+        .count();
+    assert!(
+        touched > CASES / 10,
+        "only {} of {} touched",
+        touched,
+        CASES
+    );
+    assert!(touched < CASES, "all {} touched", CASES);
+}
+
+#[test]
+fn sphere_pairs_are_antisymmetric() {
+    check_matrix(1, random_sphere, random_sphere, 1e-4);
+    check_matrix(2, random_sphere, random_box, 1e-4);
+    check_matrix(3, random_sphere, random_aabb, 1e-4);
+    check_matrix(4, random_sphere, random_plane, 1e-4);
+    check_matrix(5, random_sphere, random_capsule, 1e-4);
+}
+
+#[test]
+fn box_pairs_are_antisymmetric() {
+    // Box-box contacts pick their axis from rounded overlaps, so which of two
+    // near-equal axes wins can depend on which box asks
+    check_matrix(6, random_box, random_box, 1e-2);
+    check_matrix(7, random_box, random_aabb, 1e-2);
+    check_matrix(8, random_box, random_plane, 1e-4);
+    check_matrix(9, random_box, random_capsule, 1e-3);
+}
+
+#[test]
+fn aabb_pairs_are_antisymmetric() {
+    check_matrix(10, random_aabb, random_aabb, 1e-3);
+    check_matrix(11, random_aabb, random_plane, 1e-4);
+    check_matrix(12, random_aabb, random_capsule, 1e-3);
+}
+
+#[test]
+fn capsule_pairs_are_antisymmetric() {
+    check_matrix(13, random_capsule, random_capsule, 1e-3);
+    check_matrix(14, random_capsule, random_plane, 1e-4);
+}
+
+#[test]
+fn planes_never_push_planes() {
+    let mut rng = ChaCha8Rng::seed_from_u64(15);
+    for _ in 0..CASES {
+        let (a, b) = (random_plane(&mut rng), random_plane(&mut rng));
+        assert!(a.manifold(&b).is_none());
+        assert!(a.disp(&b).is_none());
+    }
+}
+
+#[test]
+fn capsule_contacts() {
+    let floor = Plane {
+        n: Vec3::unit_y(),
+        d: 0.0,
+    };
+    // Lying flat and sunk 0.1 into the floor: touches along its length
+    let lying = Capsule {
+        a: Pos3::new(-1.0, 0.4, 0.0),
+        b: Pos3::new(1.0, 0.4, 0.0),
+        r: 0.5,
+    };
+    let m = lying.manifold(&floor).unwrap();
+    assert!((m.normal - Vec3::unit_y()).magnitude() < 1e-4);
+    assert!((m.depth - 0.1).abs() < 1e-4);
+    assert_eq!(m.points().len(), 2);
+
+    // Standing on a box, it's pushed up off the top face
+    let b = Box {
+        c: Pos3::new(0.0, -1.0, 0.0),
+        axes: Mat3::one(),
+        half_sizes: Vec3::new(2.0, 1.0, 2.0),
+    };
+    let standing = Capsule {
+        a: Pos3::new(0.5, 0.3, 0.5),
+        b: Pos3::new(0.5, 2.3, 0.5),
+        r: 0.4,
+    };
+    let m = standing.manifold(&b).unwrap();
+    assert!((m.normal - Vec3::unit_y()).magnitude() < 1e-3);
+    assert!((m.depth - 0.1).abs() < 1e-3);
+
+    // Crossed capsules push apart along the line between their spines
+    let crossed = Capsule {
+        a: Pos3::new(0.0, 0.8, -1.0),
+        b: Pos3::new(0.0, 0.8, 1.0),
+        r: 0.5,
+    };
+    let m = crossed.manifold(&lying).unwrap();
+    assert!((m.normal - Vec3::unit_y()).magnitude() < 1e-4);
+    assert!((m.depth - 0.6).abs() < 1e-4);
+}
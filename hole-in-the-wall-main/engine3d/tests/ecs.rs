@@ -0,0 +1,299 @@
+use engine3d::collision::{Layers, TriggerPhase};
+use engine3d::ecs::{Collider, Entity, Mass, Sleep, Transform, Velocity, World, SLEEP_STEPS};
+use engine3d::geom::*;
+use engine3d::physics::Material;
+use engine3d::DT;
+
+fn floor(world: &mut World) -> Entity {
+    let e = world.spawn();
+    world.transforms.insert(e, Transform::at(Pos3::origin()));
+    world.colliders.insert(
+        e,
+        Collider::new(AnyShape::Plane(Plane {
+            n: Vec3::unit_y(),
+            d: 0.0,
+        })),
+    );
+    e
+}
+
+fn crate_at(world: &mut World, pos: Pos3) -> Entity {
+    let half_sizes = Vec3::new(0.5, 0.5, 0.5);
+    let e = world.spawn();
+    world.transforms.insert(e, Transform::at(pos));
+    world.velocities.insert(e, Velocity::default());
+    world.masses.insert(
+        e,
+        Mass::new(
+            &Box {
+                c: pos,
+                axes: Mat3::one(),
+                half_sizes,
+            },
+            1.0,
+        ),
+    );
+    world.colliders.insert(e, Collider::cuboid(half_sizes));
+    e
+}
+
+#[test]
+fn despawned_handles_go_stale() {
+    let mut world = World::new();
+    let a = world.spawn();
+    world
+        .transforms
+        .insert(a, Transform::at(Pos3::new(1.0, 2.0, 3.0)));
+    world.despawn(a);
+    assert!(!world.is_alive(a));
+    assert!(world.transforms.get(a).is_none());
+
+    // The slot is reused, but the old handle can't see the new entity
+    let b = world.spawn();
+    assert_ne!(a, b);
+    world.transforms.insert(b, Transform::at(Pos3::origin()));
+    assert!(world.transforms.get(a).is_none());
+    assert_eq!(world.transforms[b].pos, Pos3::origin());
+    assert_eq!(world.entities().collect::<Vec<_>>(), vec![b]);
+}
+
+#[test]
+fn integrate_moves_and_turns() {
+    let mut world = World::new();
+    let e = world.spawn();
+    world.transforms.insert(e, Transform::at(Pos3::origin()));
+    world.velocities.insert(
+        e,
+        Velocity {
+            linear: Vec3::new(1.0, 0.0, -2.0),
+            angular: Vec3::unit_y(),
+        },
+    );
+    // Without a transform there's nothing to move
+    let ghost = world.spawn();
+    world
+        .velocities
+        .insert(ghost, Velocity::linear(Vec3::unit_x()));
+    for _ in 0..60 {
+        world.integrate(DT);
+    }
+    let t = world.transforms[e];
+    assert!((t.pos - Pos3::new(1.0, 0.0, -2.0)).magnitude() < 1e-3);
+    let turned = t.rot * Vec3::unit_x();
+    assert!((turned - Vec3::new(1.0_f32.cos(), 0.0, -1.0_f32.sin())).magnitude() < 1e-2);
+    assert!(world.transforms.get(ghost).is_none());
+}
+
+#[test]
+fn crates_land_on_the_floor_and_each_other() {
+    let mut world = World::new();
+    let ground = floor(&mut world);
+    let low = crate_at(&mut world, Pos3::new(0.0, 0.5, 0.0));
+    let high = crate_at(&mut world, Pos3::new(0.0, 2.0, 0.0));
+    let mut contacts = vec![];
+    for _ in 0..180 {
+        for &e in &[low, high] {
+            world.velocities[e].linear.y -= 9.8 * DT;
+        }
+        contacts.clear();
+        world.gather_contacts(&mut contacts);
+        world.solve(&contacts, DT);
+        world.integrate(DT);
+    }
+    assert!((world.transforms[low].pos.y - 0.5).abs() < 0.05);
+    assert!((world.transforms[high].pos.y - 1.5).abs() < 0.1);
+    // The floor has no mass, so contacts leave it where it was
+    assert!(world.velocities.get(ground).is_none());
+    assert_eq!(world.transforms[ground].pos, Pos3::origin());
+}
+
+#[test]
+fn layers_and_triggers_by_entity() {
+    let mut world = World::new();
+    let player = crate_at(&mut world, Pos3::new(-3.0, 0.5, 0.0));
+    let zone = world.spawn();
+    world
+        .transforms
+        .insert(zone, Transform::at(Pos3::new(0.0, 0.5, 0.0)));
+    world.colliders.insert(
+        zone,
+        Collider::cuboid(Vec3::new(1.0, 1.0, 1.0))
+            .with_layers(Layers::new(2, 1))
+            .as_trigger(),
+    );
+    world.colliders[player].layers = Layers::new(1, 2);
+
+    // Triggers don't push back
+    let mut contacts = vec![];
+    world.transforms[player].pos.x = 0.0;
+    world.gather_contacts(&mut contacts);
+    assert!(contacts.is_empty());
+
+    world.update_triggers();
+    assert!(world.triggers().entered(zone));
+    let events = world.triggers().events();
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].body, events[0].trigger), (player, zone));
+    world.update_triggers();
+    assert_eq!(world.triggers().events()[0].phase, TriggerPhase::Stay);
+
+    // Despawning the player takes it out of the zone
+    world.despawn(player);
+    world.update_triggers();
+    assert_eq!(world.triggers().events()[0].phase, TriggerPhase::Exit);
+    assert!(!world.triggers().occupied(zone));
+}
+
+#[test]
+fn colliders_follow_their_transforms() {
+    let mut world = World::new();
+    let e = world.spawn();
+    world.transforms.insert(
+        e,
+        Transform {
+            pos: Pos3::new(0.0, 0.0, 5.0),
+            rot: Quat::from_axis_angle(Vec3::unit_z(), cgmath::Deg(90.0)),
+        },
+    );
+    let mut box_ = Collider::cuboid(Vec3::new(2.0, 0.5, 0.5));
+    box_.shape = match box_.shape {
+        AnyShape::Box(b) => AnyShape::Box(Box {
+            c: Pos3::new(1.0, 0.0, 0.0),
+            ..b
+        }),
+        s => s,
+    };
+    world.colliders.insert(e, box_);
+    match world.shape(e) {
+        Some(AnyShape::Box(b)) => {
+            assert!((b.c - Pos3::new(0.0, 1.0, 5.0)).magnitude() < 1e-5);
+            assert!((b.axes.x - Vec3::unit_y()).magnitude() < 1e-5);
+        }
+        s => panic!("expected a box, got {:?}", s),
+    }
+
+    world.colliders.insert(
+        e,
+        Collider::new(AnyShape::Plane(Plane {
+            n: Vec3::unit_x(),
+            d: 1.0,
+        })),
+    );
+    match world.shape(e) {
+        Some(AnyShape::Plane(p)) => {
+            assert!((p.n - Vec3::unit_y()).magnitude() < 1e-5);
+            assert!((p.d - 1.0).abs() < 1e-5);
+        }
+        s => panic!("expected a plane, got {:?}", s),
+    }
+}
+
+#[test]
+fn settled_crates_sleep_until_something_knocks_them() {
+    let mut world = World::new();
+    floor(&mut world);
+    let low = crate_at(&mut world, Pos3::new(0.0, 0.5, 0.0));
+    world.sleep.insert(low, Sleep::default());
+    let mut crates = vec![low];
+    let mut contacts = vec![];
+    let step = |world: &mut World, crates: &[Entity], contacts: &mut Vec<_>| {
+        for &e in crates {
+            if !world.is_asleep(e) {
+                world.velocities[e].linear.y -= 9.8 * DT;
+            }
+        }
+        contacts.clear();
+        world.gather_contacts(contacts);
+        world.solve(contacts, DT);
+        world.update_sleep(contacts);
+        world.integrate(DT);
+    };
+    for _ in 0..SLEEP_STEPS * 2 {
+        step(&mut world, &crates, &mut contacts);
+    }
+    // Asleep on the floor there's nothing to collide, and it stays put
+    assert!(world.is_asleep(low));
+    let rest = world.transforms[low].pos;
+    step(&mut world, &crates, &mut contacts);
+    assert!(contacts.is_empty());
+    assert_eq!(world.transforms[low].pos, rest);
+
+    // Dropping a crate on it wakes it up
+    let high = crate_at(&mut world, Pos3::new(0.0, 2.0, 0.0));
+    world.sleep.insert(high, Sleep::default());
+    crates.push(high);
+    for _ in 0..30 {
+        step(&mut world, &crates, &mut contacts);
+    }
+    assert!(!world.is_asleep(low));
+
+    // Once they've settled the pair sleep together, and wake together
+    for _ in 0..SLEEP_STEPS * 4 {
+        step(&mut world, &crates, &mut contacts);
+    }
+    assert!(world.is_asleep(low) && world.is_asleep(high));
+    assert!((world.transforms[high].pos.y - 1.5).abs() < 0.1);
+    world.wake(low);
+    assert!(!world.is_asleep(high));
+}
+
+fn slide(material: Material, speed: f32) -> f32 {
+    let mut world = World::new();
+    let ground = floor(&mut world);
+    world.colliders[ground].material = material;
+    let e = crate_at(&mut world, Pos3::new(0.0, 0.5, 0.0));
+    world.colliders[e].material = material;
+    world.velocities[e].linear.x = speed;
+    let mut contacts = vec![];
+    for _ in 0..120 {
+        world.velocities[e].linear.y -= 9.8 * DT;
+        contacts.clear();
+        world.gather_contacts(&mut contacts);
+        world.solve(&contacts, DT);
+        world.integrate(DT);
+    }
+    world.transforms[e].pos.x
+}
+
+#[test]
+fn friction_slows_sliding_crates_down() {
+    // Nothing stops a crate on ice
+    let ice = Material::default();
+    assert!((slide(ice, 1.0) - 2.0).abs() < 0.05);
+    // Dynamic friction stops it after v² / 2μg
+    let rubber = Material::new(0.9, 0.5, 0.0);
+    let stop = 1.0 / (2.0 * 0.5 * 9.8);
+    assert!((slide(rubber, 1.0) - stop).abs() < 0.05);
+    // and static friction grabs hold of a nudged one as soon as it settles
+    // onto the floor
+    assert!(slide(rubber, 0.1) < 0.02);
+}
+
+#[test]
+fn materials_combine_per_contact() {
+    let glass = Material::new(0.2, 0.1, 0.6);
+    let felt = Material::new(0.8, 0.4, 0.0);
+    let m = glass.combine(felt);
+    assert!((m.static_friction - 0.4).abs() < 1e-6);
+    assert!((m.dynamic_friction - 0.2).abs() < 1e-6);
+    assert_eq!(m.restitution, 0.6);
+    assert_eq!(glass.combine(Material::default()).dynamic_friction, 0.0);
+
+    // A bouncy crate dropped on the floor comes back up
+    let mut world = World::new();
+    let ground = floor(&mut world);
+    let e = crate_at(&mut world, Pos3::new(0.0, 2.5, 0.0));
+    world.colliders[e].material = glass;
+    let mut contacts = vec![];
+    let mut bounced = false;
+    for _ in 0..60 {
+        world.velocities[e].linear.y -= 9.8 * DT;
+        contacts.clear();
+        world.gather_contacts(&mut contacts);
+        world.solve(&contacts, DT);
+        world.integrate(DT);
+        bounced |= world.velocities[e].linear.y > 2.0;
+    }
+    assert!(bounced);
+    assert!(world.velocities.get(ground).is_none());
+}
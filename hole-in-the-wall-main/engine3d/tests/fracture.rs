@@ -0,0 +1,113 @@
+use engine3d::collision::Layers;
+use engine3d::ecs::{Collider, Mass, Transform, Velocity, World};
+use engine3d::fracture::{fracture, shatter, Debris};
+use engine3d::geom::*;
+use engine3d::DT;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+fn volume(b: &Box) -> f32 {
+    8.0 * b.half_sizes.x * b.half_sizes.y * b.half_sizes.z
+}
+
+#[test]
+fn fragments_fill_the_box_and_are_smaller_near_the_impact() {
+    let b = Box {
+        c: Pos3::new(0.0, 1.0, 5.0),
+        axes: Mat3::from_angle_y(cgmath::Deg(30.0)),
+        half_sizes: Vec3::new(1.0, 1.0, 1.0),
+    };
+    let impact = b.c + b.axes.x + b.axes.y;
+    let mut rng = ChaCha8Rng::seed_from_u64(7);
+    let pieces = fracture(&b, impact, 12, &mut rng);
+    assert_eq!(pieces.len(), 12);
+    let total: f32 = pieces.iter().map(volume).sum();
+    assert!((total - volume(&b)).abs() < 1e-3);
+    for p in pieces.iter() {
+        assert_eq!(p.axes, b.axes);
+        // Every fragment is inside the box
+        let local = b.axes.transpose() * (p.c - b.c);
+        for i in 0..3 {
+            assert!(local[i].abs() + p.half_sizes[i] <= b.half_sizes[i] + 1e-4);
+        }
+    }
+    let nearest = pieces
+        .iter()
+        .min_by(|p, q| {
+            let (dp, dq) = ((p.c - impact).magnitude(), (q.c - impact).magnitude());
+            dp.partial_cmp(&dq).unwrap()
+        })
+        .unwrap();
+    let biggest = pieces.iter().map(volume).fold(0.0, f32::max);
+    assert!(volume(nearest) < biggest / 2.0);
+}
+
+#[test]
+fn shattered_boxes_hand_on_their_motion_and_mass() {
+    let mut world = World::new();
+    let e = world.spawn();
+    let half_sizes = Vec3::new(1.0, 0.5, 0.5);
+    world.transforms.insert(e, Transform::at(Pos3::origin()));
+    world.velocities.insert(
+        e,
+        Velocity {
+            linear: Vec3::new(0.0, 0.0, -2.0),
+            angular: Vec3::unit_y(),
+        },
+    );
+    let body = Box {
+        c: Pos3::origin(),
+        axes: Mat3::one(),
+        half_sizes,
+    };
+    world.masses.insert(e, Mass::new(&body, 8.0));
+    world.colliders.insert(
+        e,
+        Collider::cuboid(half_sizes).with_layers(Layers::new(8, 1)),
+    );
+
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let pieces = shatter(&mut world, e, Pos3::new(1.0, 0.0, 0.0), 4, &mut rng);
+    assert_eq!(pieces.len(), 4);
+    assert!(!world.is_alive(e));
+    let mass: f32 = pieces.iter().map(|&p| 1.0 / world.masses[p].inv_mass).sum();
+    assert!((mass - 8.0).abs() < 1e-3);
+    for &p in pieces.iter() {
+        assert_eq!(world.colliders[p].layers, Layers::new(8, 1));
+        // Spinning about y, pieces out along +x move toward -z faster
+        let t = world.transforms[p];
+        let v = world.velocities[p];
+        let expected = -2.0 - t.pos.x;
+        assert!((v.linear.z - expected).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn debris_is_cleared_once_still_or_far_away() {
+    let mut world = World::new();
+    let mut debris = Debris::new();
+    let still = world.spawn();
+    world
+        .transforms
+        .insert(still, Transform::at(Pos3::origin()));
+    world.velocities.insert(still, Velocity::default());
+    let flying = world.spawn();
+    world
+        .transforms
+        .insert(flying, Transform::at(Pos3::origin()));
+    world
+        .velocities
+        .insert(flying, Velocity::linear(Vec3::new(0.0, 0.0, 10.0)));
+    debris.add(vec![still, flying]);
+
+    // Just stopping for a moment isn't enough
+    debris.cull(&mut world, Pos3::origin(), 30.0);
+    assert_eq!(debris.len(), 2);
+
+    for _ in 0..120 {
+        world.integrate(DT);
+        debris.cull(&mut world, Pos3::origin(), 15.0);
+    }
+    assert!(debris.is_empty());
+    assert!(!world.is_alive(still) && !world.is_alive(flying));
+}
@@ -0,0 +1,342 @@
+use engine3d::geom::*;
+use engine3d::gltf;
+use engine3d::model::{ImageSource, ModelData};
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Packs accessor data into one binary buffer as it's added.
+#[derive(Default)]
+struct Buffers {
+    bin: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl Buffers {
+    fn add(&mut self, bytes: &[u8], component_type: u32, kind: &str, count: usize) -> usize {
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+        }));
+        self.bin.extend_from_slice(bytes);
+        while !self.bin.len().is_multiple_of(4) {
+            self.bin.push(0);
+        }
+        self.accessors.push(json!({
+            "bufferView": self.views.len() - 1,
+            "componentType": component_type,
+            "count": count,
+            "type": kind,
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn floats(&mut self, data: &[f32], kind: &str, n: usize) -> usize {
+        let bytes: Vec<u8> = data.iter().flat_map(|f| f.to_le_bytes()).collect();
+        self.add(&bytes, 5126, kind, data.len() / n)
+    }
+
+    /// Positions, which have to give their bounds.
+    fn positions(&mut self, data: &[f32]) -> usize {
+        let a = self.floats(data, "VEC3", 3);
+        let bound = |pick: fn(f32, f32) -> f32| {
+            let mut b = [data[0], data[1], data[2]];
+            for p in data.chunks(3) {
+                for i in 0..3 {
+                    b[i] = pick(b[i], p[i]);
+                }
+            }
+            b
+        };
+        self.accessors[a]["min"] = json!(bound(f32::min));
+        self.accessors[a]["max"] = json!(bound(f32::max));
+        a
+    }
+
+    fn shorts(&mut self, data: &[u16]) -> usize {
+        let bytes: Vec<u8> = data.iter().flat_map(|i| i.to_le_bytes()).collect();
+        self.add(&bytes, 5123, "SCALAR", data.len())
+    }
+
+    /// Fill in the buffers of `doc`, leaving the buffer's data to the caller.
+    fn finish(self, mut doc: Value) -> (Value, Vec<u8>) {
+        doc["asset"] = json!({ "version": "2.0" });
+        doc["bufferViews"] = Value::Array(self.views);
+        doc["accessors"] = Value::Array(self.accessors);
+        doc["buffers"] = json!([{ "byteLength": self.bin.len() }]);
+        (doc, self.bin)
+    }
+}
+
+fn glb(doc: &Value, bin: &[u8]) -> Vec<u8> {
+    let mut json = serde_json::to_vec(doc).unwrap();
+    while !json.len().is_multiple_of(4) {
+        json.push(b' ');
+    }
+    let mut out = b"glTF".to_vec();
+    out.extend_from_slice(&2u32.to_le_bytes());
+    let length = 12 + 8 + json.len() + 8 + bin.len();
+    out.extend_from_slice(&(length as u32).to_le_bytes());
+    out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"JSON");
+    out.extend_from_slice(&json);
+    out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"BIN\0");
+    out.extend_from_slice(bin);
+    out
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(DIGITS[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// A column-major matrix moving things by `(x, y, z)`.
+fn translate(x: f32, y: f32, z: f32) -> [f32; 16] {
+    let mut m = [0.0; 16];
+    for i in 0..4 {
+        m[i * 5] = 1.0;
+    }
+    m[12..15].copy_from_slice(&[x, y, z]);
+    m
+}
+
+/// A right triangle in the xy plane, with its right angle at the origin.
+const TRIANGLE: [f32; 9] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+
+fn assert_close(a: [f32; 3], b: [f32; 3]) {
+    assert!(
+        (Vec3::from(a) - Vec3::from(b)).magnitude() < 1e-5,
+        "{:?} != {:?}",
+        a,
+        b
+    );
+}
+
+#[test]
+fn glb_meshes_are_placed_by_their_nodes() {
+    let mut buffers = Buffers::default();
+    let positions = buffers.positions(&TRIANGLE);
+    let uvs = buffers.floats(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0], "VEC2", 2);
+    let indices = buffers.shorts(&[0, 1, 2]);
+    let h = std::f32::consts::FRAC_1_SQRT_2;
+    let (doc, bin) = buffers.finish(json!({
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "translation": [1.0, 0.0, 0.0], "children": [1] },
+            { "name": "tri", "mesh": 0, "translation": [0.0, 2.0, 0.0],
+              "rotation": [0.0, h, 0.0, h] }
+        ],
+        "meshes": [{ "name": "tri", "primitives": [
+            { "attributes": { "POSITION": positions, "TEXCOORD_0": uvs },
+              "indices": indices, "material": 0 },
+            { "attributes": { "POSITION": positions } }
+        ] }],
+        "materials": [{ "name": "red",
+            "pbrMetallicRoughness": { "baseColorFactor": [1.0, 0.0, 0.0, 1.0] } }]
+    }));
+    let model = gltf::parse(&glb(&doc, &bin), Path::new(".")).unwrap();
+
+    assert_eq!(model.meshes.len(), 2);
+    assert_eq!(model.meshes[0].name, "tri.0");
+    assert_eq!(model.materials[0].base_color, [1.0, 0.0, 0.0, 1.0]);
+    // The second primitive has no material, so gets a plain white one
+    let plain = &model.materials[model.meshes[1].material];
+    assert_eq!(plain.base_color, [1.0; 4]);
+    assert!(plain.diffuse.is_none());
+    assert_eq!(model.meshes[1].indices, vec![0, 1, 2]);
+
+    // Turned a quarter turn about y, then moved up and along x
+    let v = &model.meshes[0].vertices;
+    assert_close(v[0].position, [1.0, 2.0, 0.0]);
+    assert_close(v[1].position, [1.0, 2.0, -1.0]);
+    assert_close(v[2].position, [1.0, 3.0, 0.0]);
+    // Normals and tangents are worked out when they aren't given
+    for v in v {
+        assert_close(v.normal, [1.0, 0.0, 0.0]);
+        assert_close([v.tangent[0], v.tangent[1], v.tangent[2]], [0.0, 0.0, -1.0]);
+        assert_eq!(v.bone_weights, [1.0, 0.0, 0.0, 0.0]);
+    }
+    assert!(model.rig.is_none());
+}
+
+#[test]
+fn embedded_skins_become_skeletons() {
+    let mut buffers = Buffers::default();
+    let positions = buffers.positions(&TRIANGLE);
+    let joints = buffers.add(&[0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0], 5121, "VEC4", 3);
+    let weights = buffers.floats(
+        &[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0],
+        "VEC4",
+        4,
+    );
+    // Bound where the joints are
+    let inverse_binds = buffers.floats(
+        &[translate(-2.0, -0.5, 0.0), translate(-2.0, -1.0, 0.0)].concat(),
+        "MAT4",
+        16,
+    );
+    let image = buffers.add(b"not really a png", 5121, "SCALAR", 16);
+    let (mut doc, bin) = buffers.finish(json!({
+        "nodes": [
+            { "name": "body", "mesh": 0, "skin": 0 },
+            { "name": "knee", "translation": [0.0, -0.5, 0.0] },
+            { "name": "hip", "translation": [0.0, 1.0, 0.0], "children": [1] },
+            { "translation": [2.0, 0.0, 0.0], "children": [2] }
+        ],
+        "meshes": [{ "primitives": [{
+            "attributes": { "POSITION": positions, "JOINTS_0": joints, "WEIGHTS_0": weights },
+            "material": 0
+        }] }],
+        "skins": [{ "joints": [1, 2], "inverseBindMatrices": inverse_binds }],
+        "materials": [{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } }],
+        "textures": [{ "source": 0 }],
+        "images": [{ "bufferView": image, "mimeType": "image/png" }]
+    }));
+    doc["buffers"][0]["uri"] = json!(format!(
+        "data:application/octet-stream;base64,{}",
+        base64(&bin)
+    ));
+    let text = serde_json::to_vec(&doc).unwrap();
+    let model = gltf::parse(&text, Path::new(".")).unwrap();
+
+    // The hip comes first, even though the skin lists the knee first
+    let skeleton = &model.rig.as_ref().unwrap().skeleton;
+    assert_eq!(skeleton.len(), 2);
+    assert_eq!(skeleton.bones[0].name, "hip");
+    assert_eq!(skeleton.bones[0].parent, None);
+    assert_eq!(skeleton.bones[0].rest.translation, Vec3::new(2.0, 1.0, 0.0));
+    assert_eq!(skeleton.bones[1].name, "knee");
+    assert_eq!(skeleton.bones[1].parent, Some(0));
+
+    // Joint 0 (the knee) is bone 1, and the weights add up to one
+    let v = &model.meshes[0].vertices;
+    assert_eq!(v[0].bone_ids[0], 1);
+    assert_eq!(v[1].bone_ids[0], 0);
+    assert_eq!(v[2].bone_ids[..2], [1, 0]);
+    assert_eq!(v[2].bone_weights, [0.5, 0.5, 0.0, 0.0]);
+    // Skinned meshes aren't moved by their node
+    assert_close(v[1].position, [1.0, 0.0, 0.0]);
+
+    match &model.materials[0].diffuse {
+        Some(ImageSource::Bytes(bytes)) => assert_eq!(bytes, b"not really a png"),
+        _ => panic!("texture wasn't read from its buffer view"),
+    }
+}
+
+#[test]
+fn bad_accessors_are_errors() {
+    let mut buffers = Buffers::default();
+    let positions = buffers.positions(&TRIANGLE);
+    let indices = buffers.shorts(&[0, 1, 3]);
+    let (doc, bin) = buffers.finish(json!({
+        "nodes": [{ "mesh": 0 }],
+        "meshes": [{ "primitives": [
+            { "attributes": { "POSITION": positions }, "indices": indices }
+        ] }]
+    }));
+    let err = gltf::parse(&glb(&doc, &bin), Path::new(".")).unwrap_err();
+    assert!(err.to_string().contains("Index 3"), "{}", err);
+
+    // Reading past the end of a view
+    let mut long = doc.clone();
+    long["accessors"][positions]["count"] = json!(4);
+    long["meshes"][0]["primitives"][0]
+        .as_object_mut()
+        .unwrap()
+        .remove("indices");
+    assert!(gltf::parse(&glb(&long, &bin), Path::new(".")).is_err());
+
+    // Or past the end of the file
+    assert!(gltf::parse(&glb(&doc, &bin)[..40], Path::new(".")).is_err());
+
+    // Positions that aren't three numbers each
+    let mut flat = doc.clone();
+    flat["accessors"][positions]["type"] = json!("VEC2");
+    assert!(gltf::parse(&glb(&flat, &bin), Path::new(".")).is_err());
+
+    // Nodes that are their own grandparents
+    let mut looped = doc;
+    looped["nodes"] = json!([{ "children": [1] }, { "mesh": 0, "children": [0] }]);
+    let err = gltf::parse(&glb(&looped, &bin), Path::new(".")).unwrap_err();
+    assert!(err.to_string().contains("loop"), "{}", err);
+}
+
+#[test]
+fn meshes_are_bound_by_their_own_skins() {
+    let mut buffers = Buffers::default();
+    let positions = buffers.positions(&TRIANGLE);
+    let joints = buffers.add(&[0; 12], 5121, "VEC4", 3);
+    let weights = buffers.floats(&[[1.0, 0.0, 0.0, 0.0]; 3].concat(), "VEC4", 4);
+    // Bound a step below where the joint is
+    let inverse_binds = buffers.floats(&translate(0.0, -1.0, 0.0), "MAT4", 16);
+    let (doc, bin) = buffers.finish(json!({
+        "nodes": [
+            { "mesh": 0, "skin": 0 },
+            { "mesh": 0, "skin": 1 },
+            { "name": "a", "translation": [1.0, 0.0, 0.0] },
+            { "name": "b", "translation": [0.0, 2.0, 0.0] }
+        ],
+        "meshes": [{ "name": "tri", "primitives": [{
+            "attributes": { "POSITION": positions, "JOINTS_0": joints, "WEIGHTS_0": weights }
+        }] }],
+        "skins": [
+            { "joints": [2] },
+            { "joints": [3], "inverseBindMatrices": inverse_binds }
+        ]
+    }));
+    let model = gltf::parse(&glb(&doc, &bin), Path::new(".")).unwrap();
+
+    // Both skins' joints are in the one skeleton
+    let skeleton = &model.rig.as_ref().unwrap().skeleton;
+    assert_eq!(skeleton.len(), 2);
+    assert_eq!(skeleton.bones[0].name, "a");
+    assert_eq!(skeleton.bones[1].name, "b");
+    let (a, b) = (&model.meshes[0].vertices, &model.meshes[1].vertices);
+    assert!(a.iter().all(|v| v.bone_ids[0] == 0));
+    assert!(b.iter().all(|v| v.bone_ids[0] == 1));
+    // Without inverse bind matrices, vertices are bound where their joints
+    // are, so move with them; with them, they're moved to match the rest pose
+    assert_close(a[1].position, [2.0, 0.0, 0.0]);
+    assert_close(b[1].position, [1.0, 1.0, 0.0]);
+}
+
+#[test]
+fn models_load_by_extension() {
+    let model = ModelData::load("../content/box.obj").unwrap();
+    assert!(!model.meshes.is_empty());
+    for mesh in model.meshes.iter() {
+        assert!(mesh.material < model.materials.len());
+        assert!(mesh
+            .indices
+            .iter()
+            .all(|&i| (i as usize) < mesh.vertices.len()));
+        for v in mesh.vertices.iter() {
+            assert!((Vec3::from(v.normal).magnitude() - 1.0).abs() < 1e-3);
+            let t = Vec3::new(v.tangent[0], v.tangent[1], v.tangent[2]);
+            assert!((t.magnitude() - 1.0).abs() < 1e-3);
+        }
+    }
+    assert!(matches!(
+        model.materials[0].diffuse,
+        Some(ImageSource::Path(_))
+    ));
+
+    assert!(ModelData::load("../content/missing.glb").is_err());
+}
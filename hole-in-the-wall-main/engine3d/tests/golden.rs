@@ -0,0 +1,118 @@
+use engine3d::assets::ModelRef;
+use engine3d::geom::*;
+use engine3d::headless::Headless;
+use engine3d::lights::Light;
+use engine3d::render::{InstanceGroups, InstanceRaw};
+use engine3d::screenshot::{self, Diff};
+use engine3d::{Engine, Game};
+use image::{Rgba, RgbaImage};
+use std::path::Path;
+
+/// A box standing on the floor in the sun.
+struct BoxOnFloor {
+    floor: ModelRef,
+    cube: ModelRef,
+}
+
+impl Game for BoxOnFloor {
+    type StaticData = ();
+    fn start(engine: &mut Engine) -> (Self, ()) {
+        let floor = engine.load_model("floor.obj");
+        let cube = engine.load_model("box.obj");
+        let camera = engine.camera_mut();
+        camera.eye = Pos3::new(4.0, 5.0, -8.0);
+        camera.target = Pos3::new(0.0, 0.5, 0.0);
+        engine.set_ambient(0.3);
+        engine.set_lights(vec![Light::directed(
+            Vec3::new(0.3, -1.0, 0.5),
+            Vec3::new(0.8, 0.8, 0.8),
+        )
+        .with_shadows()]);
+        (Self { floor, cube }, ())
+    }
+    fn update(&mut self, _rules: &(), _engine: &mut Engine) {}
+    fn handle_collision(&mut self) {}
+    fn render(&self, _rules: &(), igs: &mut InstanceGroups) {
+        igs.render(
+            self.floor,
+            InstanceRaw {
+                model: Mat4::from_nonuniform_scale(0.5, 0.05, 0.5).into(),
+            },
+        );
+        igs.render_casting_shadow(
+            self.cube,
+            InstanceRaw {
+                model: Mat4::from_translation(Vec3::new(0.0, 1.0, 0.0)).into(),
+            },
+        );
+    }
+    fn load_game(&mut self, _engine: &mut Engine) {}
+}
+
+// Set `UPDATE_GOLDEN` to redraw the golden image after changing how things
+// look. Without any adapter, even a software one, there's nothing to check.
+#[test]
+fn box_on_floor_matches_its_golden_image() {
+    let mut headless = match Headless::<BoxOnFloor>::offscreen(Path::new("../content"), 320, 180) {
+        Some(headless) => headless,
+        None => {
+            eprintln!("No adapter to draw with, skipping the golden image check");
+            return;
+        }
+    };
+    let image = headless.screenshot().unwrap();
+    assert_eq!(image.dimensions(), (320, 180));
+    screenshot::check_golden(&image, "tests/golden/box-on-floor.png", 8, 100).unwrap();
+}
+
+#[test]
+fn without_a_renderer_there_are_no_screenshots() {
+    let mut headless = Headless::<BoxOnFloor>::new(Path::new("../content"));
+    assert!(headless.engine.is_headless());
+    assert!(headless.screenshot().is_none());
+}
+
+#[test]
+fn images_are_compared_within_a_tolerance() {
+    let a = RgbaImage::from_pixel(4, 3, Rgba([100, 150, 200, 255]));
+    let mut b = a.clone();
+    b.put_pixel(0, 0, Rgba([103, 150, 200, 255]));
+    b.put_pixel(3, 2, Rgba([100, 150, 180, 255]));
+    assert_eq!(
+        screenshot::diff(&a, &b, 4),
+        Some(Diff { pixels: 1, max: 20 })
+    );
+    assert_eq!(screenshot::diff(&a, &b, 20).unwrap().pixels, 0);
+    assert_eq!(
+        screenshot::diff(&a, &RgbaImage::new(3, 4), 255),
+        None,
+        "different sizes never match"
+    );
+}
+
+#[test]
+fn golden_images_are_checked_once_written() {
+    let dir = std::env::temp_dir().join(format!("engine3d-golden-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let golden = dir.join("gray.png");
+    let gray = RgbaImage::from_pixel(8, 8, Rgba([128, 128, 128, 255]));
+    // A missing golden image is a failure, not a new golden image
+    assert!(screenshot::check_golden(&gray, &golden, 0, 0).is_err());
+    assert!(!golden.exists());
+    assert!(dir.join("gray.actual.png").exists());
+    screenshot::update_golden(&gray, &golden).unwrap();
+    screenshot::check_golden(&gray, &golden, 0, 0).unwrap();
+
+    let mut off = gray.clone();
+    off.put_pixel(2, 2, Rgba([255, 0, 0, 255]));
+    assert!(screenshot::check_golden(&off, &golden, 0, 0).is_err());
+    assert!(dir.join("gray.actual.png").exists());
+    screenshot::check_golden(&off, &golden, 0, 1).unwrap();
+
+    // Screenshots never overwrite each other
+    let first = screenshot::save(&gray, &dir).unwrap();
+    let second = screenshot::save(&off, &dir).unwrap();
+    assert_eq!(first, dir.join("screenshot-0.png"));
+    assert_eq!(second, dir.join("screenshot-1.png"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
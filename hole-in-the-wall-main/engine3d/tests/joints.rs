@@ -0,0 +1,174 @@
+use engine3d::collision::Contact;
+use engine3d::ecs::{Collider, Entity, Mass, Transform, Velocity, World};
+use engine3d::geom::*;
+use engine3d::DT;
+
+fn body(world: &mut World, pos: Pos3, half_sizes: Vec3) -> Entity {
+    let e = world.spawn();
+    world.transforms.insert(e, Transform::at(pos));
+    world.velocities.insert(e, Velocity::default());
+    world.masses.insert(
+        e,
+        Mass::new(
+            &Box {
+                c: pos,
+                axes: Mat3::one(),
+                half_sizes,
+            },
+            1.0,
+        ),
+    );
+    world.colliders.insert(e, Collider::cuboid(half_sizes));
+    e
+}
+
+fn crate_at(world: &mut World, pos: Pos3) -> Entity {
+    body(world, pos, Vec3::new(0.5, 0.5, 0.5))
+}
+
+/// Run `world` for `steps` steps with gravity on `falling`, checking
+/// `check` after each one.
+fn run(world: &mut World, falling: &[Entity], steps: usize, mut check: impl FnMut(&World)) {
+    let mut contacts: Vec<Contact<Entity>> = vec![];
+    for _ in 0..steps {
+        for &e in falling {
+            world.velocities[e].linear.y -= 9.8 * DT;
+        }
+        contacts.clear();
+        world.gather_contacts(&mut contacts);
+        world.solve(&contacts, DT);
+        world.integrate(DT);
+        check(world);
+    }
+}
+
+/// Where the point `local` on `e` is in the world.
+fn point(world: &World, e: Entity, local: Vec3) -> Pos3 {
+    let t = world.transforms[e];
+    t.pos + t.rot * local
+}
+
+#[test]
+fn pendulums_swing_on_ball_sockets() {
+    let mut world = World::new();
+    let bob = crate_at(&mut world, Pos3::new(2.0, 0.0, 0.0));
+    world.ball_socket(bob, None, Pos3::origin()).unwrap();
+    let mut lowest = 0.0f32;
+    run(&mut world, &[bob], 120, |world| {
+        // The string stays as long as it was
+        let length = world.transforms[bob].pos.to_vec().magnitude();
+        assert!((length - 2.0).abs() < 0.05, "length {}", length);
+        lowest = lowest.min(world.transforms[bob].pos.y);
+    });
+    assert!(lowest < -1.9);
+    // It swung through the bottom and out the other side
+    assert!(world.transforms[bob].pos.x < 0.0);
+}
+
+#[test]
+fn hinged_doors_only_swing_about_their_hinges() {
+    let mut world = World::new();
+    let door = body(
+        &mut world,
+        Pos3::new(1.0, 1.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.1),
+    );
+    world
+        .hinge(door, None, Pos3::new(0.0, 1.0, 0.0), Vec3::unit_y())
+        .unwrap();
+    world.velocities[door].linear.z = -1.0;
+    run(&mut world, &[door], 60, |world| {
+        let t = world.transforms[door];
+        // Gravity doesn't make it sag, and it stays upright
+        assert!((t.pos.y - 1.0).abs() < 0.02, "sagged to {}", t.pos.y);
+        assert!((t.rot * Vec3::unit_y() - Vec3::unit_y()).magnitude() < 0.02);
+        let hinge = point(world, door, Vec3::new(-1.0, 0.0, 0.0));
+        assert!((hinge - Pos3::new(0.0, 1.0, 0.0)).magnitude() < 0.02);
+    });
+    // It's swung open, away from where it was pushed
+    let t = world.transforms[door];
+    assert!(t.pos.z < -0.5);
+    assert!((t.pos.x.powi(2) + t.pos.z.powi(2) - 1.0).abs() < 0.05);
+}
+
+#[test]
+fn distance_joints_keep_bodies_apart() {
+    let mut world = World::new();
+    let a = crate_at(&mut world, Pos3::new(0.0, 5.0, 0.0));
+    let b = crate_at(&mut world, Pos3::new(3.0, 5.0, 0.0));
+    world
+        .distance(
+            a,
+            Pos3::new(0.0, 5.0, 0.0),
+            Some(b),
+            Pos3::new(3.0, 5.0, 0.0),
+        )
+        .unwrap();
+    world.velocities[a].linear.z = 2.0;
+    world.velocities[b].linear.z = -2.0;
+    run(&mut world, &[a, b], 90, |world| {
+        let apart = world.transforms[a].pos - world.transforms[b].pos;
+        assert!((apart.magnitude() - 3.0).abs() < 0.05);
+    });
+    // They've spun round each other as they fell
+    let apart = world.transforms[a].pos - world.transforms[b].pos;
+    assert!(apart.z.abs() > 1.0);
+    assert!(world.transforms[a].pos.y < 0.0);
+}
+
+#[test]
+fn fixed_joints_hold_bodies_together_through_contacts() {
+    let mut world = World::new();
+    let floor = world.spawn();
+    world
+        .transforms
+        .insert(floor, Transform::at(Pos3::origin()));
+    world.colliders.insert(
+        floor,
+        Collider::new(AnyShape::Plane(Plane {
+            n: Vec3::unit_y(),
+            d: 0.0,
+        })),
+    );
+    // Welded to the world, a crate hangs in the air
+    let held = crate_at(&mut world, Pos3::new(-5.0, 3.0, 0.0));
+    world.fix(held, None).unwrap();
+    // Welded to each other, two crates land as one
+    let a = crate_at(&mut world, Pos3::new(0.0, 3.0, 0.0));
+    let b = crate_at(&mut world, Pos3::new(1.5, 3.5, 0.0));
+    world.transforms[b].rot = Quat::from_angle_z(cgmath::Deg(30.0));
+    world.fix(b, Some(a)).unwrap();
+    world.velocities[a].angular.z = 1.0;
+    let offset = world.transforms[b].pos - world.transforms[a].pos;
+    let turned = world.transforms[a].rot.conjugate() * world.transforms[b].rot;
+    run(&mut world, &[held, a, b], 180, |world| {
+        let (ta, tb) = (world.transforms[a], world.transforms[b]);
+        assert!((ta.rot.conjugate() * (tb.pos - ta.pos) - offset).magnitude() < 0.05);
+        let drift = ta.rot.conjugate() * tb.rot * turned.conjugate();
+        assert!(drift.v.magnitude() < 0.02);
+    });
+    assert!((world.transforms[held].pos - Pos3::new(-5.0, 3.0, 0.0)).magnitude() < 0.02);
+    // Both on the floor rather than through it
+    for &e in &[a, b] {
+        let y = world.transforms[e].pos.y;
+        assert!(y > 0.3 && y < 1.5, "{:?} at {}", e, y);
+    }
+
+    world.despawn(a);
+    assert_eq!(world.joints.len(), 1);
+}
+
+#[test]
+fn bodies_cannot_be_jointed_to_themselves() {
+    let mut world = World::new();
+    let a = crate_at(&mut world, Pos3::origin());
+    assert!(world.ball_socket(a, Some(a), Pos3::origin()).is_err());
+    assert!(world
+        .hinge(a, Some(a), Pos3::origin(), Vec3::unit_y())
+        .is_err());
+    assert!(world
+        .distance(a, Pos3::origin(), Some(a), Pos3::new(1.0, 0.0, 0.0))
+        .is_err());
+    assert!(world.fix(a, Some(a)).is_err());
+    assert!(world.joints.is_empty());
+}
@@ -0,0 +1,105 @@
+use engine3d::geom::*;
+use engine3d::lights::{Light, LightRaw};
+use std::mem::{offset_of, size_of};
+
+fn assert_close(a: Vec3, b: Vec3) {
+    assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn lights_are_laid_out_for_std140() {
+    // Each vec3 is aligned to 16 bytes, with a scalar packed in after it
+    assert_eq!(size_of::<LightRaw>(), 64);
+    assert_eq!(offset_of!(LightRaw, kind), 12);
+    assert_eq!(offset_of!(LightRaw, dir), 16);
+    assert_eq!(offset_of!(LightRaw, range), 28);
+    assert_eq!(offset_of!(LightRaw, color), 32);
+    assert_eq!(offset_of!(LightRaw, shadow), 44);
+    assert_eq!(offset_of!(LightRaw, cone), 48);
+    assert_eq!(offset_of!(LightRaw, attenuation), 56);
+
+    let spot = Light::spot(
+        Pos3::new(1.0, 2.0, 3.0),
+        Vec3::new(0.0, -2.0, 0.0),
+        cgmath::Deg(20.0),
+        cgmath::Deg(30.0),
+        Vec3::new(1.0, 0.5, 0.25),
+    )
+    .with_range(10.0)
+    .with_attenuation(0.1, 0.01)
+    .to_raw();
+    assert_eq!(spot.kind, LightRaw::SPOT);
+    assert_eq!(spot.pos, [1.0, 2.0, 3.0]);
+    assert_eq!(spot.dir, [0.0, -1.0, 0.0]);
+    assert_eq!(spot.range, 10.0);
+    assert!((spot.cone[0] - 20f32.to_radians().cos()).abs() < 1e-6);
+    assert!((spot.cone[1] - 30f32.to_radians().cos()).abs() < 1e-6);
+    assert_eq!(spot.attenuation, [0.1, 0.01]);
+    // Lights don't get shadow maps until the renderer hands them out
+    assert_eq!(spot.shadow, -1);
+    // Zeroed lights give off nothing
+    assert_eq!(
+        <LightRaw as bytemuck::Zeroable>::zeroed().kind,
+        LightRaw::UNUSED
+    );
+}
+
+#[test]
+fn directional_lights_shine_the_same_everywhere() {
+    let sun = Light::directed(Vec3::new(0.0, -3.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+    for &at in &[Pos3::origin(), Pos3::new(100.0, -50.0, 3.0)] {
+        let (to_light, color) = sun.incoming(at);
+        assert_close(to_light, Vec3::unit_y());
+        assert_close(color, Vec3::new(1.0, 1.0, 1.0));
+    }
+    // Surfaces tilted away get less, and those facing away none
+    let tilted = Vec3::new(1.0, 1.0, 0.0).normalize();
+    assert!((sun.irradiance(Pos3::origin(), tilted).x - tilted.y).abs() < 1e-5);
+    assert_eq!(
+        sun.irradiance(Pos3::origin(), -Vec3::unit_y()),
+        Vec3::zero()
+    );
+    assert_eq!(sun.position(), None);
+}
+
+#[test]
+fn point_lights_fade_with_distance_and_range() {
+    let white = Vec3::new(1.0, 1.0, 1.0);
+    // Without attenuation or a range, point lights don't fade
+    let bulb = Light::point(Pos3::new(0.0, 10.0, 0.0), white);
+    let (to_light, color) = bulb.incoming(Pos3::new(0.0, 0.0, 0.0));
+    assert_close(to_light, Vec3::unit_y());
+    assert_close(color, white);
+
+    let bulb = bulb.with_attenuation(0.5, 0.25);
+    let (_, color) = bulb.incoming(Pos3::new(0.0, 8.0, 0.0));
+    assert_close(color, white / (1.0 + 1.0 + 1.0));
+
+    let bulb = Light::point(Pos3::origin(), white).with_range(4.0);
+    let near = bulb.incoming(Pos3::new(1.0, 0.0, 0.0)).1.x;
+    let far = bulb.incoming(Pos3::new(3.0, 0.0, 0.0)).1.x;
+    assert!(near > far && far > 0.0, "{} {}", near, far);
+    assert_eq!(bulb.incoming(Pos3::new(4.5, 0.0, 0.0)).1, Vec3::zero());
+}
+
+#[test]
+fn spotlights_light_only_their_cone() {
+    let spot = Light::spot(
+        Pos3::new(0.0, 5.0, 0.0),
+        -Vec3::unit_y(),
+        cgmath::Deg(10.0),
+        cgmath::Deg(20.0),
+        Vec3::new(1.0, 1.0, 1.0),
+    );
+    let at_angle = |degrees: f32| {
+        let x = 5.0 * degrees.to_radians().tan();
+        spot.irradiance(Pos3::new(x, 0.0, 0.0), Vec3::unit_y()).x
+    };
+    // Full strength in the middle, fading between the two angles
+    assert!((at_angle(0.0) - 1.0).abs() < 1e-5);
+    assert!((at_angle(9.0) - 9f32.to_radians().cos()).abs() < 1e-4);
+    let (inside, edge) = (at_angle(12.0), at_angle(18.0));
+    assert!(inside > edge && edge > 0.0, "{} {}", inside, edge);
+    assert_eq!(at_angle(25.0), 0.0);
+    assert_eq!(spot.position(), Some(Pos3::new(0.0, 5.0, 0.0)));
+}
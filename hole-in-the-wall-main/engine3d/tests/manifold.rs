@@ -0,0 +1,214 @@
+use engine3d::geom::*;
+
+fn cube(c: Pos3, rot: Quat) -> Box {
+    Box {
+        c,
+        axes: Mat3::from(rot),
+        half_sizes: Vec3::new(1.0, 1.0, 1.0),
+    }
+}
+
+fn about(axis: Vec3, degrees: f32) -> Quat {
+    Quat::from_axis_angle(axis, cgmath::Deg(degrees))
+}
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+}
+
+fn assert_close_vec(a: Vec3, b: Vec3) {
+    assert!((a - b).magnitude() < 1e-3, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn stacked_cubes_touch_over_a_face() {
+    let floor = cube(Pos3::origin(), Quat::one());
+    let top = cube(Pos3::new(0.3, 1.9, -0.2), Quat::one());
+    let m = top.manifold(&floor).unwrap();
+    assert_close_vec(m.normal, Vec3::unit_y());
+    assert_close(m.depth, 0.1);
+    // The overlap of the two faces is 1.7 by 1.8
+    assert_eq!(m.points().len(), 4);
+    for p in m.points() {
+        assert_close(p.y, 0.95);
+        assert!(p.x >= -0.7 - 1e-3 && p.x <= 1.0 + 1e-3);
+        assert!(p.z >= -1.0 - 1e-3 && p.z <= 0.8 + 1e-3);
+    }
+}
+
+#[test]
+fn box_turned_on_its_edge_touches_along_the_edge() {
+    let floor = cube(Pos3::origin(), Quat::one());
+    // Balanced on an edge parallel to z, sunk 0.1 into the floor
+    let top = cube(
+        Pos3::new(0.0, 1.0 + 2.0_f32.sqrt() - 0.1, 0.0),
+        about(Vec3::unit_z(), 45.0),
+    );
+    let m = top.manifold(&floor).unwrap();
+    assert_close_vec(m.normal, Vec3::unit_y());
+    assert_close(m.depth, 0.1);
+    assert_eq!(m.points().len(), 2);
+    for p in m.points() {
+        assert_close(p.x, 0.0);
+        assert_close(p.z.abs(), 1.0);
+    }
+}
+
+#[test]
+fn box_on_its_corner_touches_at_one_point() {
+    let floor = cube(Pos3::origin(), Quat::one());
+    // Turn the cube so a body diagonal points straight down
+    let rot = Quat::from_arc(Vec3::new(1.0, 1.0, 1.0).normalize(), Vec3::unit_y(), None);
+    let top = cube(Pos3::new(0.5, 1.0 + 3.0_f32.sqrt() - 0.1, 0.0), rot);
+    let m = top.manifold(&floor).unwrap();
+    assert_close_vec(m.normal, Vec3::unit_y());
+    assert_close(m.depth, 0.1);
+    assert_eq!(m.points().len(), 1);
+    assert_close_vec(m.points()[0].to_vec(), Vec3::new(0.5, 0.95, 0.0));
+}
+
+#[test]
+fn crossed_edges_touch_at_one_point() {
+    // One cube balanced on an edge along z, the other hanging off an edge
+    // along x, just overlapping where the edges cross
+    let a = cube(Pos3::origin(), about(Vec3::unit_z(), 45.0));
+    let b = cube(
+        Pos3::new(0.0, 2.0 * 2.0_f32.sqrt() - 0.1, 0.0),
+        about(Vec3::unit_x(), 45.0),
+    );
+    let m = b.manifold(&a).unwrap();
+    assert_close_vec(m.normal, Vec3::unit_y());
+    assert_close(m.depth, 0.1);
+    assert_eq!(m.points().len(), 1);
+    assert_close_vec(
+        m.points()[0].to_vec(),
+        Vec3::new(0.0, 2.0_f32.sqrt() - 0.05, 0.0),
+    );
+}
+
+#[test]
+fn rotated_boxes_agree_both_ways() {
+    let a = cube(
+        Pos3::origin(),
+        about(Vec3::new(1.0, 2.0, 3.0).normalize(), 30.0),
+    );
+    let b = Box {
+        c: Pos3::new(1.2, 0.9, -0.4),
+        axes: Mat3::from(about(Vec3::new(-2.0, 1.0, 0.5).normalize(), 70.0)),
+        half_sizes: Vec3::new(0.5, 1.5, 0.8),
+    };
+    let ab = a.manifold(&b).unwrap();
+    let ba = b.manifold(&a).unwrap();
+    assert_close_vec(ab.normal, -ba.normal);
+    assert_close(ab.depth, ba.depth);
+    assert_close_vec(a.disp(&b).unwrap(), -b.disp(&a).unwrap());
+    assert!(ab.depth > 0.0);
+    // Moving a out along its displacement leaves it only just touching
+    let mut moved = a;
+    moved.translate(ab.normal * (ab.depth + 0.05));
+    if let Some(m) = moved.manifold(&b) {
+        assert!(m.depth < 1e-3);
+    }
+}
+
+#[test]
+fn separated_rotated_boxes_do_not_touch() {
+    let a = cube(Pos3::origin(), about(Vec3::unit_y(), 45.0));
+    let b = cube(Pos3::new(2.0_f32.sqrt() + 1.1, 0.0, 0.0), Quat::one());
+    assert!(a.manifold(&b).is_none());
+    assert!(b.manifold(&a).is_none());
+}
+
+#[test]
+fn tilted_box_sinks_into_plane_along_an_edge() {
+    let floor = Plane {
+        n: Vec3::unit_y(),
+        d: 0.0,
+    };
+    let b = cube(
+        Pos3::new(3.0, 2.0_f32.sqrt() - 0.2, 1.0),
+        about(Vec3::unit_x(), 45.0),
+    );
+    let m = b.manifold(&floor).unwrap();
+    assert_close_vec(m.normal, Vec3::unit_y());
+    assert_close(m.depth, 0.2);
+    assert_eq!(m.points().len(), 2);
+    for p in m.points() {
+        assert_close(p.y, -0.1);
+        assert_close(p.z, 1.0);
+        assert_close((p.x - 3.0).abs(), 1.0);
+    }
+
+    let flat = cube(Pos3::new(0.0, 0.95, 0.0), Quat::one());
+    let m = flat.manifold(&floor).unwrap();
+    assert_close(m.depth, 0.05);
+    assert_eq!(m.points().len(), 4);
+
+    let above = cube(Pos3::new(0.0, 1.5, 0.0), about(Vec3::unit_z(), 10.0));
+    assert!(above.manifold(&floor).is_none());
+}
+
+#[test]
+fn sphere_against_rotated_box() {
+    let b = cube(Pos3::new(1.0, 0.0, 0.0), about(Vec3::unit_z(), 45.0));
+    let face = Vec3::new(1.0, 1.0, 0.0).normalize();
+    // Resting against the middle of a face
+    let s = Sphere {
+        c: b.c + face * 1.4,
+        r: 0.5,
+    };
+    let m = s.manifold(&b).unwrap();
+    assert_close_vec(m.normal, face);
+    assert_close(m.depth, 0.1);
+    assert_close_vec(m.points()[0] - b.c, face);
+
+    // Near a corner, the normal points from the corner to the center
+    let corner = b.c + Vec3::unit_y() * 2.0_f32.sqrt();
+    let s = Sphere {
+        c: corner + Vec3::new(0.3, 0.4, 0.0),
+        r: 0.6,
+    };
+    let m = s.manifold(&b).unwrap();
+    assert_close_vec(m.normal, Vec3::new(0.6, 0.8, 0.0));
+    assert_close(m.depth, 0.1);
+
+    // Center inside the box: leave through the nearest face
+    let s = Sphere {
+        c: b.c + face * 0.8,
+        r: 0.5,
+    };
+    let m = s.manifold(&b).unwrap();
+    assert_close_vec(m.normal, face);
+    assert_close(m.depth, 0.7);
+
+    let s = Sphere {
+        c: b.c + face * 1.6,
+        r: 0.5,
+    };
+    assert!(s.manifold(&b).is_none());
+}
+
+#[test]
+fn spheres_only_just_touching_have_a_manifold() {
+    let a = Sphere {
+        c: Pos3::new(0.0, 0.0, 0.0),
+        r: 1.0,
+    };
+    for &(c, r) in &[(2.0, 1.0), (1.5, 0.5), (3.0, 2.0)] {
+        let b = Sphere {
+            c: Pos3::new(0.0, c, 0.0),
+            r,
+        };
+        assert!(a.touching(&b));
+        let m = a.manifold(&b).unwrap();
+        assert_close_vec(m.normal, -Vec3::unit_y());
+        assert_close(m.depth, 0.0);
+        assert_close_vec(m.points()[0].to_vec(), Vec3::unit_y());
+    }
+    let apart = Sphere {
+        c: Pos3::new(0.0, 2.01, 0.0),
+        r: 1.0,
+    };
+    assert!(!a.touching(&apart));
+    assert!(a.manifold(&apart).is_none());
+}
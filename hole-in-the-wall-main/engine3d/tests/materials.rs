@@ -0,0 +1,113 @@
+use engine3d::gltf;
+use engine3d::model::{ImageSource, MaterialData, MaterialUniform, ModelData};
+use std::path::Path;
+
+fn image(path: &str) -> Option<ImageSource> {
+    Some(ImageSource::Path(Path::new("../content").join(path)))
+}
+
+#[test]
+fn glass_and_diamond_walls_are_different_materials() {
+    let glass = &ModelData::load("../content/glass-box.obj")
+        .unwrap()
+        .materials[0];
+    let diamond = &ModelData::load("../content/wall.obj").unwrap().materials[0];
+    for m in &[glass, diamond] {
+        assert_eq!(m.normal, image("cube-normal.png"));
+        // Textured materials aren't tinted by Kd
+        assert_eq!(m.base_color, [1.0; 4]);
+        assert_eq!(m.emissive, [0.0; 3]);
+    }
+    assert_eq!(glass.diffuse, image("glassTexture.jpg"));
+    assert_eq!(diamond.diffuse, image("diamondOreTexture.png"));
+    assert!(glass.roughness < diamond.roughness);
+    assert!(glass.metallic < diamond.metallic);
+
+    // Without Pr, roughness comes from Ns
+    let floor = &ModelData::load("../content/floor.obj").unwrap().materials[0];
+    assert!((floor.roughness - (1.0 - 0.324f32.sqrt())).abs() < 1e-3);
+    assert_eq!(floor.normal, None);
+}
+
+#[test]
+fn mtl_factors_and_maps_are_read() {
+    let dir = std::env::temp_dir().join(format!("engine3d-materials-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("lamp.obj"),
+        "mtllib lamp.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl bulb\nf 1 2 3\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lamp.mtl"),
+        "newmtl bulb\nKd 0.5 0.25 1.0\nd 0.5\nKe 2.0 2.0 1.5\nmap_Ke glow.png\nPr 0.3\nPm 1.0\n",
+    )
+    .unwrap();
+    let model = ModelData::load(dir.join("lamp.obj")).unwrap();
+    let bulb = &model.materials[model.meshes[0].material];
+    assert_eq!(bulb.base_color, [0.5, 0.25, 1.0, 0.5]);
+    assert_eq!(bulb.emissive, [2.0, 2.0, 1.5]);
+    assert_eq!(
+        bulb.emissive_map,
+        Some(ImageSource::Path(dir.join("glow.png")))
+    );
+    assert_eq!((bulb.metallic, bulb.roughness), (1.0, 0.3));
+    assert_eq!(bulb.diffuse, None);
+    // No texcoords in the file, but the mesh still loads
+    assert_eq!(model.meshes[0].vertices.len(), 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn gltf_materials_keep_their_factors_and_maps() {
+    let doc = r#"{
+        "asset": { "version": "2.0" },
+        "materials": [{
+            "name": "brass",
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [0.9, 0.7, 0.3, 1.0],
+                "metallicFactor": 0.8,
+                "metallicRoughnessTexture": { "index": 1 }
+            },
+            "normalTexture": { "index": 0, "scale": 0.5 },
+            "emissiveFactor": [0.1, 0.0, 0.0]
+        }, {}],
+        "textures": [{ "source": 0 }, { "source": 1 }],
+        "images": [{ "uri": "brass%20normal.png" }, { "uri": "brass-mr.png" }]
+    }"#;
+    let model = gltf::parse(doc.as_bytes(), Path::new("models")).unwrap();
+    let brass = &model.materials[0];
+    assert_eq!(
+        brass.normal,
+        Some(ImageSource::Path(
+            Path::new("models/brass normal.png").into()
+        ))
+    );
+    assert_eq!(brass.normal_scale, 0.5);
+    assert_eq!(
+        brass.metallic_roughness,
+        Some(ImageSource::Path(Path::new("models/brass-mr.png").into()))
+    );
+    assert_eq!((brass.metallic, brass.roughness), (0.8, 1.0));
+    assert_eq!(brass.emissive, [0.1, 0.0, 0.0]);
+    assert_eq!(brass.emissive_map, None);
+
+    // glTF's defaults are fully metal and fully rough
+    let plain = &model.materials[1];
+    assert_eq!((plain.metallic, plain.roughness), (1.0, 1.0));
+    assert_eq!(plain.diffuse, None);
+}
+
+#[test]
+fn material_uniforms_match_the_shader_block() {
+    // Two vec4s, then three floats padded out to a fourth
+    assert_eq!(std::mem::size_of::<MaterialUniform>(), 48);
+    let m = MaterialData {
+        emissive: [1.0, 0.5, 0.25],
+        roughness: 0.5,
+        ..MaterialData::default()
+    };
+    let u = MaterialUniform::from(&m);
+    assert_eq!(u.emissive, [1.0, 0.5, 0.25, 0.0]);
+    assert_eq!((u.metallic, u.roughness, u.normal_scale), (0.0, 0.5, 1.0));
+}
@@ -0,0 +1,191 @@
+use engine3d::collision::{self, restitute_dyn_dyn, restitute_dyn_stat, restitute_dyns, Contact};
+use engine3d::geom::*;
+use engine3d::physics::{self, Material, RigidBody};
+use engine3d::DT;
+
+fn ball(x: f32) -> Sphere {
+    Sphere {
+        c: Pos3::new(x, 0.0, 0.0),
+        r: 0.5,
+    }
+}
+
+#[test]
+fn massless_bodies_stay_put() {
+    let b = ball(0.0);
+    let mut body = RigidBody::new(&b, 0.0);
+    assert_eq!(body, RigidBody::fixed(&b));
+    body.apply_impulse(Vec3::new(1.0, 2.0, 3.0), Vec3::unit_y());
+    assert_eq!(body.vel, Vec3::zero());
+    assert_eq!(body.omega, Vec3::zero());
+}
+
+#[test]
+fn boxes_rest_on_the_floor() {
+    let floor = [Plane {
+        n: Vec3::unit_y(),
+        d: 0.0,
+    }];
+    let mut boxes = [Box {
+        c: Pos3::new(0.0, 0.5, 0.0),
+        axes: Mat3::one(),
+        half_sizes: Vec3::new(0.5, 0.5, 0.5),
+    }];
+    let mut bodies = [RigidBody::new(&boxes[0], 1.0)];
+    let mut contacts: Vec<Contact<usize>> = vec![];
+    for _ in 0..120 {
+        bodies[0].vel.y -= 9.8 * DT;
+        contacts.clear();
+        collision::gather_contacts_ab(&boxes, &floor, &mut contacts);
+        physics::solve_dyn_stat(&boxes, &mut bodies, &contacts, Material::default(), DT);
+        physics::integrate(&mut boxes, &mut bodies, DT);
+        let b = &boxes[0];
+        assert!((b.c.y - 0.5).abs() < EPS, "at {}", b.c.y);
+        assert!(bodies[0].vel.magnitude() < 0.2, "{:?}", bodies[0].vel);
+    }
+    // Flat on the floor, not tipping
+    assert!(bodies[0].omega.magnitude() < 1e-3);
+    assert!((boxes[0].axes.y - Vec3::unit_y()).magnitude() < 1e-3);
+}
+
+#[test]
+fn overlap_is_pushed_out_over_the_step_taken() {
+    // Sunk well past the solver's slop into the floor, and not moving
+    let balls = [Sphere {
+        c: Pos3::new(0.0, 0.3, 0.0),
+        r: 0.5,
+    }];
+    let contacts = on_the_floor(&balls);
+    let push = |dt: f32| {
+        let mut bodies = [RigidBody::new(&balls[0], 1.0)];
+        physics::solve_dyn_stat(&balls, &mut bodies, &contacts, Material::default(), dt);
+        bodies[0].vel.y
+    };
+    let (whole, half) = (push(DT), push(DT / 2.0));
+    assert!(whole > 0.0);
+    // Half the time to get rid of the same overlap takes twice the speed
+    assert!((half - 2.0 * whole).abs() < 1e-4, "{} vs {}", half, whole);
+}
+
+#[test]
+fn head_on_impacts_trade_momentum() {
+    // Just touching, less than the solver's slop apart
+    let balls = [ball(-0.499), ball(0.499)];
+    let mut bodies = [
+        RigidBody::new(&balls[0], 3.0),
+        RigidBody::new(&balls[1], 1.0),
+    ];
+    bodies[0].vel.x = 2.0;
+    bodies[1].vel.x = -2.0;
+    let mut contacts: Vec<Contact<usize>> = vec![];
+    collision::gather_contacts_aa(&balls, &mut contacts);
+    assert_eq!(contacts.len(), 1);
+
+    // Perfectly elastic: the heavy ball stops and the light one flies off
+    let mut elastic = bodies;
+    physics::solve_dyns(
+        &balls,
+        &mut elastic,
+        &contacts,
+        Material::new(0.0, 0.0, 1.0),
+        DT,
+    );
+    assert!(elastic[0].vel.magnitude() < 1e-4, "{:?}", elastic[0].vel);
+    assert!((elastic[1].vel - Vec3::new(4.0, 0.0, 0.0)).magnitude() < 1e-4);
+
+    // Perfectly inelastic: they move on together
+    let mut inelastic = bodies;
+    physics::solve_dyns(&balls, &mut inelastic, &contacts, Material::default(), DT);
+    for b in &inelastic {
+        assert!(
+            (b.vel - Vec3::new(1.0, 0.0, 0.0)).magnitude() < 1e-4,
+            "{:?}",
+            b.vel
+        );
+        assert!(b.omega.magnitude() < 1e-4);
+    }
+}
+
+fn on_the_floor(balls: &[Sphere]) -> Vec<Contact<usize>> {
+    let floor = [Plane {
+        n: Vec3::unit_y(),
+        d: 0.0,
+    }];
+    let mut contacts = vec![];
+    collision::gather_contacts_ab(balls, &floor, &mut contacts);
+    assert_eq!(contacts.len(), balls.len());
+    contacts
+}
+
+fn close(a: Vec3, b: Vec3) -> bool {
+    (a - b).magnitude() < 1e-5
+}
+
+#[test]
+fn friction_grips_or_slides_by_how_hard_things_hit() {
+    let balls: Vec<Sphere> = (0..3)
+        .map(|i| Sphere {
+            c: Pos3::new(i as f32 * 3.0, 0.49, 0.0),
+            r: 0.5,
+        })
+        .collect();
+    let mut contacts = on_the_floor(&balls);
+    let mut vels = vec![Vec3::new(1.0, -2.0, 0.0); 3];
+    // The floor is the same everywhere; the balls aren't
+    let rubber = Material::new(1.0, 0.8, 0.0);
+    let ice = Material::new(0.04, 0.01, 0.0);
+    let bouncy = Material::new(0.16, 0.04, 1.0);
+    let floor = Material::new(0.25, 0.25, 0.0);
+    restitute_dyn_stat(&mut vels, &[rubber, ice, bouncy], &[floor], &mut contacts);
+    // Rubber on the floor grips with up to 0.5 of the 2 it hits with,
+    // which is enough to stop it sliding
+    assert!(close(vels[0], Vec3::new(0.0, 0.0, 0.0)), "{:?}", vels[0]);
+    // Ice can't grip, and slides on 0.05 × 2 slower
+    assert!(close(vels[1], Vec3::new(0.9, 0.0, 0.0)), "{:?}", vels[1]);
+    // The bouncier side decides the bounce, and bouncing gives friction
+    // twice as much to work with: 0.1 × 4 slower
+    assert!(close(vels[2], Vec3::new(0.6, 2.0, 0.0)), "{:?}", vels[2]);
+}
+
+#[test]
+fn separating_contacts_are_left_alone() {
+    let mut contacts = on_the_floor(&[Sphere {
+        c: Pos3::new(0.0, 0.49, 0.0),
+        r: 0.5,
+    }]);
+    let sticky = Material::new(1.0, 1.0, 1.0);
+    let mut vels = [Vec3::new(1.0, 2.0, 0.0)];
+    restitute_dyn_stat(&mut vels, &[sticky], &[sticky], &mut contacts);
+    assert_eq!(vels[0], Vec3::new(1.0, 2.0, 0.0));
+}
+
+#[test]
+fn moving_shapes_split_the_bounce() {
+    let balls = [ball(-0.499), ball(0.499)];
+    let mut contacts: Vec<Contact<usize>> = vec![];
+    collision::gather_contacts_aa(&balls, &mut contacts);
+    let elastic = Material::new(0.0, 0.0, 1.0);
+    let mut vels = [Vec3::new(2.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)];
+    restitute_dyns(&mut vels, &[elastic, Material::default()], &mut contacts);
+    // The bouncier material decides, and equal shapes swap velocities
+    assert!(close(vels[0], Vec3::new(-1.0, 0.0, 0.0)), "{:?}", vels[0]);
+    assert!(close(vels[1], Vec3::new(2.0, 0.0, 0.0)), "{:?}", vels[1]);
+    // Now they're moving apart, so they stay that way
+    restitute_dyns(&mut vels, &[elastic, elastic], &mut contacts);
+    assert!(close(vels[0], Vec3::new(-1.0, 0.0, 0.0)));
+
+    // Sliding past each other as they meet, friction slows the slide
+    let mut contacts: Vec<Contact<usize>> = vec![];
+    collision::gather_contacts_ab(&balls[..1], &balls[1..], &mut contacts);
+    let mut a = [Vec3::new(1.0, 1.0, 0.0)];
+    let mut b = [Vec3::new(-1.0, 0.0, 0.0)];
+    let rough = Material::new(0.1, 0.1, 0.0);
+    restitute_dyn_dyn(&mut a, &[rough], &mut b, &[rough], &mut contacts);
+    // They meet at 2, so they slide 0.2 slower, and stop meeting
+    assert!(
+        close(a[0] - b[0], Vec3::new(0.0, 0.8, 0.0)),
+        "{:?}",
+        a[0] - b[0]
+    );
+    assert!(close(a[0] + b[0], Vec3::new(0.0, 1.0, 0.0)));
+}
@@ -0,0 +1,388 @@
+use engine3d::camera::GameCamera;
+use engine3d::collision::*;
+use engine3d::geom::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+fn assert_close(a: f32, b: f32, tolerance: f32) {
+    assert!((a - b).abs() < tolerance, "{} != {}", a, b);
+}
+
+fn assert_close_vec(a: Vec3, b: Vec3) {
+    assert!((a - b).magnitude() < 1e-3, "{:?} != {:?}", a, b);
+}
+
+fn random_pos(rng: &mut impl Rng, spread: f32) -> Pos3 {
+    Pos3::new(
+        rng.gen_range(-spread..spread),
+        rng.gen_range(-spread..spread),
+        rng.gen_range(-spread..spread),
+    )
+}
+
+fn random_box(rng: &mut impl Rng) -> Box {
+    let q = Quat::new(
+        rng.gen_range(-1.0..1.0),
+        rng.gen_range(-1.0..1.0),
+        rng.gen_range(-1.0..1.0),
+        rng.gen_range(-1.0..1.0),
+    );
+    Box {
+        c: random_pos(rng, 1.0),
+        axes: Mat3::from(q.normalize()),
+        half_sizes: Vec3::new(
+            rng.gen_range(0.2..1.0),
+            rng.gen_range(0.2..1.0),
+            rng.gen_range(0.2..1.0),
+        ),
+    }
+}
+
+/// How far `shape` can move along `dir` before `touching` says it hits,
+/// found by marching and then bisecting.
+fn first_touch<S: Shape + Copy>(shape: S, dir: Vec3, touching: impl Fn(&S) -> bool) -> Option<f32> {
+    let at = |t: f32| {
+        let mut s = shape;
+        s.translate(dir * t);
+        touching(&s)
+    };
+    let step = 0.01;
+    let mut t = 0.0;
+    while !at(t) {
+        t += step;
+        if t > 20.0 {
+            return None;
+        }
+    }
+    if t == 0.0 {
+        return Some(0.0);
+    }
+    let (mut lo, mut hi) = (t - step, t);
+    for _ in 0..20 {
+        let mid = (lo + hi) / 2.0;
+        if at(mid) {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    Some(hi)
+}
+
+/// The separating axis test without `Box::touching`'s padding.
+fn overlapping(a: &Box, b: &Box) -> bool {
+    let mut axes = vec![];
+    for i in 0..3 {
+        axes.push(a.axes[i]);
+        axes.push(b.axes[i]);
+        for j in 0..3 {
+            let l = a.axes[i].cross(b.axes[j]);
+            if l.magnitude2() > EPS * EPS {
+                axes.push(l.normalize());
+            }
+        }
+    }
+    axes.iter()
+        .all(|&l| (a.c - b.c).dot(l).abs() < a.radius_along(l) + b.radius_along(l))
+}
+
+#[test]
+fn rays_hit_each_shape_with_its_normal() {
+    let ray = Ray {
+        p: Pos3::new(0.0, 0.0, -10.0),
+        dir: Vec3::unit_z(),
+    };
+    let s = Sphere {
+        c: Pos3::new(0.0, 0.0, 0.0),
+        r: 2.0,
+    };
+    let hit = ray.cast(&s).unwrap();
+    assert_close(hit.t, 8.0, 1e-4);
+    assert_close_vec(hit.normal, -Vec3::unit_z());
+
+    let p = Plane {
+        n: Vec3::unit_z(),
+        d: 1.0,
+    };
+    let hit = ray.cast(&p).unwrap();
+    assert_close(hit.t, 11.0, 1e-4);
+    // Seen from behind, the plane faces the other way
+    assert_close_vec(hit.normal, -Vec3::unit_z());
+
+    let b = Box {
+        c: Pos3::new(0.0, 0.0, 2.0),
+        axes: Mat3::from(Quat::from_angle_y(cgmath::Deg(45.0))),
+        half_sizes: Vec3::new(1.0, 1.0, 1.0),
+    };
+    let hit = ray.cast(&b).unwrap();
+    // Straight into an edge
+    assert_close(hit.t, 12.0 - 2.0_f32.sqrt(), 1e-4);
+
+    let a = AABB {
+        c: Pos3::new(0.5, 0.0, 3.0),
+        half_sizes: Vec3::new(1.0, 1.0, 1.0),
+    };
+    let hit = ray.cast(&a).unwrap();
+    assert_close(hit.t, 12.0, 1e-4);
+    assert_close_vec(hit.normal, -Vec3::unit_z());
+    // Parallel to a face and outside it
+    let beside = Ray {
+        p: Pos3::new(0.0, 2.0, 0.0),
+        dir: Vec3::unit_z(),
+    };
+    assert!(beside.cast(&a).is_none());
+
+    // Side-on into the body of a capsule, and end-on into a cap
+    let c = Capsule {
+        a: Pos3::new(-3.0, 0.0, 0.0),
+        b: Pos3::new(3.0, 0.0, 0.0),
+        r: 0.5,
+    };
+    let hit = ray.cast(&c).unwrap();
+    assert_close(hit.t, 9.5, 1e-4);
+    assert_close_vec(hit.normal, -Vec3::unit_z());
+    let along = Ray {
+        p: Pos3::new(10.0, 0.0, 0.0),
+        dir: -Vec3::unit_x(),
+    };
+    let hit = along.cast(&c).unwrap();
+    assert_close(hit.t, 6.5, 1e-4);
+    assert_close_vec(hit.normal, Vec3::unit_x());
+
+    // Starting inside counts as an immediate hit
+    let inside = |x: f32, z: f32| Ray {
+        p: Pos3::new(x, 0.0, z),
+        dir: Vec3::unit_x(),
+    };
+    assert_eq!(inside(0.0, 0.5).cast(&s).unwrap().t, 0.0);
+    assert_eq!(inside(2.0, 0.2).cast(&c).unwrap().t, 0.0);
+    assert_eq!(inside(0.0, 1.5).cast(&b).unwrap().t, 0.0);
+}
+
+#[test]
+fn nearest_of_mixed_shapes() {
+    let floor = Plane {
+        n: Vec3::unit_y(),
+        d: 0.0,
+    };
+    let ball = Sphere {
+        c: Pos3::new(0.0, 1.0, 5.0),
+        r: 1.0,
+    };
+    let crate_ = Box {
+        c: Pos3::new(0.0, 1.0, 10.0),
+        axes: Mat3::one(),
+        half_sizes: Vec3::new(1.0, 1.0, 1.0),
+    };
+    let targets: [&dyn Target<Ray>; 3] = [&floor, &crate_, &ball];
+    let level = Ray {
+        p: Pos3::new(0.0, 1.0, 0.0),
+        dir: Vec3::unit_z(),
+    };
+    let (i, hit) = cast_nearest(&level, &targets).unwrap();
+    assert_eq!(i, 2);
+    assert_close(hit.t, 4.0, 1e-4);
+
+    let down = Ray {
+        p: Pos3::new(0.0, 5.0, 20.0),
+        dir: Vec3::new(0.0, -1.0, -1.0).normalize(),
+    };
+    let (i, hit) = cast_nearest(&down, &targets).unwrap();
+    assert_eq!(i, 0);
+    assert_close_vec(hit.normal, Vec3::unit_y());
+
+    let up = Ray {
+        p: Pos3::new(0.0, 5.0, 0.0),
+        dir: Vec3::unit_y(),
+    };
+    assert!(cast_nearest(&up, &targets).is_none());
+
+    let boxes = [
+        crate_,
+        Box {
+            c: Pos3::new(0.0, 1.0, 7.0),
+            ..crate_
+        },
+    ];
+    let (i, hit) = cast_nearest_in(&level, &boxes).unwrap();
+    assert_eq!(i, 1);
+    assert_close(hit.t, 6.0, 1e-4);
+}
+
+#[test]
+fn line_of_sight_past_blockers() {
+    let wall = Box {
+        c: Pos3::new(0.0, 1.0, 5.0),
+        axes: Mat3::one(),
+        half_sizes: Vec3::new(2.0, 1.0, 0.5),
+    };
+    let blockers: [&dyn Target<Ray>; 1] = [&wall];
+    let eye = Pos3::new(0.0, 1.0, 0.0);
+    assert!(!line_of_sight(eye, Pos3::new(0.0, 1.0, 10.0), &blockers));
+    assert!(line_of_sight(eye, Pos3::new(0.0, 5.0, 10.0), &blockers));
+    // Blockers beyond the target don't count
+    assert!(line_of_sight(eye, Pos3::new(0.0, 1.0, 4.0), &blockers));
+}
+
+/// Check a sphere cast against marching the sphere along.  Passes that only
+/// graze the target may go either way.
+fn check_sphere_cast<S>(sweep: &Sweep<Sphere>, target: &S) -> Option<CastHit>
+where
+    S: Shape + std::fmt::Debug,
+    Sphere: Collide<S>,
+    Sweep<Sphere>: Cast<S>,
+{
+    let touch = |r: f32| {
+        let shape = Sphere { r, ..sweep.shape };
+        first_touch(shape, sweep.dir, |s| s.touching(target))
+    };
+    let hit = sweep.cast(target);
+    if let Some(t) = touch(sweep.shape.r) {
+        let hit = hit.unwrap_or_else(|| panic!("{:?} missed {:?}", sweep, target));
+        assert_close(hit.t, t, 1e-3);
+    } else if hit.is_some() {
+        assert!(touch(sweep.shape.r + 1e-3).is_some());
+    }
+    hit
+}
+
+#[test]
+fn sphere_casts_stop_where_spheres_first_touch() {
+    let mut rng = ChaCha8Rng::seed_from_u64(8);
+    let mut hits = 0;
+    for _ in 0..200 {
+        let start = random_pos(&mut rng, 4.0) + Vec3::new(0.0, 0.0, -6.0);
+        let sweep = Sweep {
+            shape: Sphere {
+                c: start,
+                r: rng.gen_range(0.1..1.0),
+            },
+            dir: (random_pos(&mut rng, 2.0) - start).normalize(),
+        };
+        let b = random_box(&mut rng);
+        if let Some(hit) = check_sphere_cast(&sweep, &b) {
+            hits += 1;
+            // The contact is on the sphere, a radius back along the normal
+            let c = sweep.shape.c + sweep.dir * hit.t;
+            assert_close((hit.point - c).magnitude(), sweep.shape.r, 1e-3);
+        }
+
+        let c = Capsule {
+            a: random_pos(&mut rng, 2.0),
+            b: random_pos(&mut rng, 2.0),
+            r: rng.gen_range(0.1..1.0),
+        };
+        check_sphere_cast(&sweep, &c);
+    }
+    assert!(hits > 20);
+
+    let floor = Plane {
+        n: Vec3::unit_y(),
+        d: 0.0,
+    };
+    let falling = Sweep {
+        shape: Sphere {
+            c: Pos3::new(1.0, 5.0, 0.0),
+            r: 1.0,
+        },
+        dir: -Vec3::unit_y(),
+    };
+    let hit = falling.cast(&floor).unwrap();
+    assert_close(hit.t, 4.0, 1e-4);
+    assert_close_vec(hit.point.to_vec(), Vec3::new(1.0, 0.0, 0.0));
+}
+
+#[test]
+fn casts_without_a_direction_never_panic() {
+    let mut rng = ChaCha8Rng::seed_from_u64(10);
+    let zero = Vec3::new(0.0, 0.0, 0.0);
+    // Normalizing a zero-length direction leaves NaNs
+    for &dir in [zero, zero.normalize()].iter().cycle().take(100) {
+        let p = random_pos(&mut rng, 2.0);
+        let ray = Ray { p, dir };
+        let sweep = Sweep {
+            shape: Sphere { c: p, r: 0.5 },
+            dir,
+        };
+        let c = Capsule {
+            a: random_pos(&mut rng, 2.0),
+            b: random_pos(&mut rng, 2.0),
+            r: rng.gen_range(0.1..1.0),
+        };
+        let b = random_box(&mut rng);
+        for hit in [ray.cast(&c), ray.cast(&b), sweep.cast(&c), sweep.cast(&b)]
+            .iter()
+            .flatten()
+        {
+            assert!(hit.t.is_finite(), "{:?}", hit);
+        }
+    }
+}
+
+#[test]
+fn box_sweeps_stop_where_boxes_first_touch() {
+    let mut rng = ChaCha8Rng::seed_from_u64(9);
+    let mut hits = 0;
+    for _ in 0..200 {
+        let mut shape = random_box(&mut rng);
+        shape.c += Vec3::new(0.0, 0.0, -5.0);
+        let sweep = Sweep {
+            shape,
+            dir: (random_pos(&mut rng, 2.0) - shape.c).normalize(),
+        };
+        let b = random_box(&mut rng);
+        let expected = first_touch(shape, sweep.dir, |s| overlapping(s, &b));
+        let hit = sweep.cast(&b);
+        assert_eq!(hit.is_some(), expected.is_some(), "{:?} {:?}", sweep, b);
+        if let (Some(hit), Some(t)) = (hit, expected) {
+            hits += 1;
+            assert_close(hit.t, t, 1e-2);
+        }
+
+        let s = Sphere {
+            c: random_pos(&mut rng, 1.0),
+            r: rng.gen_range(0.2..1.0),
+        };
+        let expected = first_touch(shape, sweep.dir, |b| s.touching(b));
+        let hit = sweep.cast(&s);
+        assert_eq!(hit.is_some(), expected.is_some());
+        if let (Some(hit), Some(t)) = (hit, expected) {
+            assert_close(hit.t, t, 1e-3);
+        }
+    }
+    assert!(hits > 20);
+
+    let floor = Plane {
+        n: Vec3::unit_y(),
+        d: 0.0,
+    };
+    let tilted = Sweep {
+        shape: Box {
+            c: Pos3::new(0.0, 5.0, 0.0),
+            axes: Mat3::from(Quat::from_angle_z(cgmath::Deg(45.0))),
+            half_sizes: Vec3::new(1.0, 1.0, 1.0),
+        },
+        dir: -Vec3::unit_y(),
+    };
+    let hit = tilted.cast(&floor).unwrap();
+    assert_close(hit.t, 5.0 - 2.0_f32.sqrt(), 1e-4);
+    assert_close_vec(hit.normal, Vec3::unit_y());
+    assert_close(hit.point.y, 0.0, 1e-4);
+}
+
+#[test]
+fn camera_ray_through_screen() {
+    let cam = GameCamera::new(2.0);
+    let center = cam.ray_through(0.0, 0.0);
+    assert_close_vec(center.dir, (cam.target - cam.eye).normalize());
+    // The top edge of the screen is half the field of view above center
+    let top = cam.ray_through(0.0, 1.0);
+    assert_close(
+        top.dir.angle(center.dir).0,
+        (cam.fovy / 2.0).to_radians(),
+        1e-4,
+    );
+    let right = cam.ray_through(1.0, 0.0);
+    let half_w = ((cam.fovy / 2.0).to_radians().tan() * cam.aspect).atan();
+    assert_close(right.dir.angle(center.dir).0, half_w, 1e-4);
+}
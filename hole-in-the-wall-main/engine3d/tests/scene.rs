@@ -0,0 +1,95 @@
+use engine3d::ecs::{Transform, World};
+use engine3d::geom::*;
+use engine3d::scene::Scene;
+
+fn about_y(degrees: f32) -> Quat {
+    Quat::from_axis_angle(Vec3::unit_y(), cgmath::Deg(degrees))
+}
+
+fn assert_close(a: Pos3, b: Pos3) {
+    assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn children_are_placed_relative_to_parents() {
+    let mut scene = Scene::new();
+    let base = scene.add(None);
+    let arm = scene.add(Some(base));
+    let hand = scene.add(Some(arm));
+    scene.set_translation(base, Vec3::new(0.0, 0.0, 5.0));
+    scene.set_rotation(base, about_y(90.0));
+    scene.set_scale(arm, Vec3::new(2.0, 2.0, 2.0));
+    scene.set_translation(hand, Vec3::new(1.0, 0.0, 0.0));
+
+    // Turned a quarter left, then doubled: one unit along x lands two units
+    // along -z
+    assert_close(scene.world_position(hand), Pos3::new(0.0, 0.0, 3.0));
+    assert_eq!(scene.children(base), &[arm]);
+    assert_eq!(scene.parent(hand), Some(arm));
+
+    // Moving the base moves everything under it, even with the child's
+    // matrix already cached
+    scene.set_translation(base, Vec3::new(1.0, 1.0, 0.0));
+    assert_close(scene.world_position(hand), Pos3::new(1.0, 1.0, -2.0));
+    assert_close(scene.world_position(arm), Pos3::new(1.0, 1.0, 0.0));
+
+    // Reparenting keeps the local transform
+    scene.set_parent(hand, None);
+    assert_close(scene.world_position(hand), Pos3::new(1.0, 0.0, 0.0));
+    assert!(scene.children(arm).is_empty());
+}
+
+#[test]
+fn hiding_and_removing_take_subtrees_along() {
+    let mut scene = Scene::new();
+    let root = scene.add(None);
+    let child = scene.add(Some(root));
+    let other = scene.add(None);
+    scene.set_visible(root, false);
+    assert!(!scene.is_visible(child));
+    assert!(scene.is_visible(other));
+    scene.set_visible(root, true);
+    assert!(scene.is_visible(child));
+
+    scene.remove(root);
+    assert!(!scene.contains(root) && !scene.contains(child));
+    assert!(scene.contains(other));
+    assert_eq!(scene.instances().count(), 0);
+}
+
+#[test]
+#[should_panic]
+fn nodes_cannot_be_their_own_ancestors() {
+    let mut scene = Scene::new();
+    let a = scene.add(None);
+    let b = scene.add(Some(a));
+    scene.set_parent(a, Some(b));
+}
+
+#[test]
+fn entity_nodes_follow_their_entities() {
+    let mut world = World::new();
+    let e = world.spawn();
+    world
+        .transforms
+        .insert(e, Transform::at(Pos3::new(2.0, 0.0, 0.0)));
+    let node = world.node(e);
+    assert_eq!(world.node(e), node);
+    let label = world.scene.add(Some(node));
+    world.scene.set_translation(label, Vec3::new(0.0, 1.0, 0.0));
+    world.sync_scene();
+    assert_close(world.scene.world_position(label), Pos3::new(2.0, 1.0, 0.0));
+
+    world.transforms[e] = Transform {
+        pos: Pos3::new(0.0, 0.0, -3.0),
+        rot: Quat::from_axis_angle(Vec3::unit_z(), cgmath::Deg(90.0)),
+    };
+    world.sync_scene();
+    assert_close(
+        world.scene.world_position(label),
+        Pos3::new(-1.0, 0.0, -3.0),
+    );
+
+    world.despawn(e);
+    assert!(!world.scene.contains(node) && !world.scene.contains(label));
+}
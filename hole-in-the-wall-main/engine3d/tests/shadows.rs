@@ -0,0 +1,122 @@
+use engine3d::camera::GameCamera;
+use engine3d::geom::*;
+use engine3d::lights::Light;
+use engine3d::shadows::{self, cube_face, SHADOW_DISTANCE};
+
+fn white() -> Vec3 {
+    Vec3::new(1.0, 1.0, 1.0)
+}
+
+/// Where `p` lands in a shadow map: x and y from -1 to 1, and depth.
+fn project(m: Mat4, p: Pos3) -> Vec3 {
+    let clip = m * p.to_homogeneous();
+    clip.truncate() / clip.w
+}
+
+fn in_map(ndc: Vec3) -> bool {
+    ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0 && (0.0..=1.0).contains(&ndc.z)
+}
+
+#[test]
+fn only_lights_with_shadows_get_layers() {
+    let camera = GameCamera::new(16.0 / 9.0);
+    let lights = vec![
+        Light::directed(-Vec3::unit_y(), white()).with_shadows(),
+        Light::point(Pos3::origin(), white()),
+        Light::point(Pos3::new(0.0, 3.0, 0.0), white())
+            .with_range(10.0)
+            .with_shadows(),
+        Light::spot(
+            Pos3::new(0.0, 5.0, 0.0),
+            -Vec3::unit_y(),
+            cgmath::Deg(20.0),
+            cgmath::Deg(30.0),
+            white(),
+        )
+        .with_shadows(),
+    ];
+    // A point light needs a layer for each face of its cube
+    assert_eq!(shadows::first_layers(&lights), vec![0, -1, 1, 7]);
+    assert_eq!(shadows::matrices(&lights, &camera).len(), 8);
+    assert!(shadows::light_matrices(&lights[1], &camera).is_empty());
+}
+
+#[test]
+fn point_light_faces_cover_every_direction() {
+    let camera = GameCamera::new(1.0);
+    let pos = Pos3::new(1.0, 2.0, -3.0);
+    let bulb = Light::point(pos, white()).with_range(8.0).with_shadows();
+    let faces = shadows::light_matrices(&bulb, &camera);
+    assert_eq!(faces.len(), 6);
+    let axes = [
+        Vec3::unit_x(),
+        -Vec3::unit_x(),
+        Vec3::unit_y(),
+        -Vec3::unit_y(),
+        Vec3::unit_z(),
+        -Vec3::unit_z(),
+    ];
+    for (i, &axis) in axes.iter().enumerate() {
+        assert_eq!(cube_face(axis), i);
+        // Straight ahead is the middle of the face
+        let ndc = project(faces[i], pos + axis * 4.0);
+        assert!(ndc.x.abs() < 1e-4 && ndc.y.abs() < 1e-4, "{:?}", ndc);
+    }
+    for i in -4..=4 {
+        for j in -4..=4 {
+            for k in -4..=4 {
+                let dir = Vec3::new(i as f32, j as f32 + 0.5, k as f32 - 0.25);
+                let ndc = project(faces[cube_face(dir)], pos + dir.normalize() * 5.0);
+                assert!(in_map(ndc), "{:?} -> {:?}", dir, ndc);
+            }
+        }
+    }
+}
+
+#[test]
+fn the_sun_covers_the_view_and_nearer_is_shallower() {
+    let camera = GameCamera::new(16.0 / 9.0);
+    let dir = Vec3::new(0.3, -1.0, 0.5);
+    let sun = Light::directed(dir, white()).with_shadows();
+    let m = shadows::light_matrices(&sun, &camera)[0];
+    // Everything the camera sees, out to the shadow distance
+    for step in 0..=10 {
+        let d = camera.znear + (SHADOW_DISTANCE - camera.znear) * step as f32 / 10.0;
+        for &(x, y) in &[
+            (-1.0, -1.0),
+            (1.0, -1.0),
+            (-1.0, 1.0),
+            (1.0, 1.0),
+            (0.0, 0.0),
+        ] {
+            let ray = camera.ray_through(x, y);
+            let forward = (camera.target - camera.eye).normalize();
+            let p = ray.p + ray.dir * (d / ray.dir.dot(forward));
+            assert!(in_map(project(m, p)), "{:?}", p);
+        }
+    }
+    // Something between the sun and the floor is in front of the floor in
+    // the map, and in the same place
+    let floor = project(m, Pos3::origin());
+    let above = project(m, Pos3::origin() - dir * 2.0);
+    assert!((floor.truncate() - above.truncate()).magnitude() < 1e-4);
+    assert!(above.z < floor.z, "{:?} {:?}", above, floor);
+}
+
+#[test]
+fn spotlights_look_down_their_cones() {
+    let camera = GameCamera::new(1.0);
+    let pos = Pos3::new(0.0, 6.0, 2.0);
+    let dir = Vec3::new(0.0, -1.0, 0.2).normalize();
+    let spot = Light::spot(pos, dir, cgmath::Deg(25.0), cgmath::Deg(35.0), white())
+        .with_range(12.0)
+        .with_shadows();
+    let m = shadows::light_matrices(&spot, &camera)[0];
+    let ahead = project(m, pos + dir * 5.0);
+    assert!(ahead.x.abs() < 1e-4 && ahead.y.abs() < 1e-4, "{:?}", ahead);
+    // The whole cone fits in the map, just, but not what's behind the light
+    let side = dir.cross(Vec3::unit_x()).normalize();
+    let (sin, cos) = 34.9f32.to_radians().sin_cos();
+    assert!(in_map(project(m, pos + (dir * cos + side * sin) * 6.0)));
+    assert!(!in_map(project(m, pos - dir * 2.0)));
+}
@@ -0,0 +1,116 @@
+use engine3d::collision::*;
+use engine3d::geom::*;
+
+const PLAYER: u32 = 1;
+const PICKUP: u32 = 2;
+const SCENERY: u32 = 4;
+
+fn cube(x: f32) -> Box {
+    Box {
+        c: Pos3::new(x, 0.0, 0.0),
+        axes: Mat3::one(),
+        half_sizes: Vec3::new(0.5, 0.5, 0.5),
+    }
+}
+
+#[test]
+fn layers_interact_only_both_ways() {
+    let player = Layers::new(PLAYER, PICKUP | SCENERY);
+    let pickup = Layers::new(PICKUP, PLAYER);
+    let scenery = Layers::new(SCENERY, PLAYER);
+    assert!(player.interacts(pickup));
+    assert!(pickup.interacts(player));
+    assert!(player.interacts(scenery));
+    // Pickups ignore scenery even though scenery would take them
+    assert!(!pickup.interacts(Layers::new(SCENERY, PICKUP)));
+    assert!(!pickup.interacts(scenery));
+    assert!(Layers::ALL.interacts(player));
+    assert!(!Layers::NONE.interacts(Layers::ALL));
+    assert_eq!(Layers::default(), Layers::ALL);
+}
+
+#[test]
+fn layered_gathering_skips_masked_pairs() {
+    let a = [cube(0.0), cube(5.0)];
+    let b = [cube(0.5), cube(5.5)];
+    let alayers = [Layers::new(PLAYER, PICKUP), Layers::new(PLAYER, SCENERY)];
+    let blayers = [Layers::new(PICKUP, PLAYER), Layers::new(PICKUP, PLAYER)];
+    let mut contacts = vec![];
+    gather_contacts_ab_filtered(
+        &a,
+        &b,
+        |ai, bi| alayers[ai].interacts(blayers[bi]),
+        &mut contacts,
+    );
+    assert_eq!(contacts.len(), 1);
+    assert_eq!((contacts[0].a, contacts[0].b), (0, 0));
+
+    let all = [cube(0.0), cube(0.5), cube(1.0)];
+    let layers = [
+        Layers::new(PLAYER, PLAYER),
+        Layers::new(PLAYER, PLAYER),
+        Layers::new(SCENERY, SCENERY),
+    ];
+    contacts.clear();
+    gather_contacts_aa_filtered(
+        &all,
+        |ai, bi| layers[ai].interacts(layers[bi]),
+        &mut contacts,
+    );
+    assert_eq!(contacts.len(), 1);
+    assert_eq!((contacts[0].a, contacts[0].b), (0, 1));
+}
+
+#[test]
+fn triggers_report_enter_stay_exit() {
+    let zones = [cube(0.0), cube(1.8)];
+    let zone_layers = [Layers::new(PICKUP, PLAYER); 2];
+    let player_layers = [Layers::new(PLAYER, PICKUP)];
+    let mut triggers = Triggers::new();
+    let phases = |x: f32, triggers: &mut Triggers| {
+        triggers.update(&[cube(x)], &player_layers, &zones, &zone_layers);
+        triggers
+            .events()
+            .iter()
+            .map(|e| (e.trigger, e.phase))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(phases(-5.0, &mut triggers), vec![]);
+    assert_eq!(phases(-0.8, &mut triggers), vec![(0, TriggerPhase::Enter)]);
+    assert!(triggers.entered(0) && triggers.occupied(0));
+    assert_eq!(phases(0.2, &mut triggers), vec![(0, TriggerPhase::Stay)]);
+    assert!(!triggers.entered(0) && triggers.occupied(0));
+    // Straddling the gap between both zones
+    let straddle = phases(0.9, &mut triggers);
+    assert_eq!(
+        straddle,
+        vec![(0, TriggerPhase::Stay), (1, TriggerPhase::Enter)]
+    );
+    assert_eq!(
+        phases(2.0, &mut triggers),
+        vec![(0, TriggerPhase::Exit), (1, TriggerPhase::Stay)]
+    );
+    assert!(!triggers.occupied(0));
+    assert_eq!(phases(10.0, &mut triggers), vec![(1, TriggerPhase::Exit)]);
+    assert_eq!(phases(10.0, &mut triggers), vec![]);
+
+    // Clearing makes whatever is inside enter again
+    phases(2.0, &mut triggers);
+    triggers.clear();
+    assert_eq!(phases(2.0, &mut triggers), vec![(1, TriggerPhase::Enter)]);
+}
+
+#[test]
+fn switching_layers_off_exits_triggers() {
+    let zone = [cube(0.0)];
+    let zone_layers = [Layers::new(PICKUP, PLAYER)];
+    let body = [cube(0.0)];
+    let mut triggers = Triggers::new();
+    triggers.update(&body, &[Layers::new(PLAYER, PICKUP)], &zone, &zone_layers);
+    assert!(triggers.entered(0));
+    triggers.update(&body, &[Layers::new(PLAYER, 0)], &zone, &zone_layers);
+    assert_eq!(triggers.events()[0].phase, TriggerPhase::Exit);
+    triggers.update(&body, &[Layers::new(PLAYER, PICKUP)], &zone, &zone_layers);
+    assert!(triggers.entered(0));
+}
@@ -0,0 +1,300 @@
+use crate::{WallType, WBHS, WH, WIS, WIZ, WVSF, WW};
+use anyhow::{ensure, Context, Result};
+use engine3d::geom::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// One wall of a level.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct WallPlan {
+    pub wall_type: WallType,
+    /// The cells left out of the wall, as (column, row), counting columns
+    /// from 0 to `WW - 1` and rows from 0 (on the floor) to `WH - 1`.  Holes
+    /// bigger than one cell are just neighbouring cells.
+    pub holes: Vec<(i8, i8)>,
+    /// Holes that aren't made of whole cells, such as slots the player has
+    /// to turn sideways to fit through.  Any cell they cut into is made up
+    /// of smaller boxes around them.
+    #[serde(default)]
+    pub openings: Vec<Rect>,
+    /// How fast the wall comes at the player.
+    pub speed: f32,
+    /// How far in front of the player's start the wall goes up.
+    #[serde(default = "default_distance")]
+    pub distance: f32,
+}
+
+fn default_distance() -> f32 {
+    WIZ
+}
+
+impl WallPlan {
+    /// A wall with one hole somewhere random, a little faster for every
+    /// wall the player has already got through.
+    pub fn random(passed: usize, rng: &mut impl Rng) -> Self {
+        let wall_type = if rng.gen_range(0..2) == 0 {
+            WallType::Diamond
+        } else {
+            WallType::Glass
+        };
+        let hole = (rng.gen_range(0..WW), rng.gen_range(0..WH));
+        Self {
+            wall_type,
+            holes: vec![hole],
+            openings: vec![],
+            speed: WIS * (passed + 1).max(2) as f32 * WVSF,
+            distance: WIZ,
+        }
+    }
+
+    pub fn is_hole(&self, x: i8, y: i8) -> bool {
+        self.holes.contains(&(x, y))
+    }
+
+    /// Every part of the wall that isn't a hole or an opening.  Cells that
+    /// no opening cuts into are kept whole, in column then row order.
+    pub fn solid(&self) -> Vec<Rect> {
+        let open: Vec<Rect> = self
+            .holes
+            .iter()
+            .map(|&(x, y)| Rect::cell(x, y))
+            .chain(self.openings.iter().copied())
+            .collect();
+        let mut solid = vec![];
+        for x in 0..WW {
+            for y in 0..WH {
+                let cell = Rect::cell(x, y);
+                // Cut the cell along the edges of the openings in it, and
+                // keep the pieces that aren't open
+                let mut xs = vec![cell.from.0, cell.to.0];
+                let mut ys = vec![cell.from.1, cell.to.1];
+                for o in open.iter().filter(|o| o.overlaps(&cell)) {
+                    xs.extend(
+                        [o.from.0, o.to.0]
+                            .iter()
+                            .filter(|&&v| cell.from.0 < v && v < cell.to.0),
+                    );
+                    ys.extend(
+                        [o.from.1, o.to.1]
+                            .iter()
+                            .filter(|&&v| cell.from.1 < v && v < cell.to.1),
+                    );
+                }
+                xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                ys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                xs.dedup();
+                ys.dedup();
+                for xw in xs.windows(2) {
+                    for yw in ys.windows(2) {
+                        let piece = Rect {
+                            from: (xw[0], yw[0]),
+                            to: (xw[1], yw[1]),
+                        };
+                        let (cx, cy) = piece.center();
+                        if !open.iter().any(|o| o.contains(cx, cy)) {
+                            solid.push(piece);
+                        }
+                    }
+                }
+            }
+        }
+        solid
+    }
+
+    /// Would `b` get through the wall without touching it, heading straight
+    /// at it?  This is exact for any orientation of `b`: its outline, seen
+    /// from in front of the wall, has to miss every solid part of the wall.
+    /// Just touching the edge of a hole is fine.
+    pub fn fits(&self, b: &Box) -> bool {
+        let outline = outline(b);
+        self.solid().iter().all(|r| !overlaps(&outline, r))
+    }
+}
+
+/// A rectangle on the face of a wall, measured in cells from the bottom
+/// left corner of the wall, so the cell hole `(x, y)` is the rectangle
+/// from `(x, y)` to `(x + 1, y + 1)`.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Rect {
+    pub from: (f32, f32),
+    pub to: (f32, f32),
+}
+
+impl Rect {
+    pub fn cell(x: i8, y: i8) -> Self {
+        Self {
+            from: (x as f32, y as f32),
+            to: (x as f32 + 1.0, y as f32 + 1.0),
+        }
+    }
+    pub fn center(&self) -> (f32, f32) {
+        (
+            (self.from.0 + self.to.0) / 2.0,
+            (self.from.1 + self.to.1) / 2.0,
+        )
+    }
+    pub fn size(&self) -> (f32, f32) {
+        (self.to.0 - self.from.0, self.to.1 - self.from.1)
+    }
+    /// Do `self` and `other` share more than an edge?
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.from.0 < other.to.0
+            && other.from.0 < self.to.0
+            && self.from.1 < other.to.1
+            && other.from.1 < self.to.1
+    }
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        self.from.0 <= x && x <= self.to.0 && self.from.1 <= y && y <= self.to.1
+    }
+}
+
+/// Where the point `p` is on the face of the wall, in cells.
+pub fn to_cells(p: Pos3) -> (f32, f32) {
+    ((p.x + WW as f32 * WBHS) / (2.0 * WBHS), p.y / (2.0 * WBHS))
+}
+
+/// Where the middle of the wall piece `r` is, for a wall at `wall_z`.
+pub fn from_cells(r: &Rect, wall_z: f32) -> Pos3 {
+    let (x, y) = r.center();
+    Pos3::new(x * 2.0 * WBHS - WW as f32 * WBHS, y * 2.0 * WBHS, wall_z)
+}
+
+/// The outline of `b` seen from in front of the wall, in cells: the convex
+/// hull of its corners, counterclockwise.
+fn outline(b: &Box) -> Vec<(f32, f32)> {
+    let mut corners = vec![];
+    for &sx in &[-1.0, 1.0] {
+        for &sy in &[-1.0, 1.0] {
+            for &sz in &[-1.0, 1.0] {
+                let h = b.half_sizes;
+                let p = b.c + b.axes.x * (sx * h.x) + b.axes.y * (sy * h.y) + b.axes.z * (sz * h.z);
+                corners.push(to_cells(p));
+            }
+        }
+    }
+    corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    // Andrew's monotone chain: the bottom of the hull left to right, then
+    // the top right to left
+    let mut lower = half_hull(corners.iter().copied());
+    let mut upper = half_hull(corners.iter().rev().copied());
+    lower.pop();
+    upper.pop();
+    lower.extend(upper);
+    lower
+}
+
+fn half_hull(points: impl Iterator<Item = (f32, f32)>) -> Vec<(f32, f32)> {
+    let cross = |o: (f32, f32), a: (f32, f32), b: (f32, f32)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let mut hull: Vec<(f32, f32)> = vec![];
+    for p in points {
+        while hull.len() >= 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(p);
+    }
+    hull
+}
+
+/// Do the convex polygon `poly` and the rectangle `r` overlap by more than
+/// a sliver?  Separating axis test over the rectangle's axes and the
+/// polygon's edge normals.
+fn overlaps(poly: &[(f32, f32)], r: &Rect) -> bool {
+    const TOUCHING: f32 = 1e-4;
+    let corners = [r.from, (r.to.0, r.from.1), r.to, (r.from.0, r.to.1)];
+    let mut axes = vec![(1.0, 0.0), (0.0, 1.0)];
+    for (i, &a) in poly.iter().enumerate() {
+        let b = poly[(i + 1) % poly.len()];
+        axes.push((b.1 - a.1, a.0 - b.0));
+    }
+    let project = |pts: &[(f32, f32)], (ax, ay): (f32, f32)| {
+        let len = (ax * ax + ay * ay).sqrt();
+        pts.iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), p| {
+                let d = (p.0 * ax + p.1 * ay) / len;
+                (lo.min(d), hi.max(d))
+            })
+    };
+    axes.into_iter()
+        .filter(|&(ax, ay)| ax != 0.0 || ay != 0.0)
+        .all(|axis| {
+            let (plo, phi) = project(poly, axis);
+            let (rlo, rhi) = project(&corners, axis);
+            phi > rlo + TOUCHING && rhi > plo + TOUCHING
+        })
+}
+
+/// A run of walls for the player to get through, in order.  Once they run
+/// out the walls are made up with `WallPlan::random`.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct Level {
+    pub walls: Vec<WallPlan>,
+}
+
+impl Level {
+    /// Load one level file, checking that its holes are in the wall and
+    /// its openings aren't empty.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Couldn't open {}", path.display()))?;
+        let level: Level = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Bad level file {}", path.display()))?;
+        for w in level.walls.iter() {
+            for &(x, y) in w.holes.iter() {
+                ensure!(
+                    (0..WW).contains(&x) && (0..WH).contains(&y),
+                    "hole ({}, {}) in {} is outside the wall",
+                    x,
+                    y,
+                    path.display()
+                );
+            }
+            for o in w.openings.iter() {
+                let (width, height) = o.size();
+                ensure!(
+                    width > 0.0 && height > 0.0,
+                    "opening {:?} in {} is empty",
+                    o,
+                    path.display()
+                );
+            }
+        }
+        Ok(level)
+    }
+
+    /// Every `.json` file in `dir`, played one after another in order of
+    /// file name.  With no such directory, or if any of the files can't be
+    /// loaded, there are only random walls.
+    pub fn load_dir(dir: &Path) -> Self {
+        let mut paths: Vec<_> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .map(|e| e.unwrap().path())
+                .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+                .collect(),
+            Err(_) => vec![],
+        };
+        paths.sort();
+        let mut walls = vec![];
+        for p in paths.iter() {
+            match Level::load(p) {
+                Ok(level) => walls.extend(level.walls),
+                Err(e) => {
+                    eprintln!("{:?}", e.context("Playing random walls instead"));
+                    return Self::default();
+                }
+            }
+        }
+        Self { walls }
+    }
+
+    /// The wall to put up once the player has got through `passed` walls.
+    pub fn wall(&self, passed: usize, rng: &mut impl Rng) -> WallPlan {
+        match self.walls.get(passed) {
+            Some(w) => w.clone(),
+            None => WallPlan::random(passed, rng),
+        }
+    }
+}
@@ -0,0 +1,928 @@
+use ambisonic::Ambisonic;
+use ambisonic::SoundController;
+use ambisonic::{rodio, AmbisonicBuilder};
+use cgmath::Matrix3;
+use engine3d::{
+    assets::ModelRef,
+    camera::*,
+    collision::{self, Contact, Layers},
+    ecs::{AudioEmitter, Collider, Entity, Mass, Model, Sleep, Transform, Velocity, World},
+    fracture::{self, Debris},
+    geom::*,
+    lights::Light,
+    physics::Material,
+    render::InstanceGroups,
+    scene::NodeId,
+    Engine, RngState, DT,
+};
+use rand::Rng;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Write;
+use winit::event::VirtualKeyCode as KeyCode;
+
+pub mod level;
+use level::{Level, WallPlan};
+
+const G: f32 = 5.0;
+const MIN_VEL: f32 = 0.1; // if absolute velocity is below this value, consider the object to be stationary
+const MBHS: f32 = 0.5; // menu box half size
+const WBHS: f32 = 1.0; // wall box half size
+const PBHS: f32 = 0.5; // player box half height
+const PBHW: f32 = 0.8; // player box half width
+const PBHD: f32 = 0.3; // player box half depth
+const WH: i8 = 3; // wall height in boxes
+const WW: i8 = 6; // wall width in boxes
+const WIS: f32 = 2.0; // initial speed of wall
+const WIZ: f32 = 20.0; // initial z position of wall
+const WVSF: f32 = 0.5; // wall velocity scaling factor
+const WBM: f32 = 8.0; // wall box mass
+const PM: f32 = 1.0; // player mass
+
+// surface materials: static friction, dynamic friction, restitution
+const PLAYER_MATERIAL: Material = Material::new(0.5, 0.5, 0.0);
+const FLOOR_MATERIAL: Material = Material::new(0.5, 0.4, 0.0);
+const DIAMOND_MATERIAL: Material = Material::new(0.8, 0.6, 0.2); // rough and dull
+const GLASS_MATERIAL: Material = Material::new(0.2, 0.1, 0.4); // slick and bouncy
+const WBF: usize = 4; // fragments each wall box breaks into
+const WBBS: f32 = 2.0; // wall break burst speed
+const DR: f32 = 30.0; // debris is cleared away past this distance from the player
+
+// collision layers
+const PLAYER_LAYER: u32 = 1;
+const MENU_LAYER: u32 = 2; // triggers live in the menu
+const END_LAYER: u32 = 4; // triggers live on the end screen
+const WALL_LAYER: u32 = 8;
+const FLOOR_LAYER: u32 = 16;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Mode {
+    Menu,
+    GamePlay,
+    EndScreen,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum WallType {
+    Diamond,
+    Glass,
+}
+
+// #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Wall {
+    // what the wall looks like and how it moves once it's launched
+    pub plan: WallPlan,
+    pub boxes: Vec<Entity>,
+    // plays the wall's sound, following the wall along
+    pub sound: Entity,
+    diamond_model: ModelRef,
+    glass_model: ModelRef,
+    control: (i8, i8),
+}
+
+impl Wall {
+    pub fn generate_components(wall_z: f32, axes: Mat3, plan: &WallPlan) -> Vec<Box> {
+        plan.solid()
+            .iter()
+            .map(|r| {
+                let (w, h) = r.size();
+                Box {
+                    c: level::from_cells(r, wall_z),
+                    axes,
+                    half_sizes: Vec3::new(w * WBHS, h * WBHS, WBHS),
+                }
+            })
+            .collect()
+    }
+
+    fn model(&self) -> ModelRef {
+        match self.plan.wall_type {
+            WallType::Diamond => self.diamond_model,
+            WallType::Glass => self.glass_model,
+        }
+    }
+
+    fn material(&self) -> Material {
+        match self.plan.wall_type {
+            WallType::Diamond => DIAMOND_MATERIAL,
+            WallType::Glass => GLASS_MATERIAL,
+        }
+    }
+
+    /// Put up the wall `plan` describes at `wall_z`, in place of the old
+    /// one.  It stands still until it's launched.
+    fn build(&mut self, world: &mut World, plan: &WallPlan, wall_z: f32) {
+        for e in self.boxes.drain(..) {
+            world.despawn(e);
+        }
+        self.plan = plan.clone();
+        self.control = (0, 0);
+        let model = self.model();
+        let material = self.material();
+        for b in Wall::generate_components(wall_z, Mat3::one(), plan).iter() {
+            let e = world.spawn();
+            world.transforms.insert(
+                e,
+                Transform {
+                    pos: b.c,
+                    rot: Quat::from(b.axes),
+                },
+            );
+            world.velocities.insert(e, Velocity::default());
+            // pieces of cells cut up around an opening weigh less
+            let cells = b.half_sizes.x * b.half_sizes.y / (WBHS * WBHS);
+            world.masses.insert(e, Mass::new(b, WBM * cells));
+            world.colliders.insert(
+                e,
+                Collider::cuboid(b.half_sizes)
+                    .with_layers(Layers::new(WALL_LAYER, PLAYER_LAYER))
+                    .with_material(material),
+            );
+            world
+                .models
+                .insert(e, Model::scaled(model, b.half_sizes).casting_shadow());
+            self.boxes.push(e);
+        }
+    }
+
+    /// Set the whole wall moving at its speed.
+    fn launch(&self, world: &mut World) {
+        for &e in self.boxes.iter() {
+            world.velocities[e] = Velocity::linear(Vec3::new(0.0, 0.0, -self.plan.speed));
+        }
+    }
+
+    /// Put up the wall `plan` describes and send it at the player.
+    fn reset(&mut self, world: &mut World, plan: &WallPlan) {
+        self.build(world, plan, plan.distance);
+        self.launch(world);
+    }
+
+    /// Has the wall reached `b` along z?  From then until it's gone past,
+    /// whether `b` gets hit is down to `WallPlan::fits`.
+    fn overlaps_z(&self, world: &World, b: &Box) -> bool {
+        let wall_z = world.transforms[self.boxes[0]].pos.z;
+        let depth = (0..3)
+            .map(|i| b.axes[i].z.abs() * b.half_sizes[i])
+            .sum::<f32>();
+        (wall_z - b.c.z).abs() < WBHS + depth
+    }
+}
+
+pub struct Audio {
+    // None when running headless
+    scene: Option<Ambisonic>,
+    // what each entity's audio emitter is playing
+    emitting: BTreeMap<Entity, SoundController>,
+    sound2: Option<SoundController>,
+    sound3: Option<SoundController>,
+}
+
+impl Audio {
+    fn play_at(
+        &self,
+        path: &str,
+        amplify: f32,
+        repeat: bool,
+        posn: [f32; 3],
+    ) -> Option<SoundController> {
+        let scene = self.scene.as_ref()?;
+        let file = std::fs::File::open(path).unwrap();
+        let source = rodio::Decoder::new(BufReader::new(file)).unwrap();
+        let source = source.amplify(amplify);
+        if repeat {
+            Some(scene.play_at(source.repeat_infinite().convert_samples(), posn))
+        } else {
+            Some(scene.play_at(source.convert_samples(), posn))
+        }
+    }
+
+    fn stop(sound: &mut Option<SoundController>) {
+        if let Some(sound) = sound.take() {
+            sound.stop();
+        }
+    }
+
+    /// Start, move and stop sounds to match the world's audio emitters.
+    fn sync(&mut self, world: &World) {
+        for (e, emitter, posn) in world.emitters() {
+            let posn = [posn.x, posn.y, posn.z];
+            if !emitter.playing {
+                Audio::stop(&mut self.emitting.remove(&e));
+            } else if let Some(sound) = self.emitting.get_mut(&e) {
+                sound.adjust_position(posn);
+            } else if let Some(sound) =
+                self.play_at(&emitter.sound, emitter.volume, emitter.looping, posn)
+            {
+                self.emitting.insert(e, sound);
+            }
+        }
+    }
+
+    /// Play `e`'s emitter over from the start on the next `sync`.
+    fn restart(&mut self, e: Entity) {
+        Audio::stop(&mut self.emitting.remove(&e));
+    }
+}
+
+// #[derive(Serialize, Deserialize, Debug)]
+// #[derive(Debug)]
+pub struct Game<Cam: Camera> {
+    pub world: World,
+    start: Entity,
+    scores: Entity,
+    // shows the score, sitting on top of the scores box
+    score_label: NodeId,
+    play_again: Entity,
+    load_save: Entity,
+    pub wall: Wall,
+    // the walls to come
+    pub level: Level,
+    floor: Entity,
+    pub player: Player,
+    camera: Cam,
+    contacts: Vec<Contact<Entity>>,
+    // the player's contacts with the wall and floor, the player first
+    pw: Vec<Contact<Entity>>,
+    pf: Vec<Contact<Entity>>,
+    // player - wall impacts coming up this step, so fast walls can't skip the player
+    pwi: Vec<collision::Impact<Entity>>,
+    // what's left of the wall after the player breaks it
+    debris: Debris,
+    pub mode: Mode,
+    pub score: i8,
+    pub high_score: i8,
+    audio: Audio,
+    state: GameState,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GameState {
+    wall_z: f32,
+    // older saves have just the one hole, in missing_x and missing_y
+    #[serde(default)]
+    holes: Vec<(i8, i8)>,
+    #[serde(default)]
+    openings: Vec<level::Rect>,
+    #[serde(default, skip_serializing)]
+    missing_x: Option<i8>,
+    #[serde(default, skip_serializing)]
+    missing_y: Option<i8>,
+    // older saves work the wall's speed out from the score
+    #[serde(default)]
+    wall_speed: Option<f32>,
+    wall_type: WallType,
+    #[serde(with = "Pos3Def")]
+    player_posn: Pos3,
+    score: i8,
+    // older saves have no RNG state, and just keep the current sequence
+    #[serde(default)]
+    rng: Option<RngState>,
+}
+
+pub struct GameData {
+    pub diamond_wall_model: engine3d::assets::ModelRef,
+    pub glass_wall_model: engine3d::assets::ModelRef,
+    pub player_model: engine3d::assets::ModelRef,
+    score_models: Vec<engine3d::assets::ModelRef>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Player {
+    pub entity: Entity,
+    #[serde(with = "Vec3Def")]
+    pub acc: Vec3,
+}
+
+impl Player {
+    const MAX_SPEED: f32 = 3.0;
+    fn accelerate(&self, world: &mut World) {
+        let rot = world.transforms[self.entity].rot;
+        let vel = &mut world.velocities[self.entity].linear;
+        *vel += rot * self.acc;
+        if vel.magnitude() > Self::MAX_SPEED {
+            *vel = vel.normalize_to(Self::MAX_SPEED);
+        }
+    }
+}
+
+/// A menu object: a box showing an option, or the score.
+fn spawn_menu_object(world: &mut World, c: Pos3, model: ModelRef) -> Entity {
+    let e = world.spawn();
+    world.transforms.insert(e, Transform::at(c));
+    world
+        .models
+        .insert(e, Model::scaled(model, Vec3::new(MBHS, MBHS, MBHS)));
+    e
+}
+
+/// A menu object that the player walks into to pick its option, on the
+/// screens in `layer`.
+fn spawn_menu_trigger(world: &mut World, c: Pos3, model: ModelRef, layer: u32) -> Entity {
+    let e = spawn_menu_object(world, c, model);
+    world.colliders.insert(
+        e,
+        Collider::cuboid(Vec3::new(MBHS, MBHS, MBHS))
+            .with_layers(Layers::new(layer, PLAYER_LAYER))
+            .as_trigger(),
+    );
+    e
+}
+
+/// `e`'s contacts with anything `other` picks out, each with `e` first.
+fn contacts_of(
+    contacts: &[Contact<Entity>],
+    e: Entity,
+    other: impl Fn(Entity) -> bool,
+) -> Vec<Contact<Entity>> {
+    contacts
+        .iter()
+        .filter_map(|c| {
+            if c.a == e && other(c.b) {
+                Some(*c)
+            } else if c.b == e && other(c.a) {
+                Some(Contact {
+                    a: c.b,
+                    b: c.a,
+                    mtv: -c.mtv,
+                    manifold: c.manifold.flipped(),
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn posed_box(world: &World, e: Entity) -> Box {
+    match world.shape(e) {
+        Some(AnyShape::Box(b)) => b,
+        s => panic!("{:?} should be a box, not {:?}", e, s),
+    }
+}
+
+impl<C: Camera> Game<C> {
+    pub fn player_box(&self) -> Box {
+        posed_box(&self.world, self.player.entity)
+    }
+
+    pub fn wall_boxes(&self) -> Vec<Box> {
+        self.wall
+            .boxes
+            .iter()
+            .map(|&e| posed_box(&self.world, e))
+            .collect()
+    }
+
+    fn player_posn(&self) -> Pos3 {
+        self.world.transforms[self.player.entity].pos
+    }
+
+    fn set_player_posn(&mut self, posn: Pos3) {
+        self.world.transforms[self.player.entity].pos = posn;
+    }
+
+    // back to the start, standing still
+    fn reset_player(&mut self) {
+        self.set_player_posn(Pos3::new(0.0, PBHS, 0.0));
+        self.world.velocities[self.player.entity] = Velocity::default();
+    }
+
+    // the player meets menu objects only on the screens that show them, and
+    // the wall only knocks into itself and the floor once it's broken
+    fn set_layers(&mut self) {
+        let (menu_mask, wall_mask) = match self.mode {
+            Mode::Menu => (MENU_LAYER, PLAYER_LAYER),
+            Mode::GamePlay => (0, PLAYER_LAYER),
+            Mode::EndScreen => (END_LAYER, PLAYER_LAYER | WALL_LAYER | FLOOR_LAYER),
+        };
+        let colliders = &mut self.world.colliders;
+        colliders[self.player.entity].layers =
+            Layers::new(PLAYER_LAYER, FLOOR_LAYER | WALL_LAYER | menu_mask);
+        for e in self.wall.boxes.iter().copied().chain(self.debris.pieces()) {
+            colliders[e].layers = Layers::new(WALL_LAYER, wall_mask);
+        }
+    }
+
+    // show the objects for the current screen
+    fn show(&mut self, rules: &GameData) {
+        let menu = self.mode == Mode::Menu;
+        let end = self.mode == Mode::EndScreen;
+        let models = &mut self.world.models;
+        models[self.start].visible = menu;
+        models[self.load_save].visible = menu || end;
+        models[self.play_again].visible = end;
+        models[self.scores].visible = menu || end;
+        let scene = &mut self.world.scene;
+        scene.set_visible(self.score_label, menu || end);
+        scene.set_model(
+            self.score_label,
+            Some(rules.score_models[self.score as usize]),
+        );
+        for &e in self.wall.boxes.iter() {
+            models[e].visible = !menu;
+        }
+    }
+
+    fn play_wall_sound(&mut self, looping: bool) {
+        let emitter = &mut self.world.emitters[self.wall.sound];
+        emitter.looping = looping;
+        emitter.playing = true;
+        self.audio.restart(self.wall.sound);
+    }
+}
+
+impl<C: Camera> engine3d::Game for Game<C> {
+    type StaticData = GameData;
+    fn start(engine: &mut Engine) -> (Self, Self::StaticData) {
+        // models
+        // TODO: update .obj and .mtl files
+        let menu_object_model = engine.load_model("box.obj");
+        let diamond_wall_model = engine.load_model("wall.obj");
+        let glass_wall_model = engine.load_model("glass-box.obj");
+        let floor_model = engine.load_model("floor.obj");
+        let player_model = engine.load_model("cube.obj");
+        let start_model = engine.load_model("start.obj");
+        let load_model = engine.load_model("load.obj");
+        let score_models = vec![
+            engine.load_model("score0.obj"),
+            engine.load_model("score1.obj"),
+            engine.load_model("score2.obj"),
+            engine.load_model("score3.obj"),
+            engine.load_model("score4.obj"),
+            engine.load_model("score5.obj"),
+            engine.load_model("score6.obj"),
+            engine.load_model("score7.obj"),
+            engine.load_model("score8.obj"),
+            engine.load_model("score9.obj"),
+        ];
+
+        let mut world = World::new();
+
+        // create player
+        let player_body = Box {
+            c: Pos3::new(0.0, PBHS, 0.0),
+            axes: Matrix3::one(),
+            half_sizes: Vec3::new(PBHW, PBHS, PBHD),
+        };
+        let player = Player {
+            entity: world.spawn(),
+            acc: Vec3::zero(),
+        };
+        let pe = player.entity;
+        world.transforms.insert(pe, Transform::at(player_body.c));
+        world.velocities.insert(pe, Velocity::default());
+        world.masses.insert(pe, Mass::new(&player_body, PM));
+        world.colliders.insert(
+            pe,
+            Collider::cuboid(player_body.half_sizes).with_material(PLAYER_MATERIAL),
+        );
+        world.models.insert(
+            pe,
+            Model::scaled(player_model, player_body.half_sizes).casting_shadow(),
+        );
+        world
+            .emitters
+            .insert(pe, AudioEmitter::new("content/boxMovement.wav", 0.25, true));
+
+        // create platform
+        let floor = world.spawn();
+        world
+            .transforms
+            .insert(floor, Transform::at(Pos3::origin()));
+        world.colliders.insert(
+            floor,
+            Collider::new(AnyShape::Plane(Plane {
+                n: Vec3::new(0.0, 1.0, 0.0),
+                d: 0.0,
+            }))
+            .with_layers(Layers::new(FLOOR_LAYER, PLAYER_LAYER | WALL_LAYER))
+            .with_material(FLOOR_MATERIAL),
+        );
+        world.models.insert(
+            floor,
+            Model {
+                local: Mat4::from_translation(Vec3::new(0.0, -0.025, 0.0))
+                    * Mat4::from_nonuniform_scale(0.5, 0.05, 0.5),
+                ..Model::new(floor_model)
+            },
+        );
+
+        // create menu objects
+        let start = spawn_menu_trigger(
+            &mut world,
+            Pos3::new(3.0, MBHS, 0.0),
+            start_model,
+            MENU_LAYER,
+        );
+        let scores = spawn_menu_object(&mut world, Pos3::new(-3.0, MBHS, 0.0), menu_object_model);
+        let scores_node = world.node(scores);
+        let score_label = world.scene.add(Some(scores_node));
+        world
+            .scene
+            .set_translation(score_label, Vec3::new(0.0, 1.5 * MBHS, 0.0));
+        world
+            .scene
+            .set_scale(score_label, Vec3::new(0.5 * MBHS, 0.5 * MBHS, 0.5 * MBHS));
+        let play_again = spawn_menu_trigger(
+            &mut world,
+            Pos3::new(3.0, MBHS, 0.0),
+            menu_object_model,
+            END_LAYER,
+        );
+        let load_save = spawn_menu_trigger(
+            &mut world,
+            Pos3::new(0.0, MBHS, 3.0),
+            load_model,
+            MENU_LAYER | END_LAYER,
+        );
+
+        // create wall, which stands still until the game starts
+        let sound = world.spawn();
+        world
+            .transforms
+            .insert(sound, Transform::at(Pos3::new(0.0, 0.0, WIZ)));
+        world.emitters.insert(
+            sound,
+            AudioEmitter::new("content/wallTrainSound.mp3", 3.0, true),
+        );
+        let level = Level::load_dir(&engine.assets.asset_path("levels"));
+        let plan = level.wall(0, engine.rng());
+        let mut wall = Wall {
+            plan: plan.clone(),
+            boxes: vec![],
+            sound,
+            diamond_model: diamond_wall_model,
+            glass_model: glass_wall_model,
+            control: (0, 0),
+        };
+        wall.build(&mut world, &plan, plan.distance);
+
+        // light the platform from above and behind the player, so the
+        // player and the wall cast shadows onto it
+        engine.set_ambient(0.5);
+        engine.set_lights(vec![Light::directed(
+            Vec3::new(0.3, -1.0, 0.5),
+            Vec3::new(0.7, 0.7, 0.7),
+        )
+        .with_shadows()]);
+
+        // create camera
+        let camera = C::new(player_body.c);
+
+        // there is no audio device to play on when running headless
+        let scene = if engine.is_headless() {
+            None
+        } else {
+            Some(AmbisonicBuilder::default().build())
+        };
+
+        let audio = Audio {
+            scene,
+            emitting: BTreeMap::new(),
+            sound2: None,
+            sound3: None,
+        };
+
+        let state = GameState {
+            wall_z: plan.distance,
+            holes: plan.holes.clone(),
+            openings: plan.openings.clone(),
+            missing_x: None,
+            missing_y: None,
+            wall_speed: Some(plan.speed),
+            wall_type: plan.wall_type.clone(),
+            player_posn: player_body.c,
+            score: 0,
+            rng: None,
+        };
+
+        let rules = GameData {
+            diamond_wall_model,
+            glass_wall_model,
+            player_model,
+            score_models,
+        };
+
+        // create game
+        let mut game = Self {
+            world,
+            start,
+            scores,
+            score_label,
+            play_again,
+            load_save,
+            wall,
+            level,
+            floor,
+            player,
+            camera,
+            contacts: vec![],
+            pw: vec![],
+            pf: vec![],
+            pwi: vec![],
+            debris: Debris::new(),
+            mode: Mode::Menu,
+            score: 0,
+            high_score: 0,
+            audio,
+            state
+            // sources: vec![source1],
+            // sources: vec![source1, source2, source3, source4],
+        };
+        game.show(&rules);
+        game.world.sync_scene();
+        (game, rules)
+    }
+
+    fn render(&self, _rules: &Self::StaticData, igs: &mut InstanceGroups) {
+        self.world.render(igs);
+    }
+
+    fn handle_collision(&mut self) {
+        self.set_layers();
+        self.contacts.clear();
+        self.world.gather_contacts(&mut self.contacts);
+
+        let (player, floor) = (self.player.entity, self.floor);
+        let wall = &self.wall.boxes;
+        self.pf = contacts_of(&self.contacts, player, |e| e == floor);
+        self.pw = contacts_of(&self.contacts, player, |e| wall.contains(&e));
+
+        self.world.solve(&self.contacts, DT);
+        self.world.update_sleep(&self.contacts);
+        self.world.update_triggers();
+
+        // player - wall, over the whole of the coming step
+        self.pwi.clear();
+        if self.mode == Mode::GamePlay {
+            self.pwi = self.world.gather_impacts(player, &self.wall.boxes, DT);
+        }
+    }
+
+    fn update(&mut self, rules: &Self::StaticData, engine: &mut Engine) {
+        self.player.acc = Vec3::zero();
+        let player = self.player.entity;
+
+        // how much the player velocity changes per button click
+        // (enough to get going against the floor's friction)
+        let h_disp = Vec3::new(0.08, 0.0, 0.0);
+        let v_disp = Vec3::new(0.0, 0.30, 0.0);
+        let z_disp = Vec3::new(0.0, 0.0, 0.08);
+        let g_disp = Vec3::new(0.0, -G, 0.0);
+
+        // player should not go past these bounds
+        let top_bound = WH as f32 * WBHS * 2.0;
+        let left_bound = WW as f32 * WBHS - 2.0;
+        let right_bound = -left_bound + WBHS - 1.0;
+        let front_bound = WIZ;
+        let back_bound = 0.0;
+
+        // apply gravity here instead of integrate() so handle_collision can deal with gravity smoothly
+        self.world.velocities[player].linear += g_disp * DT;
+        for e in self.debris.pieces() {
+            if !self.world.is_asleep(e) {
+                self.world.velocities[e].linear += g_disp * DT;
+            }
+        }
+
+        self.handle_collision();
+
+        // move player
+        let psn = self.player_posn();
+        if engine.events.key_held(KeyCode::A) && psn.x + PBHS + h_disp.x <= left_bound {
+            self.player.acc += h_disp;
+        } else if engine.events.key_held(KeyCode::D) && psn.x + PBHS - h_disp.x >= right_bound {
+            self.player.acc -= h_disp;
+        }
+        if engine.events.key_held(KeyCode::W) && psn.z + PBHS + z_disp.x <= front_bound {
+            self.player.acc += z_disp;
+        } else if engine.events.key_held(KeyCode::S) && psn.z + PBHS - z_disp.x >= back_bound {
+            self.player.acc -= z_disp;
+        }
+        if engine.events.key_held(KeyCode::Space) && psn.y + PBHS + v_disp.y <= top_bound {
+            self.player.acc += v_disp;
+        }
+
+        if self.player.acc.magnitude2() > 1.0 {
+            self.player.acc = self.player.acc.normalize();
+        }
+
+        // rotate player
+        self.world.velocities[player].angular = if engine.events.key_held(KeyCode::Q) {
+            Vec3::unit_y()
+        } else if engine.events.key_held(KeyCode::E) {
+            -Vec3::unit_y()
+        } else {
+            Vec3::zero()
+        };
+
+        // save game state
+        if self.mode == Mode::GamePlay && engine.events.key_pressed(KeyCode::Return) {
+            let serialized = serde_json::to_string(&self.state).unwrap();
+            let mut file = File::create("savefile.txt").unwrap();
+            file.write_all(serialized.as_bytes()).unwrap();
+        }
+        // update game state
+        if let Some(&b) = self.wall.boxes.first() {
+            self.state.wall_z = self.world.transforms[b].pos.z;
+        }
+        self.state.holes = self.wall.plan.holes.clone();
+        self.state.openings = self.wall.plan.openings.clone();
+        self.state.wall_speed = Some(self.wall.plan.speed);
+        self.state.wall_type = self.wall.plan.wall_type.clone();
+        self.state.player_posn = psn;
+        self.state.score = self.score;
+        self.state.rng = Some(engine.rng_state());
+
+        // orbit camera
+        self.camera.update(&engine.events, psn);
+
+        self.player.accelerate(&mut self.world);
+        self.world.integrate(DT);
+        if self.world.velocities[player].linear.magnitude() < MIN_VEL {
+            self.set_player_posn(psn);
+        }
+        self.camera.integrate();
+
+        // if player is not moving, or player is not on the ground, remove sound
+        let vel = self.world.velocities[player].linear;
+        self.world.emitters[player].playing = !((vel.x.abs() <= 0.1
+            && vel.z.abs() <= 0.1
+            && self.player.acc.x.abs() <= 0.01
+            && self.player.acc.z.abs() <= 0.01)
+            || self.pf.is_empty());
+
+        // handle game transitions
+        let triggers = self.world.triggers();
+        match self.mode {
+            Mode::Menu => {
+                // if player hits start menu object, start game
+                let start = triggers.entered(self.start);
+                let load = triggers.entered(self.load_save);
+                if start {
+                    self.mode = Mode::GamePlay;
+                    // reset player position and score
+                    self.reset_player();
+                    self.score = 0;
+                    self.wall.launch(&mut self.world);
+                    // start playing wall sound
+                    self.play_wall_sound(true);
+                }
+                // if player hits load save object, load save
+                if load {
+                    self.mode = Mode::GamePlay;
+                    self.load_game(engine);
+                    // start playing wall sound
+                    self.play_wall_sound(true);
+                }
+            }
+            Mode::GamePlay => {
+                // if player hits wall, end game: once the wall reaches the
+                // player it's down to whether they fit through the holes,
+                // before that to whether the wall will hit them this step
+                let player_box = self.player_box();
+                let hit = if self.wall.overlaps_z(&self.world, &player_box) {
+                    !self.wall.plan.fits(&player_box)
+                } else {
+                    !self.pw.is_empty() || !self.pwi.is_empty()
+                };
+                if hit {
+                    self.mode = Mode::EndScreen;
+                    // stop playing wall sound
+                    self.world.emitters[self.wall.sound].playing = false;
+                    // Break the wall up where the player hit it, and blow the
+                    // pieces away from the player and toward the back
+                    let wall_z = self.world.transforms[self.wall.boxes[0]].pos.z;
+                    let impact = Pos3::new(player_box.c.x, player_box.c.y, wall_z);
+                    for e in self.wall.boxes.drain(..) {
+                        let rng = engine.rng();
+                        let pieces = fracture::shatter(&mut self.world, e, impact, WBF, rng);
+                        for &p in pieces.iter() {
+                            let away = (self.world.transforms[p].pos - impact + Vec3::unit_z())
+                                .normalize();
+                            let v = &mut self.world.velocities[p];
+                            v.linear += away * WBBS * rng.gen_range(0.5..1.5);
+                            v.angular =
+                                Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())
+                                    .normalize();
+                            // settled pieces stop costing anything
+                            self.world.sleep.insert(p, Sleep::default());
+                        }
+                        self.debris.add(pieces);
+                    }
+                    // play wall break sound
+                    let wall_posn = [impact.x, impact.y, impact.z];
+                    match self.wall.plan.wall_type {
+                        WallType::Diamond => {
+                            self.audio.sound2 = self.audio.play_at(
+                                "content/wallBreakSound.wav",
+                                1.5,
+                                false,
+                                wall_posn,
+                            );
+                        }
+                        WallType::Glass => {
+                            self.audio.sound3 = self.audio.play_at(
+                                "content/wallBreakSoundGlass.mp3",
+                                1.5,
+                                false,
+                                wall_posn,
+                            );
+                        }
+                    }
+                    // TODO: record and write score to file
+                    // reset score and player position
+                    // self.score = 0;
+                    self.reset_player();
+                } else if self.world.transforms[self.wall.boxes[0]].pos.z + WBHS
+                    < self.player_posn().z - 2.0 * WBHS
+                {
+                    // if wall passes camera, increment score and reset wall
+                    self.score += 1;
+                    if self.score > self.high_score {
+                        self.high_score = self.score;
+                    }
+                    let plan = self.level.wall(self.score as usize, engine.rng());
+                    self.wall.reset(&mut self.world, &plan);
+                    // reset wall sound
+                    self.play_wall_sound(false);
+                }
+            }
+            Mode::EndScreen => {
+                // if player hits play again menu object, start game
+                let play_again = triggers.entered(self.play_again);
+                let load = triggers.entered(self.load_save);
+                if play_again {
+                    self.mode = Mode::GamePlay;
+                    // reset wall and player position and score
+                    self.reset_player();
+                    self.score = 0;
+                    self.debris.clear(&mut self.world);
+                    let plan = self.level.wall(0, engine.rng());
+                    self.wall.reset(&mut self.world, &plan);
+                    // start playing wall sound
+                    self.play_wall_sound(false);
+                }
+                // if player hits load save object, load save
+                if load {
+                    self.mode = Mode::GamePlay;
+                    self.load_game(engine);
+                    // start playing wall sound
+                    self.play_wall_sound(true);
+                }
+                // clear wall pieces away once they settle or get far away
+                let psn = self.player_posn();
+                self.debris.cull(&mut self.world, psn, DR);
+            }
+        }
+
+        // the wall sound follows the wall
+        if self.mode != Mode::Menu {
+            if let Some(&b) = self.wall.boxes.first() {
+                let wall_z = self.world.transforms[b].pos.z;
+                self.world.transforms[self.wall.sound].pos = Pos3::new(0.0, 0.0, wall_z);
+            }
+        }
+        self.world.sync_scene();
+        self.show(rules);
+        self.audio.sync(&self.world);
+
+        self.camera.update_camera(engine.camera_mut());
+    }
+    fn load_game(&mut self, engine: &mut Engine) {
+        let file = File::open("savefile.txt").unwrap();
+        let buf_reader = BufReader::new(file);
+        let save_state: GameState = serde_json::from_reader(buf_reader).unwrap();
+
+        // pick the random sequence back up where the save left it
+        if let Some(rng) = save_state.rng {
+            engine.restore_rng(rng);
+        }
+
+        // rebuild the wall as it was
+        self.debris.clear(&mut self.world);
+        let holes = match (save_state.missing_x, save_state.missing_y) {
+            (Some(x), Some(y)) if save_state.holes.is_empty() => vec![(x, y)],
+            _ => save_state.holes,
+        };
+        let plan = WallPlan {
+            wall_type: save_state.wall_type,
+            holes,
+            openings: save_state.openings,
+            speed: save_state
+                .wall_speed
+                .unwrap_or(WIS * (save_state.score + 1) as f32 * WVSF),
+            distance: save_state.wall_z,
+        };
+        self.wall.reset(&mut self.world, &plan);
+
+        // load player posn and score
+        self.set_player_posn(save_state.player_posn);
+        self.score = save_state.score;
+    }
+}
@@ -0,0 +1,126 @@
+use engine3d::camera::OrbitCamera;
+use engine3d::events::{KeyCode, Recording, Script};
+use engine3d::headless::{run_headless, Headless};
+use engine3d::DT;
+use hole_in_the_wall::{Game, GameData, Mode};
+use std::path::{Path, PathBuf};
+
+type HoleInTheWall = Headless<Game<OrbitCamera>>;
+
+fn content() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/content"))
+}
+
+/// A file in the temp dir that no other test run is using.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("hole-in-the-wall-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn idle_player_stays_in_menu() {
+    let h: HoleInTheWall = run_headless::<GameData, _>(content(), &Script::new(), 120);
+    assert_eq!(h.engine.frame, 120);
+    assert_eq!(h.game.mode, Mode::Menu);
+    assert_eq!(h.instance_groups().instances(h.rules.player_model).len(), 1);
+}
+
+#[test]
+fn touching_start_box_begins_game_and_wall_hits_player() {
+    // A pushes the player towards the start box
+    let script = Script::new().hold(0, 90, KeyCode::A);
+    let mut h: HoleInTheWall = run_headless::<GameData, _>(content(), &script, 90);
+    assert_eq!(h.game.mode, Mode::GamePlay);
+    let wall_z = h.game.wall_boxes()[0].c.z;
+
+    // The wall keeps coming while the player stands still
+    h.run(&Script::new(), 60);
+    assert!(h.game.wall_boxes()[0].c.z < wall_z);
+    let wall_model = h.rules.glass_wall_model;
+    assert_eq!(
+        h.instance_groups().instances(wall_model).len(),
+        h.game.wall.boxes.len()
+    );
+
+    // The player straddles two wall columns, so one hole can never let it through
+    h.run(&Script::new(), 60 * 15);
+    assert_eq!(h.game.mode, Mode::EndScreen);
+    assert_eq!(h.game.score, 0);
+}
+
+#[test]
+fn fast_wall_cannot_skip_past_player() {
+    let script = Script::new().hold(0, 90, KeyCode::A);
+    let mut h: HoleInTheWall = run_headless::<GameData, _>(content(), &script, 90);
+    assert_eq!(h.game.mode, Mode::GamePlay);
+    // Put the wall just ahead of the player, moving so fast that one step
+    // takes it from there to just behind
+    let player_z = h.game.player_box().c.z;
+    let world = &mut h.game.world;
+    for &e in h.game.wall.boxes.iter() {
+        world.transforms[e].pos.z = player_z + 6.0;
+        world.velocities[e].linear.z = -12.0 / DT;
+    }
+    h.run(&Script::new(), 1);
+    assert_eq!(h.game.mode, Mode::EndScreen);
+    assert_eq!(h.game.score, 0);
+}
+
+#[test]
+fn recorded_run_replays_frame_for_frame() {
+    let script = Script::new()
+        .hold(0, 40, KeyCode::A)
+        .hold(50, 80, KeyCode::W)
+        .hold(60, 70, KeyCode::Space)
+        .hold(90, 120, KeyCode::Q);
+    let mut live: HoleInTheWall = Headless::new(content());
+    live.engine.events.record();
+    live.run(&script, 150);
+    let recording = Recording {
+        seed: live.engine.seed(),
+        script: live.engine.events.take_recording().unwrap(),
+    };
+
+    let path = temp_path("replay.json");
+    recording.save(&path).unwrap();
+    let loaded = Recording::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, recording);
+
+    let mut replayed: HoleInTheWall = Headless::replay(content(), loaded);
+    replayed.run(&Script::new(), 150);
+    assert_eq!(replayed.game.mode, live.game.mode);
+    assert_eq!(replayed.game.player_box(), live.game.player_box());
+    assert_eq!(replayed.game.wall_boxes(), live.game.wall_boxes());
+}
+
+#[test]
+fn unreadable_recordings_are_errors() {
+    assert!(Recording::load(temp_path("missing.json")).is_err());
+
+    let garbled = temp_path("garbled.json");
+    std::fs::write(&garbled, "{\"seed\": ").unwrap();
+    let loaded = Recording::load(&garbled);
+    std::fs::remove_file(&garbled).unwrap();
+    assert!(loaded.is_err());
+}
+
+#[test]
+fn broken_wall_falls_to_pieces_that_get_cleared_away() {
+    let script = Script::new().hold(0, 90, KeyCode::A);
+    let mut h: HoleInTheWall = run_headless::<GameData, _>(content(), &script, 90);
+    let wall_model = h.rules.glass_wall_model;
+    let boxes = h.game.wall.boxes.len();
+    for _ in 0..60 * 15 {
+        if h.game.mode == Mode::EndScreen {
+            break;
+        }
+        h.run(&Script::new(), 1);
+    }
+    assert_eq!(h.game.mode, Mode::EndScreen);
+    assert!(h.game.wall.boxes.is_empty());
+    assert!(h.instance_groups().instances(wall_model).len() > boxes);
+
+    // The pieces slide to a stop on the floor and are tidied up
+    h.run(&Script::new(), 60 * 12);
+    assert_eq!(h.instance_groups().instances(wall_model).len(), 0);
+}
@@ -0,0 +1,148 @@
+use engine3d::camera::OrbitCamera;
+use engine3d::events::Script;
+use engine3d::geom::*;
+use engine3d::headless::Headless;
+use hole_in_the_wall::level::{Level, Rect, WallPlan};
+use hole_in_the_wall::{Game, WallType};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::path::Path;
+
+fn content() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/content"))
+}
+
+#[test]
+fn wall_plans_read_from_json() {
+    let level: Level = serde_json::from_str(
+        r#"{ "walls": [
+            { "wall_type": "Diamond", "holes": [[0, 0], [0, 1], [1, 0]], "speed": 4.0 },
+            { "wall_type": "Glass", "holes": [], "speed": 1.0, "distance": 30.0 }
+        ] }"#,
+    )
+    .unwrap();
+    assert_eq!(level.walls.len(), 2);
+    let l_shape = &level.walls[0];
+    assert_eq!(l_shape.wall_type, WallType::Diamond);
+    assert!(l_shape.is_hole(0, 1) && !l_shape.is_hole(1, 1));
+    // Walls go up where they always have unless the level says otherwise
+    assert_eq!(l_shape.distance, 20.0);
+    assert_eq!(level.walls[1].distance, 30.0);
+}
+
+#[test]
+fn random_walls_take_over_when_the_level_runs_out() {
+    let level = Level::load_dir(&content().join("levels"));
+    assert!(!level.walls.is_empty());
+    let mut rng = StdRng::seed_from_u64(0);
+    let n = level.walls.len();
+    assert_eq!(level.wall(n - 1, &mut rng), level.walls[n - 1]);
+    let next = level.wall(n, &mut rng);
+    let later = level.wall(n + 3, &mut rng);
+    assert_eq!(next.holes.len(), 1);
+    assert!(later.speed > next.speed);
+
+    // No levels at all is fine too
+    let none = Level::load_dir(&content().join("no-such-levels"));
+    assert!(none.walls.is_empty());
+    let first: WallPlan = none.wall(0, &mut rng);
+    assert_eq!(first.holes.len(), 1);
+}
+
+#[test]
+fn bad_level_files_leave_only_random_walls() {
+    let dir = std::env::temp_dir().join(format!(
+        "hole-in-the-wall-bad-levels-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let wall = |holes: &str| {
+        format!(
+            r#"{{ "walls": [{{ "wall_type": "Glass", "holes": {}, "speed": 1.0 }}] }}"#,
+            holes
+        )
+    };
+    std::fs::write(dir.join("01-fine.json"), wall("[[2, 1]]")).unwrap();
+    assert_eq!(Level::load_dir(&dir).walls.len(), 1);
+
+    let outside = dir.join("02-outside.json");
+    std::fs::write(&outside, wall("[[9, 0]]")).unwrap();
+    let e = Level::load(&outside).unwrap_err();
+    assert!(e.to_string().contains("outside the wall"), "{}", e);
+    assert!(Level::load_dir(&dir).walls.is_empty());
+
+    std::fs::write(&outside, "{ \"walls\": [").unwrap();
+    assert!(Level::load(&outside).is_err());
+    assert!(Level::load_dir(&dir).walls.is_empty());
+    assert!(Level::load(&dir.join("03-missing.json")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn game_puts_up_the_first_wall_of_the_level() {
+    let mut h: Headless<Game<OrbitCamera>> = Headless::new(content());
+    let plan = h.game.level.walls[0].clone();
+    assert_eq!(h.game.wall.plan, plan);
+    assert_eq!(h.game.wall.boxes.len(), 6 * 3 - plan.holes.len());
+    h.run(&Script::new(), 10);
+    // Nothing moves until the game starts
+    for b in h.game.wall_boxes() {
+        assert_eq!(b.c.z, plan.distance);
+    }
+}
+
+fn player_turned(degrees: f32) -> Box {
+    Box {
+        c: Pos3::new(0.0, 0.5, 0.0),
+        axes: Mat3::from_angle_y(cgmath::Deg(degrees)),
+        half_sizes: Vec3::new(0.8, 0.5, 0.3),
+    }
+}
+
+#[test]
+fn players_turn_to_fit_through_slots() {
+    let slot = WallPlan {
+        wall_type: WallType::Glass,
+        holes: vec![],
+        openings: vec![Rect {
+            from: (2.8, 0.0),
+            to: (3.2, 0.75),
+        }],
+        speed: 2.0,
+        distance: 20.0,
+    };
+    // The slot cuts the two middle cells up around it
+    assert_eq!(slot.solid().len(), 6 * 3 - 2 + 2 * 3);
+    assert!(!slot.fits(&player_turned(0.0)));
+    assert!(slot.fits(&player_turned(90.0)));
+    assert!(slot.fits(&player_turned(-90.0)));
+    // Corners stick out when it's only partway round
+    assert!(!slot.fits(&player_turned(45.0)));
+    // Up off the floor the player's head is in the wall
+    let mut jumped = player_turned(90.0);
+    jumped.c.y = 1.5;
+    assert!(!slot.fits(&jumped));
+}
+
+#[test]
+fn cell_holes_fit_the_way_they_always_have() {
+    let hole = WallPlan {
+        wall_type: WallType::Diamond,
+        holes: vec![(2, 0)],
+        openings: vec![],
+        speed: 2.0,
+        distance: 20.0,
+    };
+    assert_eq!(hole.solid().len(), 6 * 3 - 1);
+    // The cell is two units wide, from x = -2 to 0
+    let mut player = player_turned(0.0);
+    assert!(!hole.fits(&player));
+    player.c.x = -1.0;
+    assert!(hole.fits(&player));
+    // Right up against the edge of the hole is still fine
+    player.c.x = -0.8;
+    assert!(hole.fits(&player));
+    player.c.x = -0.7;
+    assert!(!hole.fits(&player));
+}